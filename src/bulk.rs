@@ -0,0 +1,249 @@
+use crate::device::{DeviceState, DeviceStatus};
+use crate::utils::format_duration;
+use serde::Serialize;
+
+/// Outcome of a bulk operation on a single paired device
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkResult {
+    pub alias: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<DeviceState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_code: Option<u8>, // DeviceState::as_code of `state`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_consumption: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool, // gave up after --timeout-per-device
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool, // not contacted, it was unreachable in a recent run
+}
+
+impl BulkResult {
+    pub fn success(alias: &str) -> Self {
+        Self {
+            alias: alias.to_string(),
+            ok: true,
+            state: None,
+            state_code: None,
+            power_consumption: None,
+            error: None,
+            timed_out: false,
+            skipped: false,
+        }
+    }
+
+    pub fn with_status(alias: &str, status: &DeviceStatus) -> Self {
+        Self {
+            state: Some(status.state),
+            state_code: Some(status.state.as_code()),
+            power_consumption: Some(status.power_consumption),
+            ..Self::success(alias)
+        }
+    }
+
+    pub fn failure(alias: &str, error: String) -> Self {
+        Self {
+            alias: alias.to_string(),
+            ok: false,
+            state: None,
+            state_code: None,
+            power_consumption: None,
+            error: Some(error),
+            timed_out: false,
+            skipped: false,
+        }
+    }
+
+    pub fn timed_out(alias: &str, secs: u64) -> Self {
+        Self {
+            timed_out: true,
+            ..Self::failure(alias, format!("timed out after {}s", secs))
+        }
+    }
+
+    /// A device skipped because it was unreachable `age_secs` ago
+    pub fn skipped_unreachable(alias: &str, age_secs: u64) -> Self {
+        Self {
+            skipped: true,
+            ..Self::failure(
+                alias,
+                format!("skipped, unreachable {} ago", format_duration(age_secs)),
+            )
+        }
+    }
+
+    /// One row of the streaming text table, padded to `alias_width`
+    pub fn table_row(&self, alias_width: usize) -> String {
+        let result = match (&self.error, self.state) {
+            (Some(error), _) if self.timed_out => format!("⏱️  {}", error),
+            (Some(error), _) if self.skipped => format!("⏭️  {}", error),
+            (Some(error), _) => format!("❌ {}", error),
+            (None, Some(state)) => format!(
+                "✅ {} {}W",
+                state,
+                self.power_consumption.unwrap_or_default()
+            ),
+            (None, None) => "✅ ok".to_string(),
+        };
+        format!("  {:<width$}  {}", self.alias, result, width = alias_width)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BulkSummary {
+    pub ok: usize,
+    pub failed: usize,
+    pub timed_out: usize, // also counted in `failed`
+    pub skipped: usize,   // also counted in `failed`
+}
+
+/// Combined draw of the devices a bulk status found switched on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PowerTotal {
+    pub watts: u32,
+    pub devices: usize,
+}
+
+/// Aggregate report for `--format json` on bulk commands
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkReport {
+    pub results: Vec<BulkResult>,
+    pub summary: BulkSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_total: Option<PowerTotal>, // only with --show-power-total
+}
+
+impl BulkReport {
+    pub fn new(results: Vec<BulkResult>) -> Self {
+        let ok = results.iter().filter(|result| result.ok).count();
+        let summary = BulkSummary {
+            ok,
+            failed: results.len() - ok,
+            timed_out: results.iter().filter(|result| result.timed_out).count(),
+            skipped: results.iter().filter(|result| result.skipped).count(),
+        };
+        Self {
+            results,
+            summary,
+            power_total: None,
+        }
+    }
+
+    /// Add the total power of the successfully queried devices that are on
+    pub fn with_power_total(mut self) -> Self {
+        let on_devices = self
+            .results
+            .iter()
+            .filter(|result| result.ok && result.state == Some(DeviceState::On));
+        let mut total = PowerTotal {
+            watts: 0,
+            devices: 0,
+        };
+        for result in on_devices {
+            total.watts += u32::from(result.power_consumption.unwrap_or_default());
+            total.devices += 1;
+        }
+        self.power_total = Some(total);
+        self
+    }
+
+    /// Last line of `--format ndjson` output, after the per-device lines
+    pub fn summary_line(&self) -> serde_json::Value {
+        let mut line = serde_json::json!({ "summary": self.summary });
+        if let Some(total) = self.power_total {
+            line["power_total"] = serde_json::json!(total);
+        }
+        line
+    }
+
+    pub fn any_failed(&self) -> bool {
+        self.summary.failed > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_report_json() {
+        let status = DeviceStatus {
+            state: DeviceState::On,
+            power_consumption: 12,
+        };
+        let report = BulkReport::new(vec![
+            BulkResult::success("x"),
+            BulkResult::with_status("z", &status),
+            BulkResult::failure("y", "timeout".to_string()),
+            BulkResult::timed_out("w", 5),
+            BulkResult::skipped_unreachable("v", 120),
+        ]);
+        assert!(report.any_failed());
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "results": [
+                    {"alias": "x", "ok": true},
+                    {"alias": "z", "ok": true, "state": "on", "state_code": 1, "power_consumption": 12},
+                    {"alias": "y", "ok": false, "error": "timeout"},
+                    {"alias": "w", "ok": false, "error": "timed out after 5s", "timed_out": true},
+                    {"alias": "v", "ok": false, "error": "skipped, unreachable 2m ago", "skipped": true}
+                ],
+                "summary": {"ok": 2, "failed": 3, "timed_out": 1, "skipped": 1}
+            })
+        );
+        assert_eq!(report.results[2].table_row(4), "  y     ❌ timeout");
+        assert_eq!(
+            report.results[3].table_row(4),
+            "  w     ⏱️  timed out after 5s"
+        );
+        assert_eq!(
+            report.results[4].table_row(4),
+            "  v     ⏭️  skipped, unreachable 2m ago"
+        );
+    }
+
+    #[test]
+    fn test_bulk_power_total() {
+        let on = |watts| DeviceStatus {
+            state: DeviceState::On,
+            power_consumption: watts,
+        };
+        let off = DeviceStatus {
+            state: DeviceState::Off,
+            power_consumption: 3,
+        };
+        let report = BulkReport::new(vec![
+            BulkResult::with_status("a", &on(400)),
+            BulkResult::with_status("b", &on(30)),
+            BulkResult::with_status("c", &off),
+            BulkResult::failure("d", "timeout".to_string()),
+        ])
+        .with_power_total();
+
+        assert_eq!(
+            report.power_total,
+            Some(PowerTotal {
+                watts: 430,
+                devices: 2
+            })
+        );
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json["power_total"],
+            serde_json::json!({"watts": 430, "devices": 2})
+        );
+        assert_eq!(
+            report.summary_line(),
+            serde_json::json!({
+                "summary": {"ok": 3, "failed": 1, "timed_out": 0, "skipped": 0},
+                "power_total": {"watts": 430, "devices": 2}
+            })
+        );
+    }
+}
@@ -1,23 +1,74 @@
 use crate::config::ConfigManager;
 use crate::device::SwitcherDevice;
+use crate::error::SwitcherError;
 use crate::utils::current_timestamp;
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// How many previous IP addresses to remember per device
+const MAX_IP_HISTORY: usize = 5;
+
+/// How many power readings to keep per device, oldest dropped first
+const MAX_POWER_SAMPLES: usize = 10_000;
+
+/// A max age of 0 means cached devices never expire, for static installs
+/// where devices are always present but rarely rebroadcast
+pub const NO_EXPIRY: u64 = 0;
+
+/// Oldest `last_seen` a device may have to count as fresh
+fn expiry_cutoff(max_age_seconds: u64) -> u64 {
+    if max_age_seconds == NO_EXPIRY {
+        return 0;
+    }
+    current_timestamp().saturating_sub(max_age_seconds)
+}
+
+/// A power reading taken by `status` or `status-all`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PowerSample {
+    pub timestamp: u64,
+    pub watts: u16,
+}
+
 /// Cached device information
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct CachedDevice {
     pub device: SwitcherDevice,
     pub last_seen: u64,
     pub discovery_count: u32,
+    #[serde(default)]
+    pub ip_history: Vec<String>, // previous IPs, most recent first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_packet: Option<String>, // hex discovery packet, only kept with --include-raw
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub power_history: Vec<PowerSample>, // oldest first
 }
 
 /// Device cache configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct DeviceCache {
     pub devices: HashMap<String, CachedDevice>, // device_id -> CachedDevice
     pub last_updated: u64,
+    /// IP -> when a bulk command last failed to reach it, so later runs can
+    /// skip it instead of waiting out the connect timeout again
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub unreachable: HashMap<String, u64>,
+}
+
+impl AsRef<SwitcherDevice> for CachedDevice {
+    fn as_ref(&self) -> &SwitcherDevice {
+        &self.device
+    }
+}
+
+impl From<&CachedDevice> for SwitcherDevice {
+    fn from(cached: &CachedDevice) -> Self {
+        cached.device.clone()
+    }
 }
 
 impl DeviceCache {
@@ -25,6 +76,7 @@ impl DeviceCache {
         Self {
             devices: HashMap::new(),
             last_updated: current_timestamp(),
+            unreachable: HashMap::new(),
         }
     }
 
@@ -34,35 +86,122 @@ impl DeviceCache {
 
         if let Some(cached) = self.devices.get_mut(device_id) {
             debug!("Updating cached device: {} ({})", device.name, device_id);
+            if cached.device.ip_address != device.ip_address {
+                info!(
+                    "Device {} changed IP from {} to {}",
+                    device_id, cached.device.ip_address, device.ip_address
+                );
+                let previous_ip = std::mem::take(&mut cached.device.ip_address);
+                cached
+                    .ip_history
+                    .retain(|ip| *ip != previous_ip && *ip != device.ip_address);
+                cached.ip_history.insert(0, previous_ip);
+                cached.ip_history.truncate(MAX_IP_HISTORY);
+            }
             cached.device = device;
             cached.last_seen = now;
             cached.discovery_count += 1;
         } else {
-            debug!(
-                "Adding new device to cache: {} ({})",
-                device.name, device_id
-            );
+            debug!("Adding new device to cache: {}", device);
             self.devices.insert(
                 device.device_id.clone(),
                 CachedDevice {
                     device,
                     last_seen: now,
                     discovery_count: 1,
+                    ip_history: Vec::new(),
+                    raw_packet: None,
+                    power_history: Vec::new(),
                 },
             );
         }
         self.last_updated = now;
     }
 
-    pub fn get_fresh_devices(&self, max_age_seconds: u64) -> Vec<SwitcherDevice> {
+    pub fn get_device(&self, device_id: &str) -> Option<&SwitcherDevice> {
+        self.devices.get(device_id).map(|cached| &cached.device)
+    }
+
+    /// Find the cached device last seen at `ip`
+    pub fn get_by_ip(&self, ip: &str) -> Option<&SwitcherDevice> {
+        self.devices
+            .values()
+            .map(|cached| &cached.device)
+            .find(|device| device.ip_address == ip)
+    }
+
+    /// Store the raw discovery packet for a device if none is stored yet
+    pub fn set_raw_packet(&mut self, device_id: &str, raw_packet: String) {
+        if let Some(cached) = self.devices.get_mut(device_id) {
+            cached.raw_packet.get_or_insert(raw_packet);
+        }
+    }
+
+    pub fn mark_unreachable(&mut self, ip: &str, timestamp: u64) {
+        self.unreachable.insert(ip.to_string(), timestamp);
+    }
+
+    /// Forget that `ip` was unreachable. Returns false if it wasn't marked.
+    pub fn mark_reachable(&mut self, ip: &str) -> bool {
+        self.unreachable.remove(ip).is_some()
+    }
+
+    /// Drop unreachable marks older than `ttl_secs`, so IPs that went away
+    /// don't stay in the config. Returns false if none were dropped.
+    pub fn prune_unreachable(&mut self, ttl_secs: u64) -> bool {
         let now = current_timestamp();
-        let cutoff = now.saturating_sub(max_age_seconds);
+        let before = self.unreachable.len();
+        self.unreachable
+            .retain(|_, since| now.saturating_sub(*since) < ttl_secs);
+        self.unreachable.len() != before
+    }
+
+    /// When `ip` was last found unreachable, if that was within `ttl_secs`
+    pub fn unreachable_since(&self, ip: &str, ttl_secs: u64) -> Option<u64> {
+        let &since = self.unreachable.get(ip)?;
+        (current_timestamp().saturating_sub(since) < ttl_secs).then_some(since)
+    }
+
+    /// Append a power reading to a cached device's history. Returns false
+    /// if the device isn't cached.
+    pub fn record_power(&mut self, device_id: &str, watts: u16, timestamp: u64) -> bool {
+        let Some(cached) = self.devices.get_mut(device_id) else {
+            return false;
+        };
+        cached.power_history.push(PowerSample { timestamp, watts });
+        let excess = cached.power_history.len().saturating_sub(MAX_POWER_SAMPLES);
+        cached.power_history.drain(..excess);
+        true
+    }
+
+    /// Pull `last_seen` and unreachable marks that lie in the future back to
+    /// `now`. After the system clock jumps backwards (NTP correction, VM
+    /// resume) they would otherwise stay fresh until the clock catches up.
+    /// Returns how many timestamps were changed.
+    pub fn clamp_future_timestamps(&mut self, now: u64) -> usize {
+        let timestamps = self
+            .devices
+            .values_mut()
+            .map(|cached| &mut cached.last_seen)
+            .chain(self.unreachable.values_mut());
+        let mut clamped = 0;
+        for timestamp in timestamps.filter(|timestamp| **timestamp > now) {
+            *timestamp = now;
+            clamped += 1;
+        }
+        clamped
+    }
+
+    /// Devices seen within `max_age_seconds`, or every cached device when it
+    /// is `NO_EXPIRY`
+    pub fn get_fresh_devices(&self, max_age_seconds: u64) -> Vec<SwitcherDevice> {
+        let cutoff = expiry_cutoff(max_age_seconds);
 
         let fresh_devices: Vec<SwitcherDevice> = self
             .devices
             .values()
             .filter(|cached| cached.last_seen >= cutoff)
-            .map(|cached| cached.device.clone())
+            .map(SwitcherDevice::from)
             .collect();
 
         debug!(
@@ -73,9 +212,11 @@ impl DeviceCache {
         fresh_devices
     }
 
-    pub fn remove_old_devices(&mut self, max_age_seconds: u64) {
+    /// Remove devices not seen within `max_age_seconds`, returning how many
+    /// were removed. `NO_EXPIRY` removes nothing.
+    pub fn remove_old_devices(&mut self, max_age_seconds: u64) -> usize {
         let now = current_timestamp();
-        let cutoff = now.saturating_sub(max_age_seconds);
+        let cutoff = expiry_cutoff(max_age_seconds);
         let initial_count = self.devices.len();
 
         self.devices.retain(|_, cached| cached.last_seen >= cutoff);
@@ -89,6 +230,13 @@ impl DeviceCache {
         }
 
         self.last_updated = now;
+        removed_count
+    }
+}
+
+impl Default for DeviceCache {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -97,31 +245,37 @@ pub struct CacheManager {
 }
 
 impl CacheManager {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new() -> Result<Self, SwitcherError> {
         let config_manager = ConfigManager::new()?;
         Ok(Self { config_manager })
     }
 
-    pub fn load_cache(&self) -> Result<DeviceCache, Box<dyn std::error::Error>> {
+    pub fn load_cache(&self) -> Result<DeviceCache, SwitcherError> {
         debug!("Loading device cache");
         self.config_manager.load_cache_data()
     }
 
-    pub fn save_cache(&self, cache: &DeviceCache) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn save_cache(&self, cache: &DeviceCache) -> Result<(), SwitcherError> {
         debug!("Saving device cache with {} devices", cache.devices.len());
         self.config_manager.save_cache_data(cache)
     }
 
-    pub fn clear_cache(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Delete the cache file, returning how many cached devices it held
+    pub fn clear_cache(&self) -> Result<usize, SwitcherError> {
         info!("Clearing device cache");
-        self.config_manager.clear_config()
+        let device_count = self
+            .load_cache()
+            .map(|cache| cache.devices.len())
+            .unwrap_or(0);
+        self.config_manager.clear_config()?;
+        Ok(device_count)
     }
 
     pub fn cache_exists(&self) -> bool {
         self.config_manager.config_exists()
     }
 
-    pub fn get_cache_path(&self) -> &std::path::Path {
+    pub fn get_cache_path(&self) -> std::path::PathBuf {
         self.config_manager.get_config_path()
     }
 }
@@ -130,6 +284,7 @@ impl CacheManager {
 mod tests {
     use super::*;
     use crate::device::{DeviceState, SwitcherDevice};
+    use crate::utils::mock_clock;
 
     fn create_test_device(id: &str, name: &str, ip: &str) -> SwitcherDevice {
         SwitcherDevice {
@@ -137,8 +292,9 @@ mod tests {
             name: name.to_string(),
             ip_address: ip.to_string(),
             mac_address: "00:11:22:33:44:55".to_string(),
-            device_key: "a1".to_string(),
+            device_key: Some("a1".to_string()),
             device_type: "Switcher Power Plug".to_string(),
+            device_type_code: "01a8".to_string(),
             state: DeviceState::Off,
             power_consumption: 0,
         }
@@ -155,8 +311,118 @@ mod tests {
         assert!(cache.devices.contains_key("123"));
     }
 
+    #[test]
+    fn test_cache_lookups() {
+        let mut cache = DeviceCache::new();
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.100"));
+        cache.add_device(create_test_device("456", "Other Device", "192.168.1.101"));
+
+        assert_eq!(cache.get_device("123").unwrap().name, "Test Device");
+        assert!(cache.get_device("789").is_none());
+        assert_eq!(cache.get_by_ip("192.168.1.101").unwrap().device_id, "456");
+        assert!(cache.get_by_ip("192.168.1.102").is_none());
+
+        // A device that moved is only found at its new address
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.150"));
+        assert!(cache.get_by_ip("192.168.1.100").is_none());
+        assert_eq!(cache.get_by_ip("192.168.1.150").unwrap().device_id, "123");
+    }
+
+    #[test]
+    fn test_cache_tracks_ip_history() {
+        let mut cache = DeviceCache::new();
+
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.100"));
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.150"));
+
+        let cached = &cache.devices["123"];
+        assert_eq!(cached.device.ip_address, "192.168.1.150");
+        assert_eq!(cached.ip_history, vec!["192.168.1.100"]);
+
+        // Moving back drops the current IP from the history and dedupes
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.100"));
+        let cached = &cache.devices["123"];
+        assert_eq!(cached.ip_history, vec!["192.168.1.150"]);
+
+        // Same IP again leaves the history untouched
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.100"));
+        assert_eq!(cache.devices["123"].ip_history, vec!["192.168.1.150"]);
+    }
+
+    #[test]
+    fn test_cache_ip_history_is_capped() {
+        let mut cache = DeviceCache::new();
+
+        for i in 0..10 {
+            let ip = format!("192.168.1.{}", 100 + i);
+            cache.add_device(create_test_device("123", "Test Device", &ip));
+        }
+
+        let cached = &cache.devices["123"];
+        assert_eq!(cached.ip_history.len(), MAX_IP_HISTORY);
+        assert_eq!(cached.ip_history[0], "192.168.1.108");
+    }
+
+    #[test]
+    fn test_cache_keeps_first_raw_packet() {
+        let mut cache = DeviceCache::new();
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.100"));
+
+        cache.set_raw_packet("123", "fef0aa".to_string());
+        cache.set_raw_packet("123", "fef0bb".to_string());
+        cache.set_raw_packet("456", "fef0cc".to_string());
+
+        assert_eq!(cache.devices["123"].raw_packet.as_deref(), Some("fef0aa"));
+        assert_eq!(cache.devices.len(), 1);
+    }
+
+    #[test]
+    fn test_cache_unreachable_expires() {
+        mock_clock::set(1_000_000);
+        let mut cache = DeviceCache::new();
+        cache.mark_unreachable("192.168.1.100", 1_000_000);
+
+        mock_clock::advance(60);
+        assert_eq!(
+            cache.unreachable_since("192.168.1.100", 300),
+            Some(1_000_000)
+        );
+        assert_eq!(cache.unreachable_since("192.168.1.101", 300), None);
+
+        mock_clock::advance(300);
+        assert_eq!(cache.unreachable_since("192.168.1.100", 300), None);
+
+        assert!(cache.mark_reachable("192.168.1.100"));
+        assert!(!cache.mark_reachable("192.168.1.100"));
+
+        cache.mark_unreachable("192.168.1.100", 1_000_000);
+        cache.mark_unreachable("192.168.1.101", 1_000_300);
+        assert!(cache.prune_unreachable(300));
+        assert!(!cache.prune_unreachable(300));
+        assert_eq!(
+            cache.unreachable.keys().collect::<Vec<_>>(),
+            ["192.168.1.101"]
+        );
+    }
+
+    #[test]
+    fn test_cache_records_power_history() {
+        let mut cache = DeviceCache::new();
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.100"));
+
+        for i in 0..MAX_POWER_SAMPLES as u64 + 2 {
+            assert!(cache.record_power("123", 10, i));
+        }
+        assert!(!cache.record_power("456", 10, 0));
+
+        let history = &cache.devices["123"].power_history;
+        assert_eq!(history.len(), MAX_POWER_SAMPLES);
+        assert_eq!(history[0].timestamp, 2);
+    }
+
     #[test]
     fn test_cache_fresh_devices() {
+        mock_clock::set(1_000_000);
         let mut cache = DeviceCache::new();
         let device = create_test_device("123", "Test Device", "192.168.1.100");
 
@@ -166,10 +432,8 @@ mod tests {
         let fresh = cache.get_fresh_devices(3600);
         assert_eq!(fresh.len(), 1);
 
-        // Manually set an old timestamp to test freshness
-        if let Some(cached_device) = cache.devices.get_mut("123") {
-            cached_device.last_seen = current_timestamp() - 10; // 10 seconds ago
-        }
+        // Device was last seen 10 seconds ago
+        mock_clock::advance(10);
 
         // Should not be fresh if we set max age to 5 seconds
         let not_fresh = cache.get_fresh_devices(5);
@@ -180,21 +444,66 @@ mod tests {
         assert_eq!(still_fresh.len(), 1);
     }
 
+    #[test]
+    fn test_cache_clamps_future_last_seen() {
+        mock_clock::set(1_000_000);
+        let mut cache = DeviceCache::new();
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.100"));
+        cache.add_device(create_test_device("456", "Other Device", "192.168.1.101"));
+
+        // Saved an hour "ahead" before the clock was corrected backwards
+        cache.devices.get_mut("123").unwrap().last_seen = 1_003_600;
+        cache.mark_unreachable("192.168.1.9", 1_003_600);
+
+        assert_eq!(cache.clamp_future_timestamps(1_000_000), 2);
+        assert_eq!(cache.devices["123"].last_seen, 1_000_000);
+        assert_eq!(cache.devices["456"].last_seen, 1_000_000);
+
+        // It ages from the clamp instead of staying fresh for the skew
+        mock_clock::advance(1800);
+        assert!(cache.get_fresh_devices(600).is_empty());
+        assert_eq!(cache.unreachable_since("192.168.1.9", 600), None);
+    }
+
+    #[test]
+    fn test_cache_no_expiry() {
+        mock_clock::set(1_000_000);
+        let mut cache = DeviceCache::new();
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.100"));
+
+        // A week later a normal cutoff drops the device, NO_EXPIRY keeps it
+        mock_clock::advance(7 * 24 * 3600);
+        assert!(cache.get_fresh_devices(3600).is_empty());
+        assert_eq!(cache.get_fresh_devices(NO_EXPIRY).len(), 1);
+        assert_eq!(cache.remove_old_devices(NO_EXPIRY), 0);
+        assert_eq!(cache.devices.len(), 1);
+    }
+
     #[test]
     fn test_cache_remove_old_devices() {
+        mock_clock::set(1_000_000);
         let mut cache = DeviceCache::new();
         let device = create_test_device("123", "Test Device", "192.168.1.100");
 
         cache.add_device(device);
         assert_eq!(cache.devices.len(), 1);
 
-        // Manually set an old timestamp
-        if let Some(cached_device) = cache.devices.get_mut("123") {
-            cached_device.last_seen = current_timestamp() - 100; // 100 seconds ago
-        }
+        // Device was last seen 100 seconds ago
+        mock_clock::advance(100);
 
         // Remove devices older than 50 seconds (should remove the device)
-        cache.remove_old_devices(50);
+        assert_eq!(cache.remove_old_devices(50), 1);
         assert_eq!(cache.devices.len(), 0);
     }
+
+    #[test]
+    fn test_cached_device_conversions() {
+        let mut cache = DeviceCache::new();
+        cache.add_device(create_test_device("123", "Test Device", "192.168.1.100"));
+        let cached = &cache.devices["123"];
+
+        assert_eq!(cached.as_ref().device_id, "123");
+        let device = SwitcherDevice::from(cached);
+        assert_eq!(device.ip_address, "192.168.1.100");
+    }
 }
@@ -1,15 +1,34 @@
 use crate::cache::DeviceCache;
+use crate::error::SwitcherError;
 use crate::pairing::PairingConfig;
-use log::{debug, warn};
+use crate::utils::current_timestamp;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::{Read, Write};
+use std::net::Ipv4Addr;
 use std::path::{Path, PathBuf};
 
+/// Once the JSON grows past this size (power history, raw packets) the
+/// config is written gzip-compressed to `switcher_config.json.gz` instead.
+/// Gzipping the file by hand opts in earlier; gunzipping it opts back out
+/// until it grows past the threshold again.
+const COMPRESS_THRESHOLD_BYTES: usize = 1024 * 1024;
+
+/// The clock skew warning is logged once per run, not on every config load
+static CLOCK_SKEW_WARNING: std::sync::Once = std::sync::Once::new();
+
 /// Unified configuration structure that contains both cache and pairing data
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct UnifiedConfig {
     pub cache: Option<DeviceCache>,
     pub pairing: Option<PairingConfig>,
+    #[serde(default)]
+    pub settings: Settings,
     pub version: String,
 }
 
@@ -18,58 +37,190 @@ impl UnifiedConfig {
         Self {
             cache: None,
             pairing: None,
+            settings: Settings::default(),
             version: env!("CARGO_PKG_VERSION").to_string(),
         }
     }
 }
 
+/// User preferences, edited with `config set <key> <value>`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct Settings {
+    pub discovery: DiscoverySettings,
+}
+
+/// Defaults for `discover`, overridden by explicit command-line flags
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct DiscoverySettings {
+    pub timeout: u64,       // seconds to listen for broadcasts
+    pub cache_timeout: u64, // seconds a cached device stays fresh
+    pub broadcast_address: Option<Ipv4Addr>,
+}
+
+impl Default for DiscoverySettings {
+    fn default() -> Self {
+        Self {
+            timeout: 30,
+            cache_timeout: 3600,
+            broadcast_address: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Keys accepted by `get` and `set`
+    pub const KEYS: &'static [&'static str] = &[
+        "discovery.timeout",
+        "discovery.cache_timeout",
+        "discovery.broadcast_address",
+    ];
+
+    pub fn get(&self, key: &str) -> Result<String, String> {
+        match key {
+            "discovery.timeout" => Ok(self.discovery.timeout.to_string()),
+            "discovery.cache_timeout" => Ok(self.discovery.cache_timeout.to_string()),
+            "discovery.broadcast_address" => Ok(self
+                .discovery
+                .broadcast_address
+                .map(|address| address.to_string())
+                .unwrap_or_default()),
+            _ => Err(Self::unknown_key(key)),
+        }
+    }
+
+    /// Set a value from its string form. An empty value clears optional settings.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let parse_secs = |value: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("'{}' expects a number of seconds, got '{}'", key, value))
+        };
+
+        match key {
+            "discovery.timeout" => self.discovery.timeout = parse_secs(value)?,
+            "discovery.cache_timeout" => self.discovery.cache_timeout = parse_secs(value)?,
+            "discovery.broadcast_address" => {
+                self.discovery.broadcast_address = if value.is_empty() {
+                    None
+                } else {
+                    Some(crate::discovery::parse_broadcast_address(value)?)
+                }
+            }
+            _ => return Err(Self::unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    fn unknown_key(key: &str) -> String {
+        format!(
+            "Unknown setting '{}'. Available: {}",
+            key,
+            Self::KEYS.join(", ")
+        )
+    }
+}
+
+impl Default for UnifiedConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct ConfigManager {
-    config_file_path: PathBuf,
+    config_file_path: PathBuf, // uncompressed path; the .gz sibling wins if present
 }
 
 impl ConfigManager {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new() -> Result<Self, SwitcherError> {
         let config_file_path = Self::get_config_file_path()?;
         Ok(Self { config_file_path })
     }
 
-    fn get_config_file_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    /// Use a config file at `config_file_path` instead of next to the
+    /// executable, so tests can persist to a temp dir
+    #[cfg(test)]
+    pub(crate) fn with_path(config_file_path: PathBuf) -> Self {
+        Self { config_file_path }
+    }
+
+    fn compressed_path(&self) -> PathBuf {
+        let mut path = self.config_file_path.clone().into_os_string();
+        path.push(".gz");
+        PathBuf::from(path)
+    }
+
+    fn get_config_file_path() -> Result<PathBuf, SwitcherError> {
         let exe_path = std::env::current_exe()?;
-        let exe_dir = exe_path
-            .parent()
-            .ok_or("Could not determine executable directory")?;
+        let exe_dir = exe_path.parent().ok_or_else(|| {
+            SwitcherError::Config("Could not determine executable directory".to_string())
+        })?;
         Ok(exe_dir.join("switcher_config.json"))
     }
 
-    pub fn clear_config(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.config_file_path.exists() {
-            fs::remove_file(&self.config_file_path)?;
+    pub fn clear_config(&self) -> Result<(), SwitcherError> {
+        for path in [self.config_file_path.clone(), self.compressed_path()] {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
         }
         Ok(())
     }
 
     pub fn config_exists(&self) -> bool {
-        self.config_file_path.exists()
+        self.get_config_path().exists()
+    }
+
+    /// The file the config is read from: the gzip file if there is one
+    pub fn get_config_path(&self) -> PathBuf {
+        let compressed_path = self.compressed_path();
+        if compressed_path.exists() {
+            compressed_path
+        } else {
+            self.config_file_path.clone()
+        }
     }
 
-    pub fn get_config_path(&self) -> &Path {
-        &self.config_file_path
+    fn read_config_content(&self, path: &Path) -> Result<String, SwitcherError> {
+        if path == self.config_file_path {
+            return Ok(fs::read_to_string(path)?);
+        }
+        let mut content = String::new();
+        GzDecoder::new(fs::File::open(path)?).read_to_string(&mut content)?;
+        Ok(content)
+    }
+
+    /// Keys in the config file that no field reads, as dotted paths like
+    /// `pairing.devices.9c4f22.powr_scale`. Normal loading ignores them for
+    /// forward compatibility, so a typo in a hand edit goes unnoticed
+    /// without this check.
+    pub fn unknown_keys(&self) -> Result<Vec<String>, SwitcherError> {
+        let path = self.get_config_path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let content = self.read_config_content(&path)?;
+        unknown_keys_in(&content)
     }
 
     /// Load the unified config, creating a new one if it doesn't exist
-    pub fn load_unified_config(&self) -> Result<UnifiedConfig, Box<dyn std::error::Error>> {
-        debug!(
-            "Loading unified config from: {}",
-            self.config_file_path.display()
-        );
+    pub fn load_unified_config(&self) -> Result<UnifiedConfig, SwitcherError> {
+        let path = self.get_config_path();
+        debug!("Loading unified config from: {}", path.display());
 
-        if !self.config_file_path.exists() {
+        if !path.exists() {
             debug!("Config file does not exist, creating new config");
             return Ok(UnifiedConfig::new());
         }
 
-        let content = fs::read_to_string(&self.config_file_path)?;
-        let config: UnifiedConfig = serde_json::from_str(&content)?;
+        let content = self.read_config_content(&path)?;
+        let mut config: UnifiedConfig = serde_json::from_str(&content).map_err(|e| {
+            SwitcherError::Config(format!("Could not parse {}: {}", path.display(), e))
+        })?;
         debug!(
             "Successfully loaded config with version: {}",
             config.version
@@ -85,56 +236,279 @@ impl ConfigManager {
             return Ok(UnifiedConfig::new());
         }
 
+        let now = current_timestamp();
+        let clamped = config
+            .cache
+            .as_mut()
+            .map_or(0, |cache| cache.clamp_future_timestamps(now))
+            + config
+                .pairing
+                .as_mut()
+                .map_or(0, |pairing| pairing.clamp_future_timestamps(now));
+        if clamped > 0 {
+            CLOCK_SKEW_WARNING.call_once(|| {
+                warn!(
+                    "{} timestamp(s) in the config are in the future, the system clock may have jumped back; treating them as now",
+                    clamped
+                );
+            });
+        }
+
         Ok(config)
     }
 
     /// Save the unified config
-    pub fn save_unified_config(
-        &self,
-        config: &UnifiedConfig,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        debug!(
-            "Saving unified config to: {}",
-            self.config_file_path.display()
-        );
-        let content = serde_json::to_string_pretty(config)?;
+    pub fn save_unified_config(&self, config: &UnifiedConfig) -> Result<(), SwitcherError> {
+        let content = serde_json::to_string_pretty(config)
+            .map_err(|e| SwitcherError::Config(format!("Could not serialize config: {}", e)))?;
 
         // Create parent directory if it doesn't exist
         if let Some(parent) = self.config_file_path.parent() {
             fs::create_dir_all(parent)?;
         }
 
-        fs::write(&self.config_file_path, content)?;
+        let compressed_path = self.compressed_path();
+        if compressed_path.exists() || content.len() >= COMPRESS_THRESHOLD_BYTES {
+            debug!("Saving unified config to: {}", compressed_path.display());
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content.as_bytes())?;
+            fs::write(&compressed_path, encoder.finish()?)?;
+
+            if self.config_file_path.exists() {
+                info!(
+                    "Config is {} bytes, switched to compressed {}",
+                    content.len(),
+                    compressed_path.display()
+                );
+                fs::remove_file(&self.config_file_path)?;
+            }
+        } else {
+            debug!(
+                "Saving unified config to: {}",
+                self.config_file_path.display()
+            );
+            fs::write(&self.config_file_path, content)?;
+        }
         debug!("Successfully saved unified config");
         Ok(())
     }
 
+    /// Load the config once, let `apply` change any part of it and write it
+    /// back once, instead of a read-modify-write per section. `apply` returns
+    /// whether it changed anything; nothing is written if it didn't.
+    pub fn update<F>(&self, apply: F) -> Result<(), SwitcherError>
+    where
+        F: FnOnce(&mut UnifiedConfig) -> bool,
+    {
+        let mut config = self.load_unified_config()?;
+        if apply(&mut config) {
+            self.save_unified_config(&config)?;
+        } else {
+            debug!("Config unchanged, not saving");
+        }
+        Ok(())
+    }
+
+    /// Load user settings from the unified config
+    pub fn load_settings(&self) -> Result<Settings, SwitcherError> {
+        Ok(self.load_unified_config()?.settings)
+    }
+
+    /// Save user settings to the unified config
+    pub fn save_settings(&self, settings: &Settings) -> Result<(), SwitcherError> {
+        self.update(|config| {
+            config.settings = settings.clone();
+            true
+        })
+    }
+
     /// Load cache data from the unified config
-    pub fn load_cache_data(&self) -> Result<DeviceCache, Box<dyn std::error::Error>> {
+    pub fn load_cache_data(&self) -> Result<DeviceCache, SwitcherError> {
         let config = self.load_unified_config()?;
         Ok(config.cache.unwrap_or_else(DeviceCache::new))
     }
 
     /// Save cache data to the unified config
-    pub fn save_cache_data(&self, cache: &DeviceCache) -> Result<(), Box<dyn std::error::Error>> {
-        let mut config = self.load_unified_config()?;
-        config.cache = Some(cache.clone());
-        self.save_unified_config(&config)
+    pub fn save_cache_data(&self, cache: &DeviceCache) -> Result<(), SwitcherError> {
+        self.update(|config| {
+            config.cache = Some(cache.clone());
+            true
+        })
     }
 
     /// Load pairing data from the unified config
-    pub fn load_pairing_data(&self) -> Result<PairingConfig, Box<dyn std::error::Error>> {
+    pub fn load_pairing_data(&self) -> Result<PairingConfig, SwitcherError> {
         let config = self.load_unified_config()?;
         Ok(config.pairing.unwrap_or_else(PairingConfig::new))
     }
 
     /// Save pairing data to the unified config
-    pub fn save_pairing_data(
-        &self,
-        pairing: &PairingConfig,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut config = self.load_unified_config()?;
-        config.pairing = Some(pairing.clone());
-        self.save_unified_config(&config)
+    pub fn save_pairing_data(&self, pairing: &PairingConfig) -> Result<(), SwitcherError> {
+        self.update(|config| {
+            config.pairing = Some(pairing.clone());
+            true
+        })
+    }
+}
+
+fn unknown_keys_in(content: &str) -> Result<Vec<String>, SwitcherError> {
+    let mut unknown = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_str(content);
+    let _: UnifiedConfig = serde_ignored::deserialize(&mut deserializer, |path| {
+        unknown.push(path.to_string());
+    })
+    .map_err(|e| SwitcherError::Config(format!("Could not parse config: {}", e)))?;
+    Ok(unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::PowerSample;
+    use crate::device::{DeviceState, SwitcherDevice};
+
+    #[test]
+    fn test_config_switches_to_gzip_when_large() {
+        let dir = std::env::temp_dir().join(format!("switcher-config-test-{}", std::process::id()));
+        let manager = ConfigManager::with_path(dir.join("switcher_config.json"));
+        manager.clear_config().unwrap();
+
+        let mut config = UnifiedConfig::new();
+        manager.save_unified_config(&config).unwrap();
+        assert_eq!(manager.get_config_path(), dir.join("switcher_config.json"));
+
+        let mut cache = DeviceCache::new();
+        cache.add_device(SwitcherDevice {
+            device_id: "123".to_string(),
+            name: "Heater".to_string(),
+            ip_address: "192.168.1.100".to_string(),
+            mac_address: "00:11:22:33:44:55".to_string(),
+            device_key: Some("a1".to_string()),
+            device_type: "Switcher Power Plug".to_string(),
+            device_type_code: "01a8".to_string(),
+            state: DeviceState::On,
+            power_consumption: 1500,
+        });
+        let history = &mut cache.devices.get_mut("123").unwrap().power_history;
+        history.extend((0..30_000).map(|timestamp| PowerSample {
+            timestamp,
+            watts: 1500,
+        }));
+        config.cache = Some(cache);
+        manager.save_unified_config(&config).unwrap();
+
+        assert_eq!(
+            manager.get_config_path(),
+            dir.join("switcher_config.json.gz")
+        );
+        assert!(!dir.join("switcher_config.json").exists());
+        let loaded = manager.load_unified_config().unwrap();
+        assert_eq!(
+            loaded.cache.unwrap().devices["123"].power_history.len(),
+            30_000
+        );
+
+        // Stays compressed once switched, even when small again
+        manager.save_unified_config(&UnifiedConfig::new()).unwrap();
+        assert!(manager
+            .get_config_path()
+            .ends_with("switcher_config.json.gz"));
+
+        manager.clear_config().unwrap();
+        assert!(!manager.config_exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_update_writes_once_and_skips_unchanged() {
+        let dir = std::env::temp_dir().join(format!("switcher-update-test-{}", std::process::id()));
+        let manager = ConfigManager::with_path(dir.join("switcher_config.json"));
+        manager.clear_config().unwrap();
+
+        manager.update(|_| false).unwrap();
+        assert!(!manager.config_exists());
+
+        manager
+            .update(|config| {
+                config.cache = Some(DeviceCache::new());
+                config.pairing = Some(PairingConfig::new());
+                true
+            })
+            .unwrap();
+        let loaded = manager.load_unified_config().unwrap();
+        assert!(loaded.cache.is_some() && loaded.pairing.is_some());
+
+        manager.clear_config().unwrap();
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let json = format!(
+            r#"{{"cache": null, "pairing": null, "settings": {{"discovery": {{"timout": 5}}}}, "colour": "red", "version": "{}"}}"#,
+            env!("CARGO_PKG_VERSION")
+        );
+        let mut unknown = unknown_keys_in(&json).unwrap();
+        unknown.sort();
+        assert_eq!(unknown, vec!["colour", "settings.discovery.timout"]);
+
+        // A config the tool wrote has no unknown keys; the derived
+        // state_code is left out of it
+        let mut cache = DeviceCache::new();
+        cache.add_device(SwitcherDevice {
+            device_id: "123".to_string(),
+            name: "Heater".to_string(),
+            ip_address: "192.168.1.100".to_string(),
+            mac_address: "00:11:22:33:44:55".to_string(),
+            device_key: Some("a1".to_string()),
+            device_type: "Switcher Power Plug".to_string(),
+            device_type_code: "01a8".to_string(),
+            state: DeviceState::On,
+            power_consumption: 1500,
+        });
+        let mut config = UnifiedConfig::new();
+        config.cache = Some(cache);
+        let clean = serde_json::to_string(&config).unwrap();
+        assert!(!clean.contains("state_code"));
+        assert!(unknown_keys_in(&clean).unwrap().is_empty());
+
+        let misplaced = clean.replacen("\"state\"", "\"state_code\":1,\"state\"", 1);
+        assert_eq!(
+            unknown_keys_in(&misplaced).unwrap(),
+            vec!["cache.?.devices.123.device.state_code"]
+        );
+    }
+
+    #[test]
+    fn test_settings_default_when_missing() {
+        let json = format!(
+            r#"{{"cache": null, "pairing": null, "version": "{}"}}"#,
+            env!("CARGO_PKG_VERSION")
+        );
+        let config: UnifiedConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(config.settings.discovery.timeout, 30);
+        assert_eq!(config.settings.discovery.cache_timeout, 3600);
+    }
+
+    #[test]
+    fn test_settings_set_and_get() {
+        let mut settings = Settings::default();
+
+        settings.set("discovery.timeout", "15").unwrap();
+        assert_eq!(settings.get("discovery.timeout").unwrap(), "15");
+
+        settings
+            .set("discovery.broadcast_address", "192.168.5.255")
+            .unwrap();
+        assert_eq!(
+            settings.discovery.broadcast_address,
+            Some(Ipv4Addr::new(192, 168, 5, 255))
+        );
+        settings.set("discovery.broadcast_address", "").unwrap();
+        assert_eq!(settings.discovery.broadcast_address, None);
+
+        assert!(settings.set("discovery.timeout", "soon").is_err());
+        assert!(settings.set("discovery.interface", "eth0").is_err());
+        assert!(settings.get("nope").is_err());
     }
 }
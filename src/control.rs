@@ -1,131 +1,420 @@
-use crate::device::{DeviceState, DeviceStatus};
-use crate::utils::current_timestamp_hex;
+use crate::device::{DeviceDetails, DeviceState, DeviceStatus, SwitcherDevice, SwitcherDeviceType};
+use crate::error::SwitcherError;
+use crate::utils::{current_timestamp_hex, format_duration, parse_duration};
 use log::{debug, error, info, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Mutex, OnceLock};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tokio::time::{timeout, Duration};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::time::{timeout, Duration, Instant};
+use tokio_socks::tcp::Socks5Stream;
 
-const SWITCHER_PORT: u16 = 9957;
-const LOGIN_TIMEOUT_SECS: u64 = 3;
+/// TCP port the device accepts login and control packets on
+pub const SWITCHER_CONTROL_PORT: u16 = 9957;
+const RESPONSE_TIMEOUT_SECS: u64 = 3; // login, status and command replies
 const CONNECT_TIMEOUT_SECS: u64 = 5;
 const MIN_LOGIN_RESPONSE_LEN: usize = 20;
-const DEVICE_STATE_BYTE_POS: usize = 75;
-const POWER_BYTE_POS: usize = 77;
+const MIN_COMMAND_RESPONSE_LEN: usize = 20;
+// Responses used to be read with a single `[0; 1024]` read, which silently
+// truncated anything longer. Reads now accumulate in chunks up to a cap.
+const READ_CHUNK_SIZE: usize = 1024;
+const MAX_RESPONSE_LEN: usize = 64 * 1024;
 const COMMAND_VERIFY_DELAY_MS: u64 = 500;
 const COMMAND_RETRY_DELAY_MS: u64 = 1000;
+const COMMAND_REPEAT_GAP_MS: u64 = 200;
+const POWER_POLL_INTERVAL_MS: u64 = 1000;
+const WAKE_RETRY_INTERVAL_MS: u64 = 1000;
+/// Length limits for device names, in bytes as stored on the device
+pub const MIN_DEVICE_NAME_LEN: usize = 2;
+pub const MAX_DEVICE_NAME_LEN: usize = 32;
+// Range the firmware accepts for the auto-shutdown setting (same limits as
+// aioswitcher): one hour up to 23h59m
+const MIN_AUTO_SHUTDOWN_SECS: u64 = 3600;
+const MAX_AUTO_SHUTDOWN_SECS: u64 = 86340;
+// Range for the one-off timer sent with an ON command: one minute up to 24h
+const MIN_AUTO_OFF_SECS: u64 = 60;
+const MAX_AUTO_OFF_SECS: u64 = 86400;
 
+/// Where the fields of a state query response sit for one device model.
+/// Offsets are byte positions; multi-byte values are little-endian. A
+/// `--profile` file can override these for firmware this tool doesn't know.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusLayout {
+    pub min_response_len: usize,
+    pub state_byte: usize,
+    pub power_byte: usize, // u16
+    pub name_range: std::ops::Range<usize>,
+    pub remaining_time_byte: usize, // u32 seconds
+    pub on_duration_byte: usize,    // u32 seconds
+    pub auto_shutdown_byte: usize,  // u32 seconds
+}
+
+const POWER_PLUG_LAYOUT: StatusLayout = StatusLayout {
+    min_response_len: 50,
+    state_byte: 75,
+    power_byte: 77,
+    name_range: 40..72,
+    remaining_time_byte: 89,
+    on_duration_byte: 93,
+    auto_shutdown_byte: 97,
+};
+
+impl StatusLayout {
+    fn for_device_type(device_type: SwitcherDeviceType) -> &'static StatusLayout {
+        match device_type {
+            SwitcherDeviceType::PowerPlug => &POWER_PLUG_LAYOUT,
+        }
+    }
+}
+
+impl Default for StatusLayout {
+    fn default() -> Self {
+        POWER_PLUG_LAYOUT
+    }
+}
+
+/// Control ports tried by `Diagnose --probe-port` when no ports are given.
+/// Older plugs listen on 9957, newer firmware may answer on 10000 instead.
+pub const PROBE_PORTS: &[u16] = &[SWITCHER_CONTROL_PORT, 10000];
+
+/// Hostnames resolved during this run, keyed by `host:port`. A command may
+/// connect several times (verification, IP fallback, one per device in
+/// bulk), and a `--ip office-plug.lan` shouldn't hit DNS for each one.
+static RESOLVED_HOSTS: OnceLock<Mutex<HashMap<String, SocketAddr>>> = OnceLock::new();
+
+/// Resolve a `host:port` address, once per run for hostnames. IP literals
+/// skip the lookup entirely.
+async fn resolve_host(address: &str) -> Result<SocketAddr, SwitcherError> {
+    if let Ok(address) = address.parse::<SocketAddr>() {
+        return Ok(address);
+    }
+
+    let resolved = RESOLVED_HOSTS.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(&cached) = resolved.lock().unwrap().get(address) {
+        debug!("Using cached resolution {} for {}", cached, address);
+        return Ok(cached);
+    }
+
+    let remote = tokio::net::lookup_host(address)
+        .await
+        .map_err(|e| SwitcherError::HostNotFound(format!("Could not resolve {}: {}", address, e)))?
+        .next()
+        .ok_or_else(|| {
+            SwitcherError::HostNotFound(format!("Could not resolve {}: no addresses", address))
+        })?;
+    debug!("Resolved {} to {}", address, remote);
+    resolved.lock().unwrap().insert(address.to_string(), remote);
+    Ok(remote)
+}
+
+/// Parse a `--proxy` value of the form `socks5://host:port`, returning the
+/// proxy's `host:port`
+pub fn parse_proxy_url(value: &str) -> Result<String, String> {
+    let invalid = || {
+        format!(
+            "'{}' is not a SOCKS5 proxy URL (expected socks5://host:port)",
+            value
+        )
+    };
+    let address = value.strip_prefix("socks5://").ok_or_else(invalid)?;
+    let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+    if host.is_empty() || port.parse::<u16>().is_err() {
+        return Err(invalid());
+    }
+    Ok(address.to_string())
+}
+
+/// Check an auto-shutdown duration against the range the firmware accepts
+pub fn validate_auto_shutdown(duration: Duration) -> Result<u32, SwitcherError> {
+    let secs = duration.as_secs();
+    if !(MIN_AUTO_SHUTDOWN_SECS..=MAX_AUTO_SHUTDOWN_SECS).contains(&secs) {
+        return Err(SwitcherError::Usage(format!(
+            "Auto-shutdown must be between 1h and 23h59m, got {}",
+            format_duration(secs)
+        )));
+    }
+    Ok(secs as u32)
+}
+
+/// Check a one-off `--auto-off` timer against the range the firmware accepts
+pub fn validate_auto_off(duration: Duration) -> Result<u32, SwitcherError> {
+    let secs = duration.as_secs();
+    if !(MIN_AUTO_OFF_SECS..=MAX_AUTO_OFF_SECS).contains(&secs) {
+        return Err(SwitcherError::Usage(format!(
+            "Auto-off timer must be between 1m and 24h, got {}",
+            format_duration(secs)
+        )));
+    }
+    Ok(secs as u32)
+}
+
+/// Parse an `--auto-off` value for `on`, e.g. `30m`, `1h30m` or `90`
+pub fn parse_auto_off(value: &str) -> Result<Duration, String> {
+    let duration = parse_duration(value)?;
+    validate_auto_off(duration).map_err(|e| e.to_string())?;
+    Ok(duration)
+}
+
+/// Parse a `--set` value for `auto-shutdown`, e.g. `2h` or `1h30m`
+pub fn parse_auto_shutdown(value: &str) -> Result<Duration, String> {
+    let duration = parse_duration(value)?;
+    validate_auto_shutdown(duration).map_err(|e| e.to_string())?;
+    Ok(duration)
+}
+
+/// Connection, timeout and retry settings for a controller. Build one from
+/// the command line flags and hand a clone to every controller a command
+/// creates, rather than chaining a builder call per setting.
+#[derive(Debug, Clone)]
+pub struct ControllerConfig {
+    /// Control port, 9957 unless the firmware listens elsewhere
+    pub port: u16,
+    /// Send each on/off packet this many times, for relays that drop the
+    /// first packet. Unlike the verification retry this always re-sends.
+    pub repeat: u32,
+    /// Open control connections through this SOCKS5 proxy (`host:port`),
+    /// e.g. an SSH tunnel. Discovery is UDP and can't use it.
+    pub proxy: Option<String>,
+    /// Bind the client end of control connections to this local port, for
+    /// firewalls that only allow a known source port. Ephemeral if unset.
+    pub local_port: Option<u16>,
+    /// Response offsets to use instead of the built-in ones for the model
+    pub layout: Option<StatusLayout>,
+    /// Keep retrying a refused or timed-out connect for this long, for
+    /// sleep-capable models that only accept connections when they wake
+    pub wake_window: Option<Duration>,
+    /// Query the state before an on/off command and send nothing if the
+    /// device is already there, sparing the relay and a round-trip
+    pub precheck_state: bool,
+    pub connect_timeout: Duration,
+    /// How long to wait for the login, status and command replies
+    pub response_timeout: Duration,
+}
+
+impl Default for ControllerConfig {
+    fn default() -> Self {
+        Self {
+            port: SWITCHER_CONTROL_PORT,
+            repeat: 1,
+            proxy: None,
+            local_port: None,
+            layout: None,
+            wake_window: None,
+            precheck_state: false,
+            connect_timeout: Duration::from_secs(CONNECT_TIMEOUT_SECS),
+            response_timeout: Duration::from_secs(RESPONSE_TIMEOUT_SECS),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct SwitcherController {
     ip_address: String,
     device_id: String,
-    port: u16,
+    device_type: SwitcherDeviceType,
+    config: ControllerConfig,
 }
 
 impl SwitcherController {
+    #[allow(dead_code)] // library API, the CLI always passes its ControllerConfig
     pub fn new(ip_address: String, device_id: String) -> Self {
+        Self::with_config(ip_address, device_id, ControllerConfig::default())
+    }
+
+    pub fn with_config(ip_address: String, device_id: String, config: ControllerConfig) -> Self {
         Self {
             ip_address,
             device_id,
-            port: SWITCHER_PORT,
+            device_type: SwitcherDeviceType::default(),
+            config: ControllerConfig {
+                repeat: config.repeat.max(1),
+                ..config
+            },
+        }
+    }
+
+    /// Build a controller for a discovered, cached or paired device. The
+    /// device's type code selects how status responses are parsed.
+    #[allow(dead_code)] // library API, the CLI always passes its ControllerConfig
+    pub fn from_device(device: &SwitcherDevice) -> Self {
+        Self::from_device_with_config(device, ControllerConfig::default())
+    }
+
+    pub fn from_device_with_config(device: &SwitcherDevice, config: ControllerConfig) -> Self {
+        let mut controller =
+            Self::with_config(device.ip_address.clone(), device.device_id.clone(), config);
+        if let Some(device_type) = SwitcherDeviceType::from_type_code(&device.device_type_code) {
+            controller.device_type = device_type;
         }
+        controller
+    }
+
+    fn layout(&self) -> &StatusLayout {
+        self.config
+            .layout
+            .as_ref()
+            .unwrap_or_else(|| StatusLayout::for_device_type(self.device_type))
+    }
+
+    /// Same device at a different address, e.g. one from its IP history
+    pub fn with_ip_address(mut self, ip_address: String) -> Self {
+        self.ip_address = ip_address;
+        self
+    }
+
+    pub fn ip_address(&self) -> &str {
+        &self.ip_address
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    /// The control port this controller connects to
+    pub fn port(&self) -> u16 {
+        self.config.port
+    }
+
+    /// Connect and log in without sending any command, to check that the
+    /// device accepts a session on the configured port
+    pub async fn check_login(&self) -> Result<(), SwitcherError> {
+        let session = self.open_session().await?;
+        debug!(
+            "Login accepted on port {}, session_id: {}",
+            self.config.port,
+            session.session_id()
+        );
+        Ok(())
     }
 
-    pub async fn turn_on(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn turn_on(&self) -> Result<(), SwitcherError> {
         info!(
             "Turning device ON - IP: {}, Device ID: {}",
             self.ip_address, self.device_id
         );
 
-        debug!("Sending turn ON command");
-        self.send_control_command("1").await?;
+        let mut session = self.open_session().await?;
+        session.turn_on().await?;
 
-        // Verify the command worked by checking status (with retry)
-        debug!(
-            "Waiting {}ms before verifying command",
-            COMMAND_VERIFY_DELAY_MS
+        info!("Device successfully turned ON");
+        Ok(())
+    }
+
+    /// Turn the device on, then keep polling on the same session until the
+    /// load draws at least `min_watts` or `timeout` elapses. Returns the
+    /// last reading either way; it is below `min_watts` on timeout.
+    pub async fn turn_on_and_wait_for_power(
+        &self,
+        min_watts: u16,
+        timeout: Duration,
+    ) -> Result<DeviceStatus, SwitcherError> {
+        info!(
+            "Turning device ON and waiting for {}W - IP: {}, Device ID: {}",
+            min_watts, self.ip_address, self.device_id
         );
-        tokio::time::sleep(tokio::time::Duration::from_millis(COMMAND_VERIFY_DELAY_MS)).await;
-        let mut status = self.get_status().await?;
 
-        if status.state != DeviceState::On {
-            warn!(
-                "Device not ON after first attempt, retrying after {}ms",
-                COMMAND_RETRY_DELAY_MS
-            );
-            // Device might need more time, try once more
-            tokio::time::sleep(tokio::time::Duration::from_millis(COMMAND_RETRY_DELAY_MS)).await;
-            status = self.get_status().await?;
+        let mut session = self.open_session().await?;
+        session.turn_on().await?;
 
-            if status.state != DeviceState::On {
-                error!(
-                    "Device failed to turn ON after retry - current state: {:?}",
-                    status.state
-                );
-                return Err("Command sent but device did not turn ON (invalid device ID?)".into());
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = session.get_status().await?;
+            debug!(
+                "Load drawing {}W, waiting for {}W",
+                status.power_consumption, min_watts
+            );
+            if status.power_consumption >= min_watts {
+                return Ok(status);
+            }
+            let poll_interval = Duration::from_millis(POWER_POLL_INTERVAL_MS);
+            if Instant::now() + poll_interval > deadline {
+                return Ok(status);
             }
+            tokio::time::sleep(poll_interval).await;
         }
+    }
 
-        info!("Device successfully turned ON");
+    /// Turn the device on with a one-off timer, after which the device turns
+    /// itself off. Unlike `set_auto_shutdown` this only applies this time.
+    pub async fn turn_on_with_timer(&self, duration: Duration) -> Result<(), SwitcherError> {
+        // Reject out-of-range values before touching the network
+        let secs = validate_auto_off(duration)?;
+
+        info!(
+            "Turning device ON for {} - IP: {}, Device ID: {}",
+            format_duration(secs.into()),
+            self.ip_address,
+            self.device_id
+        );
+
+        let mut session = self.open_session().await?;
+        session.turn_on_with_timer(secs).await?;
+
+        info!("Device successfully turned ON with a timer");
         Ok(())
     }
 
-    pub async fn turn_off(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn turn_off(&self) -> Result<(), SwitcherError> {
         info!(
             "Turning device OFF - IP: {}, Device ID: {}",
             self.ip_address, self.device_id
         );
 
-        debug!("Sending turn OFF command");
-        self.send_control_command("0").await?;
-
-        // Verify the command worked by checking status (with retry)
-        debug!(
-            "Waiting {}ms before verifying command",
-            COMMAND_VERIFY_DELAY_MS
-        );
-        tokio::time::sleep(tokio::time::Duration::from_millis(COMMAND_VERIFY_DELAY_MS)).await;
-        let mut status = self.get_status().await?;
+        let mut session = self.open_session().await?;
+        session.turn_off().await?;
 
-        if status.state != DeviceState::Off {
-            warn!(
-                "Device not OFF after first attempt, retrying after {}ms",
-                COMMAND_RETRY_DELAY_MS
-            );
-            // Device might need more time, try once more
-            tokio::time::sleep(tokio::time::Duration::from_millis(COMMAND_RETRY_DELAY_MS)).await;
-            status = self.get_status().await?;
+        info!("Device successfully turned OFF");
+        Ok(())
+    }
 
-            if status.state != DeviceState::Off {
-                error!(
-                    "Device failed to turn OFF after retry - current state: {:?}",
-                    status.state
-                );
-                return Err("Command sent but device did not turn OFF (invalid device ID?)".into());
+    /// Send the on/off control packet and return as soon as it is written,
+    /// skipping the status verification round-trip. Success only means the
+    /// packet was sent, not that the device changed state.
+    pub async fn send_command_unverified(&self, target: DeviceState) -> Result<(), SwitcherError> {
+        let command = match target {
+            DeviceState::On => "1",
+            DeviceState::Off => "0",
+            DeviceState::Unknown => {
+                return Err(SwitcherError::Usage(
+                    "Cannot send an Unknown state command".to_string(),
+                ))
             }
-        }
+        };
 
-        info!("Device successfully turned OFF");
-        Ok(())
+        info!(
+            "Sending unverified {:?} command - IP: {}, Device ID: {}",
+            target, self.ip_address, self.device_id
+        );
+        self.send_repeated(command).await
     }
 
-    pub async fn set_device_name(&self, new_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut stream = timeout(
-            Duration::from_secs(CONNECT_TIMEOUT_SECS),
-            TcpStream::connect(format!("{}:{}", self.ip_address, self.port)),
-        )
-        .await??;
+    pub async fn set_device_name(&self, new_name: &str) -> Result<(), SwitcherError> {
+        // Reject invalid names before touching the network
+        self.string_to_hexadecimal_device_name(new_name)?;
+
+        let mut stream = self.connect().await?;
 
         let (timestamp, session_id) = self.login(&mut stream).await?;
         let packet = self.build_set_name_packet(&session_id, &timestamp, new_name)?;
 
         let signed_packet = self.sign_packet(&packet);
-        stream.write_all(&hex::decode(signed_packet)?).await?;
+        stream.write_all(&packet_bytes(&signed_packet)?).await?;
 
         // Read response to confirm command was received
-        let mut response = [0; 1024];
-        let len = stream.read(&mut response).await?;
+        let response = read_at_least(
+            &mut stream,
+            MIN_COMMAND_RESPONSE_LEN,
+            self.config.response_timeout,
+        )
+        .await?;
 
-        if len < 20 {
-            return Err("Device did not respond to name change command".into());
+        if response.len() < MIN_COMMAND_RESPONSE_LEN {
+            return Err(SwitcherError::ShortResponse(
+                "Device did not respond to name change command".to_string(),
+            ));
         }
 
         // Wait a moment for the device to process the name change
@@ -134,130 +423,240 @@ impl SwitcherController {
         Ok(())
     }
 
-    pub async fn get_status(&self) -> Result<DeviceStatus, Box<dyn std::error::Error>> {
+    pub async fn get_status(&self) -> Result<DeviceStatus, SwitcherError> {
         debug!(
             "Getting device status - IP: {}, Device ID: {}",
             self.ip_address, self.device_id
         );
 
-        debug!("Connecting to device at {}:{}", self.ip_address, self.port);
-        let mut stream = timeout(
-            Duration::from_secs(CONNECT_TIMEOUT_SECS),
-            TcpStream::connect(format!("{}:{}", self.ip_address, self.port)),
-        )
-        .await
-        .map_err(|e| {
-            error!(
-                "Connection timeout to {}:{}: {}",
-                self.ip_address, self.port, e
-            );
-            e
-        })?
-        .map_err(|e| {
-            error!(
-                "Failed to connect to {}:{}: {}",
-                self.ip_address, self.port, e
-            );
-            e
-        })?;
+        let mut session = self.open_session().await?;
+        session.get_status().await
+    }
 
-        debug!("Successfully connected, performing login");
-        let (timestamp, session_id) = self.login(&mut stream).await?;
-        debug!("Login successful, session_id: {}", session_id);
+    /// Query the extended device record: name, timers and auto-shutdown
+    /// configuration on top of state and power. Fields the firmware leaves
+    /// out of its response are reported as `None`.
+    pub async fn get_details(&self) -> Result<DeviceDetails, SwitcherError> {
+        debug!(
+            "Getting device details - IP: {}, Device ID: {}",
+            self.ip_address, self.device_id
+        );
 
-        let packet = self.build_get_state_packet(&session_id, &timestamp);
-        debug!("Built status request packet");
+        let mut session = self.open_session().await?;
+        session.get_details().await
+    }
 
-        let signed_packet = self.sign_packet(&packet);
-        debug!("Sending status request packet");
-        stream.write_all(&hex::decode(signed_packet)?).await?;
+    /// Read the persistent auto-shutdown duration. Unlike a countdown timer
+    /// this applies every time the device is turned on.
+    pub async fn get_auto_shutdown(&self) -> Result<Duration, SwitcherError> {
+        let details = self.get_details().await?;
+        details
+            .auto_shutdown_seconds
+            .map(|secs| Duration::from_secs(secs.into()))
+            .ok_or_else(|| {
+                SwitcherError::InvalidDevice(
+                    "Device did not report an auto-shutdown setting, this model may not support it"
+                        .to_string(),
+                )
+            })
+    }
 
-        let mut response = [0; 1024];
-        let len = stream.read(&mut response).await?;
-        debug!("Received {} bytes response", len);
+    /// Change the persistent auto-shutdown duration, which must be between
+    /// 1h and 23h59m
+    pub async fn set_auto_shutdown(&self, duration: Duration) -> Result<(), SwitcherError> {
+        // Reject out-of-range values before touching the network
+        let secs = validate_auto_shutdown(duration)?;
 
-        // Check if we got a valid response (should be > 100 bytes for real device)
-        if len < 50 {
-            error!(
-                "Received short response ({} bytes), device may not exist or invalid device ID",
-                len
-            );
-            return Err("Device did not respond or invalid device ID".into());
-        }
+        info!(
+            "Setting auto-shutdown to {} - IP: {}, Device ID: {}",
+            format_duration(secs.into()),
+            self.ip_address,
+            self.device_id
+        );
+        let mut session = self.open_session().await?;
+        session.set_auto_shutdown(secs).await
+    }
 
-        let state = if len > DEVICE_STATE_BYTE_POS {
-            match response[DEVICE_STATE_BYTE_POS] {
-                0x01 => DeviceState::On,
-                0x00 => DeviceState::Off,
-                _ => DeviceState::Unknown,
+    async fn send_repeated(&self, command: &str) -> Result<(), SwitcherError> {
+        for attempt in 1..=self.config.repeat {
+            if attempt > 1 {
+                debug!(
+                    "Repeating control command '{}' ({}/{})",
+                    command, attempt, self.config.repeat
+                );
+                tokio::time::sleep(Duration::from_millis(COMMAND_REPEAT_GAP_MS)).await;
             }
-        } else {
-            DeviceState::Off
-        };
+            self.send_control_command(command).await?;
+        }
+        Ok(())
+    }
 
-        let power = if len > POWER_BYTE_POS + 1 {
-            u16::from_le_bytes([response[POWER_BYTE_POS], response[POWER_BYTE_POS + 1]])
-        } else {
-            0
-        };
+    async fn send_control_command(&self, command: &str) -> Result<(), SwitcherError> {
+        debug!(
+            "Sending control command '{}' to device at {}:{}",
+            command, self.ip_address, self.config.port
+        );
+        // Unverified: don't wait for the reply, the connection is dropped anyway
+        let mut session = self.open_session().await?;
+        session.write_control_packet(command, 0).await
+    }
 
-        Ok(DeviceStatus {
-            state,
-            power_consumption: power,
+    /// Connect and log in once, returning a session that can send several
+    /// commands over the same connection without logging in again
+    pub async fn open_session(&self) -> Result<Session, SwitcherError> {
+        let mut stream = self.connect().await?;
+
+        debug!("Successfully connected, performing login");
+        let (timestamp, session_id) = self.login(&mut stream).await?;
+        debug!("Login successful, session_id: {}", session_id);
+
+        Ok(Session {
+            controller: self.clone(),
+            stream,
+            session_id,
+            timestamp,
         })
     }
 
-    async fn send_control_command(&self, command: &str) -> Result<(), Box<dyn std::error::Error>> {
+    async fn connect(&self) -> Result<TcpStream, SwitcherError> {
+        let Some(wake_window) = self.config.wake_window else {
+            return self.connect_once().await;
+        };
+
+        let started = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let e = match self.connect_once().await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => e,
+            };
+            let asleep = matches!(e, SwitcherError::Unreachable(_) | SwitcherError::Timeout(_));
+            if !asleep || started.elapsed() >= wake_window {
+                return Err(e);
+            }
+            debug!(
+                "Connect attempt {} to {} failed, device may be asleep: {}",
+                attempt, self.ip_address, e
+            );
+            tokio::time::sleep(Duration::from_millis(WAKE_RETRY_INTERVAL_MS)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn connect_once(&self) -> Result<TcpStream, SwitcherError> {
+        if let Some(proxy) = &self.config.proxy {
+            return self.connect_via_proxy(proxy).await;
+        }
+
         debug!(
-            "Sending control command '{}' to device at {}:{}",
-            command, self.ip_address, self.port
+            "Connecting to device at {}:{}",
+            self.ip_address, self.config.port
         );
-
-        debug!("Connecting to device for control command");
-        let mut stream = TcpStream::connect(format!("{}:{}", self.ip_address, self.port))
+        let address = format!("{}:{}", self.ip_address, self.config.port);
+        let remote = timeout(self.config.connect_timeout, resolve_host(&address))
+            .await
+            .map_err(|_| {
+                SwitcherError::HostNotFound(format!("Timed out resolving {}", address))
+            })??;
+        let stream = timeout(self.config.connect_timeout, self.open_tcp(remote))
             .await
             .map_err(|e| {
-                error!("Failed to connect to device for control command: {}", e);
-                e
+                error!(
+                    "Connection timeout to {}:{}: {}",
+                    self.ip_address, self.config.port, e
+                );
+                SwitcherError::Timeout(format!(
+                    "Timed out connecting to {}:{}",
+                    self.ip_address, self.config.port
+                ))
+            })?
+            .map_err(|e| {
+                error!(
+                    "Failed to connect to {}:{}: {}",
+                    self.ip_address, self.config.port, e
+                );
+                SwitcherError::Unreachable(format!(
+                    "Could not connect to {}:{}: {}",
+                    self.ip_address, self.config.port, e
+                ))
             })?;
 
-        debug!("Connected, performing login for control command");
-        let (timestamp, session_id) = self.login(&mut stream).await?;
+        Ok(stream)
+    }
+
+    async fn connect_via_proxy(&self, proxy: &str) -> Result<TcpStream, SwitcherError> {
         debug!(
-            "Login successful for control command, session_id: {}",
-            session_id
+            "Connecting to device at {}:{} through SOCKS5 proxy {}",
+            self.ip_address, self.config.port, proxy
         );
+        // The device address goes to the proxy as-is, so hostnames are
+        // resolved on the far side of the tunnel
+        let proxy_address = timeout(self.config.connect_timeout, resolve_host(proxy))
+            .await
+            .map_err(|_| SwitcherError::HostNotFound(format!("Timed out resolving {}", proxy)))??;
+        let stream = timeout(self.config.connect_timeout, async {
+            let socket = self.open_tcp(proxy_address).await?;
+            Socks5Stream::connect_with_socket(socket, (self.ip_address.as_str(), self.config.port))
+                .await
+                .map_err(std::io::Error::other)
+        })
+        .await
+        .map_err(|_| {
+            SwitcherError::Timeout(format!(
+                "Timed out connecting to {}:{} through proxy {}",
+                self.ip_address, self.config.port, proxy
+            ))
+        })?
+        .map_err(|e| {
+            error!(
+                "Failed to connect to {}:{} through proxy {}: {}",
+                self.ip_address, self.config.port, proxy, e
+            );
+            SwitcherError::Unreachable(format!(
+                "Could not connect to {}:{} through proxy {}: {}",
+                self.ip_address, self.config.port, proxy, e
+            ))
+        })?;
 
-        let packet = self.build_control_packet(&session_id, &timestamp, command);
-        debug!("Built control packet for command '{}'", command);
+        Ok(stream.into_inner())
+    }
 
-        let signed_packet = self.sign_packet(&packet);
-        debug!("Sending control command packet");
-        stream.write_all(&hex::decode(signed_packet)?).await?;
+    /// Open a TCP connection to `address` (`host:port`), from
+    /// `config.local_port` when one is set
+    async fn open_tcp(&self, remote: SocketAddr) -> std::io::Result<TcpStream> {
+        let Some(local_port) = self.config.local_port else {
+            return TcpStream::connect(remote).await;
+        };
 
-        debug!("Control command '{}' sent successfully", command);
-        Ok(())
+        let (socket, local_ip): (_, std::net::IpAddr) = if remote.is_ipv4() {
+            (TcpSocket::new_v4()?, std::net::Ipv4Addr::UNSPECIFIED.into())
+        } else {
+            (TcpSocket::new_v6()?, std::net::Ipv6Addr::UNSPECIFIED.into())
+        };
+        // Reconnecting from the same port soon after a previous connection
+        // would otherwise fail while the old one sits in TIME_WAIT
+        socket.set_reuseaddr(true)?;
+        socket.bind(SocketAddr::new(local_ip, local_port))?;
+        debug!("Connecting to {} from local port {}", remote, local_port);
+        socket.connect(remote).await
     }
 
-    async fn login(
-        &self,
-        stream: &mut TcpStream,
-    ) -> Result<(String, String), Box<dyn std::error::Error>> {
+    async fn login(&self, stream: &mut TcpStream) -> Result<(String, String), SwitcherError> {
         let timestamp = self.get_timestamp();
         let packet = self.build_login_packet(&timestamp);
         let signed_packet = self.sign_packet(&packet);
 
-        stream.write_all(&hex::decode(signed_packet)?).await?;
+        stream.write_all(&packet_bytes(&signed_packet)?).await?;
 
-        let mut response = [0; 1024];
-        let len = timeout(
-            Duration::from_secs(LOGIN_TIMEOUT_SECS),
-            stream.read(&mut response),
-        )
-        .await??;
+        let response =
+            read_at_least(stream, MIN_LOGIN_RESPONSE_LEN, self.config.response_timeout).await?;
 
-        if len < MIN_LOGIN_RESPONSE_LEN {
-            return Err("Login response too short".into());
+        if response.len() < MIN_LOGIN_RESPONSE_LEN {
+            return Err(SwitcherError::LoginFailed(format!(
+                "Login response too short ({} of {} bytes)",
+                response.len(),
+                MIN_LOGIN_RESPONSE_LEN
+            )));
         }
 
         let session_id = hex::encode(&response[16..20]);
@@ -277,7 +676,15 @@ impl SwitcherController {
         )
     }
 
-    fn build_control_packet(&self, session_id: &str, timestamp: &str, command: &str) -> String {
+    /// `timer_secs` turns the device off again after that long; 0 for no
+    /// timer. Following aioswitcher TURN_ON_WITH_TIMER_PACKET format.
+    fn build_control_packet(
+        &self,
+        session_id: &str,
+        timestamp: &str,
+        command: &str,
+        timer_secs: u32,
+    ) -> String {
         format!(
             "fef05d0002320102{}340001000000000000000000{}00000000000000000000f0fe{}{}000106000{}00{}",
             session_id,
@@ -285,7 +692,7 @@ impl SwitcherController {
             &self.device_id,
             "0".repeat(72),
             command,
-            "00000000"
+            hex::encode(timer_secs.to_le_bytes())
         )
     }
 
@@ -296,12 +703,24 @@ impl SwitcherController {
         )
     }
 
+    fn build_auto_shutdown_packet(&self, session_id: &str, timestamp: &str, secs: u32) -> String {
+        // Following aioswitcher UPDATE_AUTO_OFF_SET_PACKET format
+        format!(
+            "fef05b0002320102{}340001000000000000000000{}00000000000000000000f0fe{}{}040400{}",
+            session_id,
+            timestamp,
+            &self.device_id,
+            "0".repeat(74),
+            hex::encode(secs.to_le_bytes())
+        )
+    }
+
     fn build_set_name_packet(
         &self,
         session_id: &str,
         timestamp: &str,
         new_name: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+    ) -> Result<String, SwitcherError> {
         // Convert name to hex and pad to 32 bytes (following aioswitcher implementation)
         let name_hex = self.string_to_hexadecimal_device_name(new_name)?;
 
@@ -316,17 +735,19 @@ impl SwitcherController {
         ))
     }
 
-    fn string_to_hexadecimal_device_name(
-        &self,
-        name: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+    fn string_to_hexadecimal_device_name(&self, name: &str) -> Result<String, SwitcherError> {
         let length = name.len();
-        if length < 2 || length > 32 {
-            return Err(format!(
-                "Device name length must be between 2 and 32 characters, got {}",
-                length
-            )
-            .into());
+        if length < MIN_DEVICE_NAME_LEN {
+            return Err(SwitcherError::NameTooShort(length));
+        }
+        if length > MAX_DEVICE_NAME_LEN {
+            return Err(SwitcherError::NameTooLong(length));
+        }
+
+        // The device stores the name NUL-terminated, anything after an
+        // embedded NUL would be silently dropped
+        if name.contains('\0') {
+            return Err(SwitcherError::NameContainsNul);
         }
 
         let name_bytes = name.as_bytes();
@@ -370,3 +791,799 @@ impl SwitcherController {
         )
     }
 }
+
+/// A logged-in connection to one device. Commands sent through a session
+/// reuse its stream and session id instead of connecting and logging in
+/// for each packet; the connection closes when the session is dropped.
+pub struct Session {
+    controller: SwitcherController,
+    stream: TcpStream,
+    session_id: String,
+    timestamp: String, // from the login packet, reused for every command
+}
+
+impl Session {
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub async fn get_status(&mut self) -> Result<DeviceStatus, SwitcherError> {
+        // Wait for the power field too, the device may split the response across reads
+        let layout = self.controller.layout().clone();
+        let response = self.query_state(layout.power_byte + 2).await?;
+
+        Ok(DeviceStatus {
+            state: parse_state(&layout, &response),
+            power_consumption: parse_power(&layout, &response),
+        })
+    }
+
+    /// The extended device record, as `SwitcherController::get_details`
+    pub async fn get_details(&mut self) -> Result<DeviceDetails, SwitcherError> {
+        let layout = self.controller.layout().clone();
+        let response = self.query_state(layout.auto_shutdown_byte + 4).await?;
+        Ok(parse_details(
+            &layout,
+            &self.controller.device_id,
+            &response,
+        ))
+    }
+
+    pub async fn turn_on(&mut self) -> Result<(), SwitcherError> {
+        self.switch(DeviceState::On, "1", 0).await
+    }
+
+    pub async fn turn_on_with_timer(&mut self, secs: u32) -> Result<(), SwitcherError> {
+        self.switch(DeviceState::On, "1", secs).await
+    }
+
+    pub async fn turn_off(&mut self) -> Result<(), SwitcherError> {
+        self.switch(DeviceState::Off, "0", 0).await
+    }
+
+    /// Send the control packet (repeated as configured on the controller),
+    /// then check the state on the same session, giving the device one more
+    /// chance if it has not switched yet
+    async fn switch(
+        &mut self,
+        target: DeviceState,
+        command: &str,
+        timer_secs: u32,
+    ) -> Result<(), SwitcherError> {
+        let label = target.to_string().to_uppercase();
+        if self.controller.config.precheck_state && self.get_status().await?.state == target {
+            info!("Device already {}, not sending the command", label);
+            return Ok(());
+        }
+
+        let repeat = self.controller.config.repeat;
+        for attempt in 1..=repeat {
+            if attempt > 1 {
+                debug!(
+                    "Repeating control command '{}' ({}/{})",
+                    command, attempt, repeat
+                );
+                tokio::time::sleep(Duration::from_millis(COMMAND_REPEAT_GAP_MS)).await;
+            }
+            self.send_control_command(command, timer_secs).await?;
+        }
+
+        // Verify the command worked by checking status (with retry)
+        debug!(
+            "Waiting {}ms before verifying command",
+            COMMAND_VERIFY_DELAY_MS
+        );
+        tokio::time::sleep(Duration::from_millis(COMMAND_VERIFY_DELAY_MS)).await;
+        let mut status = self.get_status().await?;
+
+        if status.state != target {
+            warn!(
+                "Device not {} after first attempt, retrying after {}ms",
+                label, COMMAND_RETRY_DELAY_MS
+            );
+            // Device might need more time, try once more
+            tokio::time::sleep(Duration::from_millis(COMMAND_RETRY_DELAY_MS)).await;
+            status = self.get_status().await?;
+
+            if status.state != target {
+                error!(
+                    "Device failed to turn {} after retry - current state: {:?}",
+                    label, status.state
+                );
+                return Err(SwitcherError::InvalidDevice(format!(
+                    "Command sent but device did not turn {} (invalid device ID?)",
+                    label
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send the state query and return the raw response, waiting for up to
+    /// `wanted_len` bytes
+    async fn query_state(&mut self, wanted_len: usize) -> Result<Vec<u8>, SwitcherError> {
+        let packet = self
+            .controller
+            .build_get_state_packet(&self.session_id, &self.timestamp);
+        debug!("Built status request packet");
+
+        let signed_packet = self.controller.sign_packet(&packet);
+        debug!("Sending status request packet");
+        self.stream
+            .write_all(&packet_bytes(&signed_packet)?)
+            .await?;
+
+        let response = read_at_least(
+            &mut self.stream,
+            wanted_len,
+            self.controller.config.response_timeout,
+        )
+        .await?;
+        let len = response.len();
+        debug!("Received {} bytes response", len);
+
+        // Check if we got a valid response (should be > 100 bytes for real device)
+        if len < self.controller.layout().min_response_len {
+            error!(
+                "Received short response ({} bytes), device may not exist or invalid device ID",
+                len
+            );
+            return Err(SwitcherError::InvalidDevice(
+                "Device did not respond or invalid device ID".to_string(),
+            ));
+        }
+
+        Ok(response)
+    }
+
+    async fn set_auto_shutdown(&mut self, secs: u32) -> Result<(), SwitcherError> {
+        let packet =
+            self.controller
+                .build_auto_shutdown_packet(&self.session_id, &self.timestamp, secs);
+        let signed_packet = self.controller.sign_packet(&packet);
+        self.stream
+            .write_all(&packet_bytes(&signed_packet)?)
+            .await?;
+
+        let response = read_at_least(
+            &mut self.stream,
+            MIN_COMMAND_RESPONSE_LEN,
+            self.controller.config.response_timeout,
+        )
+        .await?;
+        if response.len() < MIN_COMMAND_RESPONSE_LEN {
+            return Err(SwitcherError::ShortResponse(
+                "Device did not respond to auto-shutdown command".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write the control packet (repeated as configured) without reading the
+    /// reply or verifying the state, as `send_command_unverified` does
+    pub async fn send_unverified(&mut self, target: DeviceState) -> Result<(), SwitcherError> {
+        let command = match target {
+            DeviceState::On => "1",
+            DeviceState::Off => "0",
+            DeviceState::Unknown => {
+                return Err(SwitcherError::Usage(
+                    "Cannot send an Unknown state command".to_string(),
+                ))
+            }
+        };
+
+        let repeat = self.controller.config.repeat;
+        for attempt in 1..=repeat {
+            if attempt > 1 {
+                tokio::time::sleep(Duration::from_millis(COMMAND_REPEAT_GAP_MS)).await;
+            }
+            self.write_control_packet(command, 0).await?;
+        }
+        Ok(())
+    }
+
+    /// Send the control packet and consume the device's reply, so the next
+    /// query on this session reads its own response
+    async fn send_control_command(
+        &mut self,
+        command: &str,
+        timer_secs: u32,
+    ) -> Result<(), SwitcherError> {
+        self.write_control_packet(command, timer_secs).await?;
+
+        let response = read_at_least(
+            &mut self.stream,
+            MIN_COMMAND_RESPONSE_LEN,
+            self.controller.config.response_timeout,
+        )
+        .await?;
+        if response.len() < MIN_COMMAND_RESPONSE_LEN {
+            return Err(SwitcherError::ShortResponse(format!(
+                "Device did not respond to control command '{}'",
+                command
+            )));
+        }
+        Ok(())
+    }
+
+    async fn write_control_packet(
+        &mut self,
+        command: &str,
+        timer_secs: u32,
+    ) -> Result<(), SwitcherError> {
+        let packet = self.controller.build_control_packet(
+            &self.session_id,
+            &self.timestamp,
+            command,
+            timer_secs,
+        );
+        debug!("Built control packet for command '{}'", command);
+
+        let signed_packet = self.controller.sign_packet(&packet);
+        debug!("Sending control command packet");
+        self.stream
+            .write_all(&packet_bytes(&signed_packet)?)
+            .await?;
+
+        debug!("Control command '{}' sent successfully", command);
+        Ok(())
+    }
+}
+
+/// Decode a signed hex packet for the wire. The device ID is spliced into
+/// every packet, so a non-hex ID is what makes this fail.
+fn packet_bytes(signed_packet: &str) -> Result<Vec<u8>, SwitcherError> {
+    hex::decode(signed_packet)
+        .map_err(|e| SwitcherError::InvalidDevice(format!("Device ID is not valid hex: {}", e)))
+}
+
+fn parse_state(layout: &StatusLayout, response: &[u8]) -> DeviceState {
+    match response.get(layout.state_byte) {
+        Some(0x01) => DeviceState::On,
+        Some(0x00) | None => DeviceState::Off,
+        Some(_) => DeviceState::Unknown,
+    }
+}
+
+fn parse_power(layout: &StatusLayout, response: &[u8]) -> u16 {
+    response
+        .get(layout.power_byte..layout.power_byte + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .unwrap_or(0)
+}
+
+fn parse_u32_le(response: &[u8], pos: usize) -> Option<u32> {
+    let bytes = response.get(pos..pos + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn parse_details(layout: &StatusLayout, device_id: &str, response: &[u8]) -> DeviceDetails {
+    let name = response
+        .get(layout.name_range.clone())
+        .and_then(|name_bytes| {
+            let name_end = name_bytes
+                .iter()
+                .position(|&b| b == 0)
+                .unwrap_or(name_bytes.len());
+            let name = String::from_utf8_lossy(&name_bytes[..name_end]).to_string();
+            (!name.is_empty()).then_some(name)
+        });
+
+    DeviceDetails {
+        device_id: device_id.to_string(),
+        name,
+        state: parse_state(layout, response),
+        power_consumption: parse_power(layout, response),
+        remaining_seconds: parse_u32_le(response, layout.remaining_time_byte),
+        on_duration_seconds: parse_u32_le(response, layout.on_duration_byte),
+        auto_shutdown_seconds: parse_u32_le(response, layout.auto_shutdown_byte),
+    }
+}
+
+/// Read until at least `min_len` bytes have arrived, the peer closes the
+/// connection, or `wait` elapses. TCP may deliver a response in several
+/// chunks, so a single read is not enough. Once `min_len` is reached, any
+/// bytes already buffered are drained too, so longer responses are returned
+/// whole rather than cut at a chunk boundary. Returns whatever was received,
+/// at most `MAX_RESPONSE_LEN` bytes.
+async fn read_at_least(
+    stream: &mut TcpStream,
+    min_len: usize,
+    wait: Duration,
+) -> Result<Vec<u8>, SwitcherError> {
+    let deadline = Instant::now() + wait;
+    let mut response = Vec::new();
+    let mut buf = [0; READ_CHUNK_SIZE];
+
+    while response.len() < min_len && response.len() < MAX_RESPONSE_LEN {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match timeout(remaining, stream.read(&mut buf)).await {
+            Ok(Ok(0)) => break,
+            Ok(Ok(len)) => {
+                debug!("Read {} bytes ({} total)", len, response.len() + len);
+                response.extend_from_slice(&buf[..len]);
+            }
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => {
+                debug!(
+                    "Timed out waiting for {} bytes, got {}",
+                    min_len,
+                    response.len()
+                );
+                break;
+            }
+        }
+    }
+
+    while response.len() < MAX_RESPONSE_LEN {
+        match stream.try_read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => {
+                debug!(
+                    "Drained {} extra bytes ({} total)",
+                    len,
+                    response.len() + len
+                );
+                response.extend_from_slice(&buf[..len]);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if response.len() > MAX_RESPONSE_LEN {
+        warn!("Response exceeded {} bytes, truncating", MAX_RESPONSE_LEN);
+        response.truncate(MAX_RESPONSE_LEN);
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Start a fake device that answers each request with the given chunks
+    async fn spawn_mock_device(replies: Vec<Vec<Vec<u8>>>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            for chunks in replies {
+                let _ = socket.read(&mut buf).await.unwrap();
+                for chunk in chunks {
+                    socket.write_all(&chunk).await.unwrap();
+                    socket.flush().await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+            }
+        });
+
+        port
+    }
+
+    fn mock_controller(port: u16) -> SwitcherController {
+        SwitcherController::with_config(
+            "127.0.0.1".to_string(),
+            "9c4f22".to_string(),
+            ControllerConfig {
+                port,
+                ..ControllerConfig::default()
+            },
+        )
+    }
+
+    fn login_response() -> Vec<u8> {
+        let mut response = vec![0; MIN_LOGIN_RESPONSE_LEN];
+        response[16..20].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        response
+    }
+
+    /// Reply to a control or settings packet, shorter than a status reply
+    fn command_response() -> Vec<u8> {
+        vec![0; MIN_COMMAND_RESPONSE_LEN]
+    }
+
+    #[tokio::test]
+    async fn test_login_assembles_split_response() {
+        let response = login_response();
+        let port =
+            spawn_mock_device(vec![vec![response[..10].to_vec(), response[10..].to_vec()]]).await;
+
+        let controller = mock_controller(port);
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let (_, session_id) = controller.login(&mut stream).await.unwrap();
+
+        assert_eq!(session_id, "deadbeef");
+    }
+
+    #[tokio::test]
+    async fn test_short_login_response_is_login_failed() {
+        let port = spawn_mock_device(vec![vec![login_response()[..10].to_vec()]]).await;
+
+        let err = mock_controller(port).check_login().await.unwrap_err();
+
+        assert!(matches!(err, SwitcherError::LoginFailed(_)));
+        assert_eq!(err.kind(), "login_failed");
+        assert_eq!(err.exit_code(), crate::error::EXIT_UNREACHABLE);
+    }
+
+    #[tokio::test]
+    async fn test_get_status_assembles_split_response() {
+        let layout = &POWER_PLUG_LAYOUT;
+        let mut status = [0; 120];
+        status[layout.state_byte] = 0x01;
+        status[layout.power_byte..layout.power_byte + 2].copy_from_slice(&1500u16.to_le_bytes());
+
+        let login = login_response();
+        let port = spawn_mock_device(vec![
+            vec![login[..5].to_vec(), login[5..].to_vec()],
+            vec![status[..60].to_vec(), status[60..].to_vec()],
+        ])
+        .await;
+
+        let status = mock_controller(port).get_status().await.unwrap();
+
+        assert_eq!(status.state, DeviceState::On);
+        assert_eq!(status.power_consumption, 1500);
+    }
+
+    #[tokio::test]
+    async fn test_session_reuses_login() {
+        let layout = &POWER_PLUG_LAYOUT;
+        let off = [0; 120];
+        let mut on = [0; 120];
+        on[layout.state_byte] = 0x01;
+
+        // The mock accepts a single connection and answers one login
+        let port = spawn_mock_device(vec![
+            vec![login_response()],
+            vec![off.to_vec()],
+            vec![command_response()],
+            vec![on.to_vec()],
+        ])
+        .await;
+
+        let mut session = mock_controller(port).open_session().await.unwrap();
+        assert_eq!(session.session_id(), "deadbeef");
+        assert_eq!(session.get_status().await.unwrap().state, DeviceState::Off);
+        session.turn_on().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_switch_consumes_control_replies() {
+        let layout = &POWER_PLUG_LAYOUT;
+        let mut on = [0; 120];
+        on[layout.state_byte] = 0x01;
+
+        // Each control packet is answered before the status query, and only
+        // one status reply is sent: control replies left unread would shift
+        // the status bytes, read as OFF, and the retry would find nothing
+        let port = spawn_mock_device(vec![
+            vec![login_response()],
+            vec![command_response()],
+            vec![command_response()],
+            vec![on.to_vec()],
+        ])
+        .await;
+        let controller = SwitcherController::with_config(
+            "127.0.0.1".to_string(),
+            "9c4f22".to_string(),
+            ControllerConfig {
+                port,
+                repeat: 2,
+                ..ControllerConfig::default()
+            },
+        );
+
+        controller.turn_on().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_details_then_off_on_one_session() {
+        let layout = &POWER_PLUG_LAYOUT;
+        let mut on = [0; 120];
+        on[layout.state_byte] = 0x01; // no timer running
+        let off = [0; 120];
+
+        // The mock accepts a single connection and answers one login
+        let port = spawn_mock_device(vec![
+            vec![login_response()],
+            vec![on.to_vec()],
+            vec![command_response()],
+            vec![off.to_vec()],
+        ])
+        .await;
+
+        let mut session = mock_controller(port).open_session().await.unwrap();
+        let details = session.get_details().await.unwrap();
+        assert_eq!(details.state, DeviceState::On);
+        assert_eq!(details.remaining_seconds, Some(0));
+        session.turn_off().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_precheck_skips_redundant_command() {
+        let layout = &POWER_PLUG_LAYOUT;
+        let mut on = [0; 120];
+        on[layout.state_byte] = 0x01;
+
+        // Only the login and the pre-check are answered; a control packet
+        // would go unanswered and fail verification
+        let port = spawn_mock_device(vec![vec![login_response()], vec![on.to_vec()]]).await;
+
+        let controller = SwitcherController::with_config(
+            "127.0.0.1".to_string(),
+            "9c4f22".to_string(),
+            ControllerConfig {
+                port,
+                precheck_state: true,
+                ..ControllerConfig::default()
+            },
+        );
+        controller.turn_on().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_turn_on_and_wait_for_power() {
+        let layout = &POWER_PLUG_LAYOUT;
+        let mut spooling = [0; 120];
+        spooling[layout.state_byte] = 0x01;
+        let mut running = spooling;
+        running[layout.power_byte..layout.power_byte + 2].copy_from_slice(&1800u16.to_le_bytes());
+
+        let port = spawn_mock_device(vec![
+            vec![login_response()],
+            vec![command_response()],
+            vec![spooling.to_vec()], // verification
+            vec![spooling.to_vec()],
+            vec![running.to_vec()],
+        ])
+        .await;
+
+        let status = mock_controller(port)
+            .turn_on_and_wait_for_power(1000, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(status.power_consumption, 1800);
+    }
+
+    #[tokio::test]
+    async fn test_query_state_reads_past_chunk_size() {
+        let layout = &POWER_PLUG_LAYOUT;
+        let mut status = vec![0; READ_CHUNK_SIZE * 3];
+        status[layout.state_byte] = 0x01;
+        *status.last_mut().unwrap() = 0xff;
+
+        let port = spawn_mock_device(vec![vec![login_response()], vec![status.clone()]]).await;
+
+        let mut session = mock_controller(port).open_session().await.unwrap();
+        let response = session.query_state(layout.power_byte + 2).await.unwrap();
+
+        assert_eq!(response, status);
+    }
+
+    #[test]
+    fn test_with_config_keeps_at_least_one_send() {
+        let controller = SwitcherController::with_config(
+            "10.0.0.24".to_string(),
+            "9c4f22".to_string(),
+            ControllerConfig {
+                repeat: 0,
+                proxy: Some("127.0.0.1:1080".to_string()),
+                ..ControllerConfig::default()
+            },
+        );
+        assert_eq!(controller.config.repeat, 1);
+        assert_eq!(controller.port(), SWITCHER_CONTROL_PORT);
+        assert_eq!(controller.config.proxy.as_deref(), Some("127.0.0.1:1080"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_from_local_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let local_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let mut controller = mock_controller(port);
+        controller.config.local_port = Some(local_port);
+        let (_stream, (_, peer)) =
+            tokio::join!(async { controller.connect().await.unwrap() }, async {
+                listener.accept().await.unwrap()
+            });
+
+        assert_eq!(peer.port(), local_port);
+    }
+
+    #[tokio::test]
+    async fn test_connect_waits_for_wake() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut controller = mock_controller(port);
+        assert!(controller.connect().await.is_err());
+
+        // The "device" only starts listening after the first attempt
+        let device = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+            listener.accept().await.unwrap();
+        });
+        controller.config.wake_window = Some(Duration::from_secs(5));
+        assert!(controller.connect().await.is_ok());
+        device.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resolve_host() {
+        let literal = resolve_host("192.168.1.5:9957").await.unwrap();
+        assert_eq!(literal, "192.168.1.5:9957".parse().unwrap());
+
+        let localhost = resolve_host("localhost:9957").await.unwrap();
+        assert!(localhost.ip().is_loopback());
+        assert!(RESOLVED_HOSTS
+            .get()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .contains_key("localhost:9957"));
+
+        assert!(matches!(
+            resolve_host("no-such-plug.invalid:9957").await,
+            Err(SwitcherError::HostNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_device_name_rejects_nul() {
+        let controller = mock_controller(0);
+        let name_error = |name: &str| {
+            let err = controller
+                .string_to_hexadecimal_device_name(name)
+                .unwrap_err();
+            format!("{:?}", err)
+        };
+        assert_eq!(name_error("Office\0Plug"), "NameContainsNul");
+        assert_eq!(name_error("A"), "NameTooShort(1)");
+        assert_eq!(name_error(&"A".repeat(40)), "NameTooLong(40)");
+
+        let hex_name = controller
+            .string_to_hexadecimal_device_name("Office")
+            .unwrap();
+        assert_eq!(hex_name.len(), 64);
+        assert!(hex_name.starts_with(&hex::encode("Office")));
+    }
+
+    #[test]
+    fn test_parse_proxy_url() {
+        assert_eq!(
+            parse_proxy_url("socks5://127.0.0.1:1080"),
+            Ok("127.0.0.1:1080".to_string())
+        );
+        assert_eq!(
+            parse_proxy_url("socks5://jump.example.com:9050"),
+            Ok("jump.example.com:9050".to_string())
+        );
+        assert!(parse_proxy_url("127.0.0.1:1080").is_err());
+        assert!(parse_proxy_url("http://127.0.0.1:1080").is_err());
+        assert!(parse_proxy_url("socks5://127.0.0.1").is_err());
+        assert!(parse_proxy_url("socks5://:1080").is_err());
+    }
+
+    #[test]
+    fn test_parse_details() {
+        let layout = &POWER_PLUG_LAYOUT;
+        let mut response = [0; 120];
+        response[layout.name_range.clone()][..6].copy_from_slice(b"Heater");
+        response[layout.state_byte] = 0x01;
+        response[layout.remaining_time_byte..layout.remaining_time_byte + 4]
+            .copy_from_slice(&600u32.to_le_bytes());
+        response[layout.auto_shutdown_byte..layout.auto_shutdown_byte + 4]
+            .copy_from_slice(&7200u32.to_le_bytes());
+
+        let details = parse_details(layout, "9c4f22", &response);
+
+        assert_eq!(details.name.as_deref(), Some("Heater"));
+        assert_eq!(details.state, DeviceState::On);
+        assert_eq!(details.remaining_seconds, Some(600));
+        assert_eq!(details.auto_shutdown_seconds, Some(7200));
+    }
+
+    #[test]
+    fn test_auto_shutdown_range() {
+        assert_eq!(parse_auto_shutdown("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(
+            parse_auto_shutdown("23h59m"),
+            Ok(Duration::from_secs(86340))
+        );
+        assert!(parse_auto_shutdown("59m").is_err());
+        assert!(parse_auto_shutdown("24h").is_err());
+
+        let controller = mock_controller(0);
+        let packet = controller.build_auto_shutdown_packet("deadbeef", "00000000", 7200);
+        assert_eq!(signed_len(&controller, &packet), declared_len(&packet));
+    }
+
+    /// Byte 2 of the header, the packet length the device expects
+    fn declared_len(packet: &str) -> usize {
+        hex::decode(packet).unwrap()[2] as usize
+    }
+
+    fn signed_len(controller: &SwitcherController, packet: &str) -> usize {
+        hex::decode(controller.sign_packet(packet)).unwrap().len()
+    }
+
+    #[test]
+    fn test_packet_lengths_match_header() {
+        let controller = mock_controller(0);
+        let packets = [
+            controller.build_login_packet("00000000"),
+            controller.build_control_packet("deadbeef", "00000000", "1", 1800),
+            controller.build_get_state_packet("deadbeef", "00000000"),
+            controller.build_auto_shutdown_packet("deadbeef", "00000000", 7200),
+            controller
+                .build_set_name_packet("deadbeef", "00000000", "Boiler")
+                .unwrap(),
+        ];
+        for packet in &packets {
+            assert_eq!(
+                signed_len(&controller, packet),
+                declared_len(packet),
+                "{}",
+                packet
+            );
+        }
+    }
+
+    #[test]
+    fn test_auto_off_range() {
+        assert_eq!(parse_auto_off("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_auto_off("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_auto_off("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_auto_off("24h"), Ok(Duration::from_secs(86400)));
+        assert!(parse_auto_off("59").is_err());
+        assert!(parse_auto_off("24h1m").is_err());
+
+        let controller = mock_controller(0);
+        let packet = controller.build_control_packet("deadbeef", "00000000", "1", 1800);
+        assert!(packet.ends_with("010600010008070000"));
+        let packet = controller.build_control_packet("deadbeef", "00000000", "1", 0);
+        assert!(packet.ends_with("010600010000000000"));
+    }
+
+    #[tokio::test]
+    async fn test_set_auto_shutdown() {
+        let port = spawn_mock_device(vec![vec![login_response()], vec![command_response()]]).await;
+        let controller = mock_controller(port);
+
+        controller
+            .set_auto_shutdown(Duration::from_secs(5400))
+            .await
+            .unwrap();
+        // Out of range values fail without connecting
+        let err = controller
+            .set_auto_shutdown(Duration::from_secs(60))
+            .await
+            .unwrap_err();
+        assert_eq!(err.exit_code(), crate::error::EXIT_USAGE);
+    }
+
+    #[test]
+    fn test_parse_details_short_response() {
+        let details = parse_details(&POWER_PLUG_LAYOUT, "9c4f22", &[0; 80]);
+
+        assert_eq!(details.name, None);
+        assert_eq!(details.remaining_seconds, None);
+        assert_eq!(details.auto_shutdown_seconds, None);
+    }
+}
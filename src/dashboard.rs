@@ -0,0 +1,326 @@
+use crate::control::{ControllerConfig, SwitcherController};
+use crate::device::{DeviceState, DeviceStatus, SwitcherDevice};
+use crate::pairing::{LabelColor, PairingManager};
+use crate::utils::{current_timestamp, format_timestamp};
+use log::{debug, warn};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::time::{interval, Duration};
+
+/// One paired device as shown in the dashboard table
+struct DeviceRow {
+    alias: String,
+    label_color: Option<LabelColor>,
+    device: SwitcherDevice,
+    controller: SwitcherController,
+    status: Option<DeviceStatus>,
+    last_seen: Option<u64>, // last successful poll
+    error: Option<String>,
+    busy: bool, // a toggle is in flight
+}
+
+impl DeviceRow {
+    fn state_label(&self) -> String {
+        match (&self.status, self.busy) {
+            (_, true) => "…".to_string(),
+            (Some(status), false) => status.state.to_string(),
+            (None, false) => "?".to_string(),
+        }
+    }
+
+    fn power_label(&self) -> String {
+        self.status
+            .as_ref()
+            .map(|status| format!("{}W", status.power_consumption))
+            .unwrap_or_default()
+    }
+}
+
+enum Update {
+    Status(usize, Result<DeviceStatus, String>),
+    Toggled(usize, Result<DeviceState, String>),
+    Key(KeyCode),
+    Redraw,
+}
+
+/// Show a live table of all paired devices, polling each one every
+/// `poll_interval`. Space/Enter toggles the selected device, `r` refreshes
+/// and `q` quits. Devices are reached through `proxy` (SOCKS5 `host:port`)
+/// when given.
+pub async fn run(
+    poll_interval: Duration,
+    controller_config: ControllerConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pairing = PairingManager::new()?.load_pairing()?;
+    let mut paired_devices = pairing.get_paired_devices();
+    if paired_devices.is_empty() {
+        return Err("No paired devices found. Use 'pair' to add one first".into());
+    }
+    paired_devices.sort_by(|a, b| a.alias.cmp(&b.alias));
+
+    let mut rows: Vec<DeviceRow> = paired_devices
+        .into_iter()
+        .map(|paired| DeviceRow {
+            alias: paired.alias.clone(),
+            label_color: paired.label_color(),
+            device: SwitcherDevice::from(paired),
+            controller: SwitcherController::from_device_with_config(
+                paired.as_ref(),
+                controller_config.clone(),
+            ),
+            status: None,
+            last_seen: None,
+            error: None,
+            busy: false,
+        })
+        .collect();
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut rows, poll_interval).await;
+    ratatui::restore();
+    result
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    rows: &mut [DeviceRow],
+    poll_interval: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    // Terminal events block, so read them on their own thread
+    let key_tx = tx.clone();
+    std::thread::spawn(move || loop {
+        let update = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => Update::Key(key.code),
+            Ok(Event::Resize(_, _)) => Update::Redraw,
+            Ok(_) => continue,
+            Err(e) => {
+                warn!("Could not read terminal event: {}", e);
+                break;
+            }
+        };
+        if key_tx.send(update).is_err() {
+            break;
+        }
+    });
+
+    let controllers: Vec<SwitcherController> =
+        rows.iter().map(|row| row.controller.clone()).collect();
+    let poll_tx = tx.clone();
+    let poller = tokio::spawn(async move {
+        let mut ticker = interval(poll_interval);
+        loop {
+            ticker.tick().await;
+            poll_all(&controllers, &poll_tx);
+        }
+    });
+
+    let mut table_state = TableState::default().with_selected(Some(0));
+
+    loop {
+        terminal.draw(|frame| draw(frame, rows, &mut table_state))?;
+
+        let Some(update) = rx.recv().await else {
+            break;
+        };
+        match update {
+            Update::Status(index, result) => {
+                let row = &mut rows[index];
+                match result {
+                    Ok(status) => {
+                        row.status = Some(status);
+                        row.last_seen = Some(current_timestamp());
+                        row.error = None;
+                    }
+                    Err(e) => row.error = Some(e),
+                }
+            }
+            Update::Toggled(index, result) => {
+                let row = &mut rows[index];
+                row.busy = false;
+                match result {
+                    Ok(state) => {
+                        if let Some(status) = row.status.as_mut() {
+                            status.state = state;
+                        }
+                        row.error = None;
+                        spawn_status(index, &row.controller, &tx);
+                    }
+                    Err(e) => row.error = Some(e),
+                }
+            }
+            Update::Key(KeyCode::Char('q')) | Update::Key(KeyCode::Esc) => break,
+            Update::Key(KeyCode::Down) | Update::Key(KeyCode::Char('j')) => {
+                table_state.select(table_state.selected().map(|i| (i + 1) % rows.len()));
+            }
+            Update::Key(KeyCode::Up) | Update::Key(KeyCode::Char('k')) => {
+                table_state.select(
+                    table_state
+                        .selected()
+                        .map(|i| (i + rows.len() - 1) % rows.len()),
+                );
+            }
+            Update::Key(KeyCode::Char(' ')) | Update::Key(KeyCode::Enter) => {
+                if let Some(index) = table_state.selected() {
+                    toggle(index, &mut rows[index], &tx);
+                }
+            }
+            Update::Key(KeyCode::Char('r')) => {
+                let controllers: Vec<SwitcherController> =
+                    rows.iter().map(|row| row.controller.clone()).collect();
+                poll_all(&controllers, &tx);
+            }
+            Update::Key(_) | Update::Redraw => {}
+        }
+    }
+
+    poller.abort();
+    Ok(())
+}
+
+fn poll_all(controllers: &[SwitcherController], tx: &UnboundedSender<Update>) {
+    for (index, controller) in controllers.iter().enumerate() {
+        spawn_status(index, controller, tx);
+    }
+}
+
+fn spawn_status(index: usize, controller: &SwitcherController, tx: &UnboundedSender<Update>) {
+    let controller = controller.clone();
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let result = controller.get_status().await.map_err(|e| e.to_string());
+        let _ = tx.send(Update::Status(index, result));
+    });
+}
+
+/// Switch the device to the opposite of its last known state
+fn toggle(index: usize, row: &mut DeviceRow, tx: &UnboundedSender<Update>) {
+    if row.busy {
+        return;
+    }
+
+    let target = match row.status.as_ref().map(|status| status.state) {
+        Some(DeviceState::On) => DeviceState::Off,
+        _ => DeviceState::On,
+    };
+    debug!("Dashboard toggling '{}' to {}", row.alias, target);
+    row.busy = true;
+
+    let controller = row.controller.clone();
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let result = match target {
+            DeviceState::Off => controller.turn_off().await,
+            _ => controller.turn_on().await,
+        };
+        let _ = tx.send(Update::Toggled(
+            index,
+            result.map(|_| target).map_err(|e| e.to_string()),
+        ));
+    });
+}
+
+/// The alias cell's style: its label color, or the row's state color when unset
+fn label_style(label_color: Option<LabelColor>) -> Style {
+    let Some(label_color) = label_color else {
+        return Style::default();
+    };
+    Style::default().fg(match label_color {
+        LabelColor::Red => Color::Red,
+        LabelColor::Green => Color::Green,
+        LabelColor::Yellow => Color::Yellow,
+        LabelColor::Blue => Color::Blue,
+        LabelColor::Magenta => Color::Magenta,
+        LabelColor::Cyan => Color::Cyan,
+    })
+}
+
+fn draw(frame: &mut Frame, rows: &[DeviceRow], table_state: &mut TableState) {
+    let [table_area, help_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+    let header = Row::new(["Alias", "IP", "State", "Power", "Last seen", "Error"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let table_rows = rows.iter().map(|row| {
+        let color = match row.status.as_ref().map(|status| status.state) {
+            Some(DeviceState::On) => Color::Green,
+            Some(_) => Color::Reset,
+            None => Color::DarkGray,
+        };
+        Row::new([
+            Cell::from(row.alias.clone()).style(label_style(row.label_color)),
+            Cell::from(row.device.ip_address.clone()),
+            Cell::from(row.state_label()),
+            Cell::from(row.power_label()),
+            Cell::from(row.last_seen.map(format_timestamp).unwrap_or_default()),
+            Cell::from(row.error.clone().unwrap_or_default()),
+        ])
+        .style(Style::default().fg(color))
+    });
+    let widths = [
+        Constraint::Length(20),
+        Constraint::Length(16),
+        Constraint::Length(8),
+        Constraint::Length(8),
+        Constraint::Length(16),
+        Constraint::Min(10),
+    ];
+
+    let table = Table::new(table_rows, widths)
+        .header(header)
+        .block(Block::bordered().title(" Switcher devices "))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(table, table_area, table_state);
+
+    frame.render_widget(
+        Paragraph::new(" ↑/↓ select   space toggle   r refresh   q quit"),
+        help_area,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_labels() {
+        let device = SwitcherDevice {
+            device_id: "123".to_string(),
+            name: "Office".to_string(),
+            ip_address: "192.168.1.100".to_string(),
+            mac_address: "00:11:22:33:44:55".to_string(),
+            device_key: Some("a1".to_string()),
+            device_type: "Switcher Power Plug".to_string(),
+            device_type_code: "01a8".to_string(),
+            state: DeviceState::Off,
+            power_consumption: 0,
+        };
+        let mut row = DeviceRow {
+            alias: "Office".to_string(),
+            label_color: None,
+            controller: SwitcherController::from_device(&device),
+            device,
+            status: None,
+            last_seen: None,
+            error: None,
+            busy: false,
+        };
+        assert_eq!(row.state_label(), "?");
+        assert_eq!(row.power_label(), "");
+
+        row.status = Some(DeviceStatus {
+            state: DeviceState::On,
+            power_consumption: 12,
+        });
+        assert_eq!(row.state_label(), "on");
+        assert_eq!(row.power_label(), "12W");
+
+        row.busy = true;
+        assert_eq!(row.state_label(), "…");
+    }
+}
@@ -1,117 +1,385 @@
+use crate::handler::{DeviceTypeRegistry, PacketRejection};
 use serde::{Deserialize, Serialize};
 
+/// A discovered Switcher device.
+///
+/// The serialized field names are a stable API used by the config file and
+/// JSON output; they are pinned with `rename` so Rust-side renames don't
+/// change them. JSON output adds a derived `state_code` (see
+/// `DeviceState::as_code`) that the config file doesn't store.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SwitcherDevice {
+    #[serde(rename = "device_id")]
     pub device_id: String,
-    pub device_key: String,
+    /// Hex of the key byte from discovery, None when the device broadcast
+    /// the no-key sentinel (see `handler::NO_DEVICE_KEY`)
+    #[serde(rename = "device_key", default)]
+    pub device_key: Option<String>,
+    #[serde(rename = "ip_address")]
     pub ip_address: String,
+    #[serde(rename = "mac_address")]
     pub mac_address: String,
+    #[serde(rename = "name")]
     pub name: String,
+    #[serde(rename = "device_type")]
     pub device_type: String,
+    #[serde(rename = "device_type_code", default)]
+    pub device_type_code: String, // raw hex type code from discovery, e.g. "01a8"
+    #[serde(rename = "state")]
     pub state: DeviceState,
+    #[serde(rename = "power_consumption")]
     pub power_consumption: u16,
 }
 
+/// One-line summary for logs and quick listings: `name (ip) [id] state power`
+impl std::fmt::Display for SwitcherDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}) [{}] {} {}W",
+            self.name, self.ip_address, self.device_id, self.state, self.power_consumption
+        )
+    }
+}
+
+/// Serialized lowercase like its `Display`; config files written before that
+/// used `On`/`Off`/`Unknown`, which still load.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
 pub enum DeviceState {
+    #[serde(alias = "On")]
     On,
+    #[serde(alias = "Off")]
     Off,
+    #[serde(alias = "Unknown")]
     Unknown,
 }
 
+impl DeviceState {
+    /// Numeric form for integrations that want a number rather than a
+    /// string: Off = 0, On = 1, Unknown = 255. Written as `state_code` in
+    /// JSON output.
+    pub const fn as_code(self) -> u8 {
+        match self {
+            DeviceState::Off => 0,
+            DeviceState::On => 1,
+            DeviceState::Unknown => 255,
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceState::On => write!(f, "on"),
+            DeviceState::Off => write!(f, "off"),
+            DeviceState::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Live state and power reading. It serializes through a helper whose
+/// renames pin the field names and which adds `state_code`.
 #[derive(Debug, Clone)]
 pub struct DeviceStatus {
     pub state: DeviceState,
     pub power_consumption: u16,
 }
 
-impl SwitcherDevice {
-    pub fn from_discovery_packet(data: &[u8]) -> Option<Self> {
-        if data.len() != 165 || &data[0..2] != &[0xfe, 0xf0] {
-            return None;
+impl Serialize for DeviceStatus {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct DeviceStatusJson {
+            #[serde(rename = "state")]
+            state: DeviceState,
+            #[serde(rename = "state_code")]
+            state_code: u8,
+            #[serde(rename = "power_consumption")]
+            power_consumption: u16,
         }
 
-        let hex_data = hex::encode(data);
+        DeviceStatusJson {
+            state: self.state,
+            state_code: self.state.as_code(),
+            power_consumption: self.power_consumption,
+        }
+        .serialize(serializer)
+    }
+}
 
-        let device_id = hex::encode(&data[18..21]);
-        let device_key = hex::encode(&data[40..41]);
+/// Extended device information from the state query response
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceDetails {
+    pub device_id: String,
+    pub name: Option<String>,
+    pub state: DeviceState,
+    pub power_consumption: u16,
+    pub remaining_seconds: Option<u32>, // time left on a running timer
+    pub on_duration_seconds: Option<u32>, // how long the device has been on
+    pub auto_shutdown_seconds: Option<u32>, // configured auto-shutdown duration
+}
 
-        let name_bytes = &data[42..74];
-        let name_end = name_bytes
-            .iter()
-            .position(|&b| b == 0)
-            .unwrap_or(name_bytes.len());
-        let name = String::from_utf8_lossy(&name_bytes[..name_end]).to_string();
+/// Device type code of the only model this tool currently supports
+pub const POWER_PLUG_TYPE_CODE: &str = "01a8";
 
-        let device_type_hex = hex::encode(&data[74..76]);
-        // Only accept Power Plug devices (01a8)
-        if device_type_hex != "01a8" {
-            return None;
+/// Switcher models this tool can talk to, identified by the type code in
+/// their discovery packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SwitcherDeviceType {
+    #[default]
+    PowerPlug,
+}
+
+impl SwitcherDeviceType {
+    pub fn from_type_code(type_code: &str) -> Option<Self> {
+        match type_code {
+            POWER_PLUG_TYPE_CODE => Some(Self::PowerPlug),
+            _ => None,
         }
-        let device_type = "Switcher Power Plug".to_string();
+    }
 
-        // IP address from hex positions 152:160 (aioswitcher protocol)
-        if hex_data.len() < 160 {
-            return None;
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Self::PowerPlug => "Switcher Power Plug",
         }
-        let hex_ip = &hex_data[152..160];
-        let ip_addr = u32::from_str_radix(
-            &format!(
-                "{}{}{}{}",
-                &hex_ip[6..8],
-                &hex_ip[4..6],
-                &hex_ip[2..4],
-                &hex_ip[0..2]
-            ),
-            16,
-        )
-        .ok()?;
-        let ip_address = format!(
-            "{}.{}.{}.{}",
-            ip_addr & 0xFF,
-            (ip_addr >> 8) & 0xFF,
-            (ip_addr >> 16) & 0xFF,
-            (ip_addr >> 24) & 0xFF
+    }
+}
+
+impl SwitcherDevice {
+    /// Type code of a Switcher-framed discovery packet from a model this tool
+    /// doesn't support, so it can be reported instead of silently dropped
+    #[allow(dead_code)] // library API, discovery uses its own registry
+    pub fn unsupported_type_code(data: &[u8]) -> Option<String> {
+        DeviceTypeRegistry::default().unsupported_type_code(data)
+    }
+
+    /// Parse a discovery broadcast with the built-in device type handlers,
+    /// defaulting fields that don't parse cleanly (an unknown state byte
+    /// reads as Off)
+    #[allow(dead_code)] // library API, discovery uses its own registry
+    pub fn from_discovery_packet(data: &[u8]) -> Option<Self> {
+        DeviceTypeRegistry::default().parse(data, false)
+    }
+
+    /// Like `from_discovery_packet`, but say why a packet was dropped
+    #[allow(dead_code)] // library API, discovery uses its own registry
+    pub fn try_from_discovery_packet(data: &[u8]) -> Result<Self, PacketRejection> {
+        DeviceTypeRegistry::default().try_parse(data, false)
+    }
+
+    /// Like `from_discovery_packet`, but reject the packet instead of
+    /// defaulting any field, so protocol drift shows up as a missing device
+    /// rather than made-up values
+    #[allow(dead_code)] // library API, discovery uses its own registry
+    pub fn from_discovery_packet_strict(data: &[u8]) -> Option<Self> {
+        DeviceTypeRegistry::default().parse(data, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unsupported_type_code() {
+        let mut packet = vec![0; 159];
+        packet[0..2].copy_from_slice(&[0xfe, 0xf0]);
+        packet[74..76].copy_from_slice(&[0x0e, 0x01]);
+        assert_eq!(
+            SwitcherDevice::unsupported_type_code(&packet),
+            Some("0e01".to_string())
         );
+        assert!(SwitcherDevice::from_discovery_packet(&packet).is_none());
+
+        packet[74..76].copy_from_slice(&[0x01, 0xa8]);
+        assert_eq!(SwitcherDevice::unsupported_type_code(&packet), None);
+
+        assert_eq!(SwitcherDevice::unsupported_type_code(&[0xde, 0xad]), None);
+    }
+
+    proptest::proptest! {
+        // Discovery packets are untrusted network input: no buffer may panic
+        #[test]
+        fn test_discovery_packet_never_panics(
+            data in proptest::collection::vec(proptest::num::u8::ANY, 0..400)
+        ) {
+            SwitcherDevice::from_discovery_packet(&data);
+            SwitcherDevice::from_discovery_packet_strict(&data);
+            SwitcherDevice::unsupported_type_code(&data);
+        }
+
+        // Random payloads behind a valid Power Plug header reach every field
+        // parser; whatever parses must be well-formed
+        #[test]
+        fn test_discovery_packet_fields_are_valid(
+            body in proptest::collection::vec(proptest::num::u8::ANY, 165)
+        ) {
+            let mut packet = body;
+            packet[0..2].copy_from_slice(&[0xfe, 0xf0]);
+            packet[74..76].copy_from_slice(&[0x01, 0xa8]);
 
-        // MAC address (hex positions 160:172 in hex representation)
-        if hex_data.len() < 278 {
-            return None;
+            let device = SwitcherDevice::from_discovery_packet(&packet);
+            let device = device.expect("lenient parsing defaults bad fields");
+            proptest::prop_assert_eq!(device.device_id.len(), 6);
+            proptest::prop_assert!(device.ip_address.parse::<std::net::Ipv4Addr>().is_ok());
+            proptest::prop_assert_eq!(device.mac_address.len(), 17);
+
+            // Strict parsing defaults nothing, so when it succeeds it agrees
+            if let Some(strict) = SwitcherDevice::from_discovery_packet_strict(&packet) {
+                proptest::prop_assert_eq!(strict.name, device.name);
+                proptest::prop_assert_eq!(strict.state, device.state);
+                proptest::prop_assert_eq!(strict.power_consumption, device.power_consumption);
+            }
         }
-        let hex_mac = &hex_data[160..172];
-        let mac_address = format!(
-            "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
-            u8::from_str_radix(&hex_mac[0..2], 16).ok()?,
-            u8::from_str_radix(&hex_mac[2..4], 16).ok()?,
-            u8::from_str_radix(&hex_mac[4..6], 16).ok()?,
-            u8::from_str_radix(&hex_mac[6..8], 16).ok()?,
-            u8::from_str_radix(&hex_mac[8..10], 16).ok()?,
-            u8::from_str_radix(&hex_mac[10..12], 16).ok()?
+    }
+
+    #[test]
+    fn test_display() {
+        let device = SwitcherDevice {
+            device_id: "9c4f22".to_string(),
+            device_key: Some("a1".to_string()),
+            ip_address: "192.168.1.100".to_string(),
+            mac_address: "00:11:22:33:44:55".to_string(),
+            name: "Office".to_string(),
+            device_type: "Switcher Power Plug".to_string(),
+            device_type_code: "01a8".to_string(),
+            state: DeviceState::On,
+            power_consumption: 1200,
+        };
+        assert_eq!(
+            device.to_string(),
+            "Office (192.168.1.100) [9c4f22] on 1200W"
+        );
+    }
+
+    #[test]
+    fn test_strict_discovery_parsing() {
+        let mut packet = vec![0; 165];
+        packet[0..2].copy_from_slice(&[0xfe, 0xf0]);
+        packet[42..46].copy_from_slice(b"Plug");
+        packet[74..76].copy_from_slice(&[0x01, 0xa8]);
+        packet[133] = 0x01; // state
+        assert_eq!(
+            SwitcherDevice::from_discovery_packet_strict(&packet)
+                .unwrap()
+                .state,
+            DeviceState::On
+        );
+
+        // Unknown state byte: lenient parsing reports Off, strict rejects
+        packet[133] = 0x07;
+        assert_eq!(
+            SwitcherDevice::from_discovery_packet(&packet)
+                .unwrap()
+                .state,
+            DeviceState::Off
         );
+        assert!(SwitcherDevice::from_discovery_packet_strict(&packet).is_none());
 
-        // Device state (hex positions 266:268 in hex representation)
-        let hex_device_state = &hex_data[266..268];
-        let state = match hex_device_state {
-            "01" => DeviceState::On,
-            "00" => DeviceState::Off,
-            _ => DeviceState::Off, // Default to Off for unknown states
+        // Invalid UTF-8 in the name
+        packet[133] = 0x00;
+        packet[42] = 0xff;
+        assert_eq!(
+            SwitcherDevice::from_discovery_packet(&packet).unwrap().name,
+            "\u{fffd}lug"
+        );
+        assert!(SwitcherDevice::from_discovery_packet_strict(&packet).is_none());
+    }
+
+    #[test]
+    fn test_device_type_from_code() {
+        assert_eq!(
+            SwitcherDeviceType::from_type_code("01a8"),
+            Some(SwitcherDeviceType::PowerPlug)
+        );
+        assert_eq!(SwitcherDeviceType::from_type_code("0e01"), None);
+        assert_eq!(
+            SwitcherDeviceType::PowerPlug.display_name(),
+            "Switcher Power Plug"
+        );
+    }
+
+    #[test]
+    fn test_stable_json_field_names() {
+        let device = SwitcherDevice {
+            device_id: "123".to_string(),
+            device_key: Some("a1".to_string()),
+            ip_address: "192.168.1.100".to_string(),
+            mac_address: "00:11:22:33:44:55".to_string(),
+            name: "Office".to_string(),
+            device_type: "Switcher Power Plug".to_string(),
+            device_type_code: "01a8".to_string(),
+            state: DeviceState::On,
+            power_consumption: 12,
+        };
+        let json = serde_json::to_value(&device).unwrap();
+        let mut keys: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            [
+                "device_id",
+                "device_key",
+                "device_type",
+                "device_type_code",
+                "ip_address",
+                "mac_address",
+                "name",
+                "power_consumption",
+                "state"
+            ]
+        );
+
+        let status = DeviceStatus {
+            state: DeviceState::Off,
+            power_consumption: 0,
+        };
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            serde_json::json!({"state": "off", "state_code": 0, "power_consumption": 0})
+        );
+        assert_eq!(DeviceState::On.as_code(), 1);
+        assert_eq!(DeviceState::Unknown.as_code(), 255);
+    }
+
+    #[test]
+    fn test_json_round_trips_every_field() {
+        // Every field non-default, so one the JSON drops would come back different
+        let device = SwitcherDevice {
+            device_id: "9c4f22".to_string(),
+            device_key: Some("a1".to_string()),
+            ip_address: "192.168.1.100".to_string(),
+            mac_address: "00:11:22:33:44:55".to_string(),
+            name: "Office".to_string(),
+            device_type: "Switcher Power Plug".to_string(),
+            device_type_code: "01a8".to_string(),
+            state: DeviceState::On,
+            power_consumption: 1200,
         };
 
-        // Power consumption (hex positions 270:278 in hex representation)
-        let hex_power = &hex_data[270..278];
-        let power_consumption =
-            u16::from_str_radix(&format!("{}{}", &hex_power[2..4], &hex_power[0..2]), 16)
-                .unwrap_or(0);
-
-        Some(SwitcherDevice {
-            device_id,
-            device_key,
-            ip_address,
-            mac_address,
-            name,
-            device_type,
-            state,
-            power_consumption,
-        })
+        let json = serde_json::to_value(&device).unwrap();
+        let read_back: SwitcherDevice = serde_json::from_value(json).unwrap();
+
+        assert_eq!(format!("{:?}", read_back), format!("{:?}", device));
+    }
+
+    #[test]
+    fn test_state_reads_old_spelling() {
+        for (json, state) in [
+            ("\"on\"", DeviceState::On),
+            ("\"On\"", DeviceState::On),
+            ("\"Off\"", DeviceState::Off),
+            ("\"Unknown\"", DeviceState::Unknown),
+        ] {
+            assert_eq!(serde_json::from_str::<DeviceState>(json).unwrap(), state);
+        }
+        assert_eq!(serde_json::to_string(&DeviceState::On).unwrap(), "\"on\"");
     }
 }
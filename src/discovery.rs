@@ -1,62 +1,334 @@
-use crate::cache::CacheManager;
+use crate::cache::DeviceCache;
+use crate::config::ConfigManager;
 use crate::device::SwitcherDevice;
-use crate::pairing::PairingManager;
+use crate::error::SwitcherError;
+use crate::handler::{DeviceTypeRegistry, PacketRejection, PowerPlugHandler};
+use crate::profile::ProtocolProfile;
 use log::{debug, error, info, warn};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::net::Ipv4Addr;
 use std::sync::{Arc, Mutex};
 use tokio::net::UdpSocket;
-use tokio::time::{sleep, Duration};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration, Instant};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+
+/// UDP port devices broadcast their discovery packets on
+pub const SWITCHER_DISCOVERY_PORT: u16 = 10002;
+
+/// Probe datagram for active discovery: an empty Switcher-framed packet.
+/// It isn't a documented Switcher request and devices may ignore it, so it
+/// is only sent when a broadcast address is given explicitly; devices are
+/// still picked up from their periodic broadcasts.
+const DISCOVERY_PROBE: [u8; 4] = [0xfe, 0xf0, 0x00, 0x00];
+
+/// Where an active probe goes unless a broadcast address is given. Unlike a
+/// directed subnet broadcast the limited broadcast is never routed, so
+/// routers that filter directed broadcasts still pass it on the local segment.
+const DEFAULT_PROBE_ADDRESS: Ipv4Addr = Ipv4Addr::BROADCAST;
+
+/// Broadcasts gathered by a settling scan: a device's first broadcast opens a
+/// window of `settle`, and later broadcasts inside that window replace it
+struct SettledDevices {
+    duration: Duration, // devices first heard after this are left out
+    settle: Duration,
+    devices: Vec<(SwitcherDevice, Duration)>, // latest broadcast, first heard at
+}
+
+impl SettledDevices {
+    fn new(duration: Duration, settle: Duration) -> Self {
+        Self {
+            duration,
+            settle,
+            devices: Vec::new(),
+        }
+    }
+
+    /// Record a broadcast heard `at` after the scan started
+    fn add(&mut self, device: SwitcherDevice, at: Duration) {
+        let known = self
+            .devices
+            .iter_mut()
+            .find(|(known, _)| known.device_id == device.device_id);
+        match known {
+            Some((known, first_at)) if at <= *first_at + self.settle => {
+                if known.state != device.state {
+                    debug!(
+                        "Device {} settled from {} to {}",
+                        device.device_id, known.state, device.state
+                    );
+                }
+                *known = device;
+            }
+            None if self.settle.is_zero() || at < self.duration => {
+                self.devices.push((device, at));
+            }
+            Some(_) | None => {}
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    fn into_devices(self) -> Vec<SwitcherDevice> {
+        self.devices.into_iter().map(|(device, _)| device).collect()
+    }
+}
+
+/// Which field identifies "the same device" when merging discovery results
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DedupeKey {
+    #[default]
+    DeviceId,
+    /// Normalized MAC address. A factory reset changes the device ID but
+    /// not the MAC, so this collapses a reset device's old and new entries.
+    Mac,
+}
+
+impl DedupeKey {
+    fn key(self, device: &SwitcherDevice) -> String {
+        match self {
+            DedupeKey::DeviceId => device.device_id.clone(),
+            DedupeKey::Mac => device
+                .mac_address
+                .chars()
+                .filter(char::is_ascii_hexdigit)
+                .collect::<String>()
+                .to_ascii_uppercase(),
+        }
+    }
+}
+
+/// Merge devices by `key`, later entries replacing earlier ones
+fn dedupe_devices(
+    devices: impl IntoIterator<Item = SwitcherDevice>,
+    key: DedupeKey,
+) -> Vec<SwitcherDevice> {
+    let mut device_map: HashMap<String, SwitcherDevice> = HashMap::new();
+    for device in devices {
+        device_map.insert(key.key(&device), device);
+    }
+    device_map.into_values().collect()
+}
+
+/// A Switcher device that answered discovery but isn't a supported model
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedDevice {
+    pub ip_address: String,
+    pub device_type_code: String,
+}
+
+/// Two devices announcing the same device_id during one scan, e.g. a cloned
+/// config or a firmware bug. Only the first one is reported as discovered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateDeviceId {
+    pub device_id: String,
+    pub first_ip: String,
+    pub first_mac: String,
+    pub other_ip: String,
+    pub other_mac: String,
+}
+
+/// A device found at a different IP than the one it was cached with
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MovedDevice {
+    pub device_id: String,
+    pub name: String,
+    pub old_ip: String,
+    pub new_ip: String,
+}
+
+/// Discovered devices whose IP differs from their cache entry
+fn find_moved(cache: &DeviceCache, discovered: &[SwitcherDevice]) -> Vec<MovedDevice> {
+    discovered
+        .iter()
+        .filter_map(|device| {
+            let cached = cache.get_device(&device.device_id)?;
+            (cached.ip_address != device.ip_address).then(|| MovedDevice {
+                device_id: device.device_id.clone(),
+                name: device.name.clone(),
+                old_ip: cached.ip_address.clone(),
+                new_ip: device.ip_address.clone(),
+            })
+        })
+        .collect()
+}
 
 pub struct SwitcherDiscovery {
-    cache_manager: Option<CacheManager>,
+    config_manager: Option<ConfigManager>,
     use_cache: bool,
-    cache_max_age: u64, // seconds
+    cache_max_age: u64,                  // seconds
+    broadcast_address: Option<Ipv4Addr>, // where to send active discovery probes, if anywhere
+    capture_raw: bool,
+    strict: bool,            // drop packets with fields that would need defaulting
+    every_broadcast: bool,   // stream repeat broadcasts, not just the first per device
+    save_on_interrupt: bool, // Ctrl-C ends a cached scan early, still saving results
+    settle: Duration,        // extra wait for a device's second broadcast
+    registry: Arc<DeviceTypeRegistry>, // parses packets by device type code
+    dedupe_key: DedupeKey,
+    write_cache: bool,    // save discovered devices back to the cache
+    update_pairing: bool, // refresh paired devices' IP/name/last_seen
+    raw_packets: Arc<Mutex<HashMap<String, String>>>, // device_id -> hex packet
+    unsupported: Arc<Mutex<Vec<UnsupportedDevice>>>,
+    duplicates: Arc<Mutex<Vec<DuplicateDeviceId>>>,
+    moved: Mutex<Vec<MovedDevice>>,
 }
 
 impl SwitcherDiscovery {
     pub fn new() -> Self {
-        Self {
-            cache_manager: CacheManager::new().ok(),
-            use_cache: true,
-            cache_max_age: 3600, // 1 hour default
-        }
+        Self::with_cache_settings(true, 3600) // 1 hour default
     }
 
     pub fn with_cache_settings(use_cache: bool, cache_max_age: u64) -> Self {
         Self {
-            cache_manager: if use_cache {
-                CacheManager::new().ok()
+            config_manager: if use_cache {
+                ConfigManager::new().ok()
             } else {
                 None
             },
             use_cache,
             cache_max_age,
+            broadcast_address: None,
+            capture_raw: false,
+            strict: false,
+            every_broadcast: false,
+            save_on_interrupt: false,
+            settle: Duration::ZERO,
+            registry: Arc::new(DeviceTypeRegistry::default()),
+            dedupe_key: DedupeKey::default(),
+            write_cache: true,
+            update_pairing: true,
+            raw_packets: Arc::new(Mutex::new(HashMap::new())),
+            unsupported: Arc::new(Mutex::new(Vec::new())),
+            duplicates: Arc::new(Mutex::new(Vec::new())),
+            moved: Mutex::new(Vec::new()),
         }
     }
 
     pub fn without_cache() -> Self {
-        Self {
-            cache_manager: None,
-            use_cache: false,
-            cache_max_age: 0,
-        }
+        Self::with_cache_settings(false, 0)
+    }
+
+    /// Actively probe the given broadcast address (e.g. a directed subnet
+    /// broadcast like 192.168.5.255) in addition to listening passively
+    pub fn with_broadcast_address(mut self, broadcast_address: Ipv4Addr) -> Self {
+        self.broadcast_address = Some(broadcast_address);
+        self
+    }
+
+    /// Actively probe the limited broadcast 255.255.255.255, unless a
+    /// broadcast address was already given
+    pub fn with_active_probe(mut self) -> Self {
+        self.broadcast_address.get_or_insert(DEFAULT_PROBE_ADDRESS);
+        self
+    }
+
+    /// Keep the raw discovery packet of each device, and store it in the
+    /// cache the first time a device is seen
+    pub fn with_raw_capture(mut self) -> Self {
+        self.capture_raw = true;
+        self
+    }
+
+    /// Only report devices whose discovery packet parses without
+    /// defaulting any field
+    pub fn with_strict_parsing(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Yield every broadcast from `discover_stream`, not only the first one
+    /// per device, so the timing between broadcasts can be measured
+    pub fn with_every_broadcast(mut self) -> Self {
+        self.every_broadcast = true;
+        self
+    }
+
+    /// Let Ctrl-C end a cached discovery early: the devices found so far are
+    /// still written to the cache and pairing config, then
+    /// `SwitcherError::Interrupted` is returned
+    pub fn with_save_on_interrupt(mut self) -> Self {
+        self.save_on_interrupt = true;
+        self
+    }
+
+    /// After a device's first broadcast, keep listening for `settle` and use
+    /// its latest broadcast from that window instead. A device that just
+    /// rebooted may first announce a stale state; the later packet wins.
+    pub fn with_settle(mut self, settle: Duration) -> Self {
+        self.settle = settle;
+        self
+    }
+
+    /// Also accept the profile's extra device type codes as Power Plugs
+    pub fn with_profile(mut self, profile: &ProtocolProfile) -> Self {
+        let power_plug = PowerPlugHandler::with_type_codes(profile.device_type_codes.clone());
+        self.registry = Arc::new(DeviceTypeRegistry::empty().with_handler(power_plug));
+        self
+    }
+
+    /// Parse packets with these device type handlers instead of the built-in ones
+    #[allow(dead_code)] // library API, the CLI only adds type codes via --profile
+    pub fn with_registry(mut self, registry: DeviceTypeRegistry) -> Self {
+        self.registry = Arc::new(registry);
+        self
+    }
+
+    /// Merge cached and discovered devices by `dedupe_key` instead of device ID
+    pub fn with_dedupe_key(mut self, dedupe_key: DedupeKey) -> Self {
+        self.dedupe_key = dedupe_key;
+        self
+    }
+
+    /// Read the cache but never write discovered devices back to it
+    pub fn without_cache_write(mut self) -> Self {
+        self.write_cache = false;
+        self
+    }
+
+    /// Leave the pairing config untouched instead of refreshing paired
+    /// devices from the scan results
+    pub fn without_pairing_update(mut self) -> Self {
+        self.update_pairing = false;
+        self
+    }
+
+    /// Hex-encoded raw packets captured by the last scan, keyed by device_id
+    pub fn raw_packets(&self) -> HashMap<String, String> {
+        self.raw_packets.lock().unwrap().clone()
+    }
+
+    /// Devices of unsupported models seen by the last network scan
+    pub fn unsupported_devices(&self) -> Vec<UnsupportedDevice> {
+        self.unsupported.lock().unwrap().clone()
+    }
+
+    /// device_ids the last network scan saw from more than one IP or MAC
+    pub fn duplicate_device_ids(&self) -> Vec<DuplicateDeviceId> {
+        self.duplicates.lock().unwrap().clone()
+    }
+
+    /// Devices the last cached discovery found at a new IP
+    pub fn moved_devices(&self) -> Vec<MovedDevice> {
+        self.moved.lock().unwrap().clone()
     }
 
     /// Discover devices from cache only (no network scan)
-    pub fn discover_from_cache_only(
-        &self,
-    ) -> Result<Vec<SwitcherDevice>, Box<dyn std::error::Error>> {
+    pub fn discover_from_cache_only(&self) -> Result<Vec<SwitcherDevice>, SwitcherError> {
         if !self.use_cache {
             return Ok(Vec::new());
         }
 
-        let cache_manager = self
-            .cache_manager
+        let config_manager = self
+            .config_manager
             .as_ref()
-            .ok_or("Cache manager not available")?;
+            .ok_or_else(|| SwitcherError::Config("Config manager not available".to_string()))?;
 
-        let cache = cache_manager.load_cache()?;
-        let devices = cache.get_fresh_devices(self.cache_max_age);
+        let cache = config_manager.load_cache_data()?;
+        let devices = dedupe_devices(cache.get_fresh_devices(self.cache_max_age), self.dedupe_key);
 
         info!("Found {} cached device(s)", devices.len());
 
@@ -67,18 +339,19 @@ impl SwitcherDiscovery {
     pub async fn discover_with_cache(
         &self,
         duration: Duration,
-    ) -> Result<Vec<SwitcherDevice>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<SwitcherDevice>, SwitcherError> {
         debug!(
             "Starting discovery with cache - duration: {:?}, use_cache: {}, cache_max_age: {}",
             duration, self.use_cache, self.cache_max_age
         );
 
         let mut all_devices = Vec::new();
+        let mut previous_cache = None;
 
         if self.use_cache {
-            if let Some(cache_manager) = &self.cache_manager {
+            if let Some(config_manager) = &self.config_manager {
                 debug!("Loading devices from cache");
-                match cache_manager.load_cache() {
+                match config_manager.load_cache_data() {
                     Ok(cache) => {
                         let cached_devices = cache.get_fresh_devices(self.cache_max_age);
                         if !cached_devices.is_empty() {
@@ -87,6 +360,7 @@ impl SwitcherDiscovery {
                         } else {
                             debug!("No fresh devices found in cache");
                         }
+                        previous_cache = Some(cache);
                     }
                     Err(e) => {
                         warn!("Could not load cache: {}", e);
@@ -95,81 +369,117 @@ impl SwitcherDiscovery {
             }
         }
 
-        let discovered_devices = self.discover_network(duration).await?;
-        let mut device_map: HashMap<String, SwitcherDevice> = HashMap::new();
-
-        // Add cached devices first
-        for device in all_devices {
-            device_map.insert(device.device_id.clone(), device);
-        }
+        let (discovered_devices, interrupted) = if self.save_on_interrupt {
+            self.discover_until_interrupt(duration).await?
+        } else {
+            (self.discover_network(duration).await?, false)
+        };
+        let discovered_count = discovered_devices.len();
 
-        // Add/update with newly discovered devices
-        for device in discovered_devices {
-            device_map.insert(device.device_id.clone(), device);
+        if let Some(cache) = &previous_cache {
+            let moved = find_moved(cache, &discovered_devices);
+            for device in &moved {
+                info!(
+                    "Device '{}' ({}) moved from {} to {}",
+                    device.name, device.device_id, device.old_ip, device.new_ip
+                );
+            }
+            *self.moved.lock().unwrap() = moved;
         }
 
-        let final_devices: Vec<SwitcherDevice> = device_map.into_values().collect();
+        // Cached devices first, so newly discovered ones replace them
+        let final_devices = dedupe_devices(
+            all_devices.into_iter().chain(discovered_devices),
+            self.dedupe_key,
+        );
 
-        if self.use_cache {
-            if let Some(cache_manager) = &self.cache_manager {
-                match cache_manager.load_cache() {
-                    Ok(mut cache) => {
-                        for device in &final_devices {
-                            cache.add_device(device.clone());
-                        }
+        // Write the cache and refresh paired devices in one config save
+        if let Some(config_manager) = &self.config_manager {
+            let result = config_manager.update(|config| {
+                let mut changed = false;
 
-                        cache.remove_old_devices(self.cache_max_age * 2);
+                if self.write_cache {
+                    let cache = config.cache.get_or_insert_with(DeviceCache::new);
+                    for device in &final_devices {
+                        cache.add_device(device.clone());
+                    }
 
-                        if let Err(e) = cache_manager.save_cache(&cache) {
-                            warn!("Could not save cache: {}", e);
-                        } else {
-                            debug!("Updated device cache");
+                    if self.capture_raw {
+                        for (device_id, raw_packet) in self.raw_packets() {
+                            cache.set_raw_packet(&device_id, raw_packet);
                         }
                     }
-                    Err(e) => {
-                        warn!("Could not update cache: {}", e);
-                    }
-                }
-            }
-        }
 
-        // Update pairing data for discovered devices
-        if let Ok(pairing_manager) = PairingManager::new() {
-            match pairing_manager.load_pairing() {
-                Ok(mut pairing) => {
-                    let mut updated = false;
+                    cache.remove_old_devices(self.cache_max_age * 2);
+                    changed = true;
+                }
 
+                if !self.update_pairing {
+                    debug!("Skipping pairing update");
+                } else if let Some(pairing) = config.pairing.as_mut() {
                     for device in &final_devices {
                         if pairing.update_device_info(device) {
-                            updated = true;
-                        }
-                    }
-
-                    if updated {
-                        if let Err(e) = pairing_manager.save_pairing(&pairing) {
-                            warn!("Could not update pairing data: {}", e);
-                        } else {
-                            debug!("Updated pairing data for discovered devices");
+                            changed = true;
                         }
                     }
                 }
-                Err(e) => {
-                    warn!("Could not load pairing data: {}", e);
-                }
+
+                changed
+            });
+            match result {
+                Ok(()) => debug!("Saved discovery results to the config"),
+                Err(e) => warn!("Could not save discovery results: {}", e),
             }
         }
 
+        if interrupted {
+            return Err(SwitcherError::Interrupted(discovered_count));
+        }
         Ok(final_devices)
     }
 
-    pub async fn discover(
+    /// Like `discover_network`, but Ctrl-C ends the scan early. Returns the
+    /// devices found so far and whether the scan was interrupted.
+    async fn discover_until_interrupt(
         &self,
         duration: Duration,
-    ) -> Result<Vec<SwitcherDevice>, Box<dyn std::error::Error>> {
+    ) -> Result<(Vec<SwitcherDevice>, bool), SwitcherError> {
+        let start = Instant::now();
+        let stream = self
+            .listen(duration + self.settle, !self.settle.is_zero())
+            .await?;
+        tokio::pin!(stream);
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+        let mut listening = true; // false if the handler couldn't be installed
+
+        let mut devices = SettledDevices::new(duration, self.settle);
+        loop {
+            tokio::select! {
+                device = stream.next() => match device {
+                    Some(device) => devices.add(device, start.elapsed()),
+                    None => return Ok((devices.into_devices(), false)),
+                },
+                result = &mut ctrl_c, if listening => match result {
+                    Ok(()) => {
+                        warn!("Discovery interrupted after {} device(s), saving them", devices.len());
+                        return Ok((devices.into_devices(), true));
+                    }
+                    Err(e) => {
+                        warn!("Could not listen for Ctrl-C: {}", e);
+                        listening = false;
+                    }
+                },
+            }
+        }
+    }
+
+    pub async fn discover(&self, duration: Duration) -> Result<Vec<SwitcherDevice>, SwitcherError> {
         if self.use_cache {
             self.discover_with_cache(duration).await
         } else {
-            self.discover_network(duration).await
+            let devices = self.discover_network(duration).await?;
+            Ok(dedupe_devices(devices, self.dedupe_key))
         }
     }
 
@@ -177,13 +487,45 @@ impl SwitcherDiscovery {
     pub async fn discover_network(
         &self,
         duration: Duration,
-    ) -> Result<Vec<SwitcherDevice>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<SwitcherDevice>, SwitcherError> {
+        let start = Instant::now();
+        let stream = self
+            .listen(duration + self.settle, !self.settle.is_zero())
+            .await?;
+        tokio::pin!(stream);
+        let mut settled = SettledDevices::new(duration, self.settle);
+        while let Some(device) = stream.next().await {
+            settled.add(device, start.elapsed());
+        }
+        let devices = settled.into_devices();
+        info!(
+            "Network discovery completed - found {} devices",
+            devices.len()
+        );
+        Ok(devices)
+    }
+
+    /// Listen for `duration` and yield each device as soon as it is first
+    /// seen, deduplicated by device_id. The stream ends when the time is up.
+    /// No caching or pairing updates are done.
+    pub async fn discover_stream(
+        &self,
+        duration: Duration,
+    ) -> Result<impl Stream<Item = SwitcherDevice>, SwitcherError> {
+        self.listen(duration, self.every_broadcast).await
+    }
+
+    /// `discover_stream` with an explicit choice of yielding repeat broadcasts
+    async fn listen(
+        &self,
+        duration: Duration,
+        every_broadcast: bool,
+    ) -> Result<impl Stream<Item = SwitcherDevice>, SwitcherError> {
         debug!("Starting network discovery - duration: {:?}", duration);
-        let discovered_devices = Arc::new(Mutex::new(HashMap::new()));
 
         // Power Plug devices broadcast on port 10002 only
-        debug!("Binding UDP socket to 0.0.0.0:10002");
-        let socket = match UdpSocket::bind("0.0.0.0:10002").await {
+        debug!("Binding UDP socket to 0.0.0.0:{}", SWITCHER_DISCOVERY_PORT);
+        let socket = match UdpSocket::bind(("0.0.0.0", SWITCHER_DISCOVERY_PORT)).await {
             Ok(socket) => {
                 debug!("Successfully bound UDP socket");
                 socket
@@ -195,32 +537,140 @@ impl SwitcherDiscovery {
         };
 
         socket.set_broadcast(true)?;
-        info!("Listening for Power Plug devices on UDP port 10002");
+        info!(
+            "Listening for Power Plug devices on UDP port {}",
+            SWITCHER_DISCOVERY_PORT
+        );
 
-        let devices_clone = Arc::clone(&discovered_devices);
-        let handle = tokio::spawn(async move {
+        // Replies arrive on the listening socket, so they're collected below
+        if let Some(broadcast_address) = self.broadcast_address {
+            info!(
+                "Sending discovery probe to {}:{}",
+                broadcast_address, SWITCHER_DISCOVERY_PORT
+            );
+            if let Err(e) = socket
+                .send_to(
+                    &DISCOVERY_PROBE,
+                    (broadcast_address, SWITCHER_DISCOVERY_PORT),
+                )
+                .await
+            {
+                warn!(
+                    "Could not send discovery probe to {}: {}",
+                    broadcast_address, e
+                );
+            }
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let raw_packets = self.capture_raw.then(|| Arc::clone(&self.raw_packets));
+        let unsupported = Arc::clone(&self.unsupported);
+        let duplicates = Arc::clone(&self.duplicates);
+        let strict = self.strict;
+        let registry = Arc::clone(&self.registry);
+        tokio::spawn(async move {
             let mut buf = [0; 1024];
+            let mut seen: HashMap<String, (String, String)> = HashMap::new(); // id -> (ip, mac)
+            let deadline = sleep(duration);
+            tokio::pin!(deadline);
 
+            debug!(
+                "Waiting for {} seconds to collect device broadcasts",
+                duration.as_secs()
+            );
             loop {
-                match socket.recv_from(&mut buf).await {
+                let received = tokio::select! {
+                    _ = &mut deadline => break,
+                    received = socket.recv_from(&mut buf) => received,
+                };
+                match received {
                     Ok((len, addr)) => {
                         debug!("Received {} bytes from {}", len, addr);
-                        if let Some(device) = SwitcherDevice::from_discovery_packet(&buf[..len]) {
-                            let mut devices = devices_clone.lock().unwrap();
-                            if !devices.contains_key(&device.device_id) {
-                                info!(
-                                    "Discovered new device: {} (ID: {}) at {}",
-                                    device.name, device.device_id, device.ip_address
-                                );
-                                devices.insert(device.device_id.clone(), device);
+                        if buf[..len] == DISCOVERY_PROBE {
+                            debug!("Ignoring discovery probe from {}", addr);
+                            continue;
+                        }
+                        let parsed = registry.try_parse(&buf[..len], strict);
+                        if let Ok(device) = parsed {
+                            let first = match seen.get(&device.device_id) {
+                                None => {
+                                    seen.insert(
+                                        device.device_id.clone(),
+                                        (device.ip_address.clone(), device.mac_address.clone()),
+                                    );
+                                    info!("Discovered new device: {}", device);
+                                    true
+                                }
+                                Some((ip, mac))
+                                    if *ip != device.ip_address || *mac != device.mac_address =>
+                                {
+                                    let duplicate = DuplicateDeviceId {
+                                        device_id: device.device_id.clone(),
+                                        first_ip: ip.clone(),
+                                        first_mac: mac.clone(),
+                                        other_ip: device.ip_address.clone(),
+                                        other_mac: device.mac_address.clone(),
+                                    };
+                                    let mut duplicates = duplicates.lock().unwrap();
+                                    if !duplicates.contains(&duplicate) {
+                                        warn!(
+                                            "Device ID {} seen from {} ({}) and {} ({})",
+                                            duplicate.device_id,
+                                            duplicate.first_ip,
+                                            duplicate.first_mac,
+                                            duplicate.other_ip,
+                                            duplicate.other_mac
+                                        );
+                                        duplicates.push(duplicate);
+                                    }
+                                    false
+                                }
+                                Some(_) => false,
+                            };
+                            if first || every_broadcast {
+                                if let Some(raw_packets) = &raw_packets {
+                                    raw_packets
+                                        .lock()
+                                        .unwrap()
+                                        .insert(device.device_id.clone(), hex::encode(&buf[..len]));
+                                }
+                                if tx.send(device).is_err() {
+                                    debug!("Discovery stream dropped, stopping");
+                                    break;
+                                }
                             } else {
                                 debug!("Device {} already discovered, skipping", device.device_id);
                             }
-                        } else {
-                            debug!(
-                                "Received packet from {} but could not parse as Switcher device",
+                        } else if strict && registry.parse(&buf[..len], false).is_some() {
+                            warn!(
+                                "Rejected packet from {} in strict mode: some fields did not parse cleanly",
                                 addr
                             );
+                        } else if let Err(PacketRejection::UnsupportedDeviceType(
+                            device_type_code,
+                        )) = parsed
+                        {
+                            let unsupported_device = UnsupportedDevice {
+                                ip_address: addr.ip().to_string(),
+                                device_type_code,
+                            };
+                            let mut unsupported = unsupported.lock().unwrap();
+                            if !unsupported.contains(&unsupported_device) {
+                                info!(
+                                    "Ignoring unsupported device at {} (type {})",
+                                    unsupported_device.ip_address,
+                                    unsupported_device.device_type_code
+                                );
+                                unsupported.push(unsupported_device);
+                            } else {
+                                debug!(
+                                    "Dropped packet from {}: unsupported device type {}",
+                                    addr, unsupported_device.device_type_code
+                                );
+                            }
+                        } else if let Err(rejection) = parsed {
+                            // Shown with --verbose: why a packet didn't become a device
+                            info!("Dropped packet from {}: {}", addr, rejection);
                         }
                     }
                     Err(e) => {
@@ -231,19 +681,334 @@ impl SwitcherDiscovery {
             }
         });
 
-        debug!(
-            "Waiting for {} seconds to collect device broadcasts",
-            duration.as_secs()
+        Ok(UnboundedReceiverStream::new(rx))
+    }
+}
+
+impl Default for SwitcherDiscovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How many of several discovery scans saw a device
+#[derive(Debug, Clone)]
+pub struct ScanPresence {
+    pub device: SwitcherDevice,
+    pub seen: usize,
+    pub scans: usize,
+}
+
+impl ScanPresence {
+    /// Share of scans that saw the device, as a percentage
+    pub fn stability(&self) -> f64 {
+        self.seen as f64 * 100.0 / self.scans as f64
+    }
+}
+
+/// Count, per device_id, how many scans saw each device. The most recently
+/// seen details are kept; results are ordered least stable first.
+pub fn tally_scans(scans: &[Vec<SwitcherDevice>]) -> Vec<ScanPresence> {
+    let mut counts: HashMap<String, ScanPresence> = HashMap::new();
+    for scan in scans {
+        for device in scan {
+            counts
+                .entry(device.device_id.clone())
+                .and_modify(|presence| {
+                    presence.device = device.clone();
+                    presence.seen += 1;
+                })
+                .or_insert_with(|| ScanPresence {
+                    device: device.clone(),
+                    seen: 1,
+                    scans: scans.len(),
+                });
+        }
+    }
+
+    let mut presence: Vec<ScanPresence> = counts.into_values().collect();
+    presence.sort_by(|a, b| {
+        a.seen
+            .cmp(&b.seen)
+            .then_with(|| a.device.name.cmp(&b.device.name))
+    });
+    presence
+}
+
+/// Broadcast timing of one device over a `discovery-probe` run
+#[derive(Debug, Clone)]
+pub struct BroadcastTiming {
+    pub device: SwitcherDevice,
+    pub packets: usize,
+    pub min_gap: Option<Duration>, // None until a second broadcast arrives
+    pub avg_gap: Option<Duration>,
+    pub max_gap: Option<Duration>,
+}
+
+/// Group broadcast arrival times (offsets from the start of the run) by
+/// device_id and work out the gaps between them. Results are ordered by
+/// largest gap first, so the most erratic broadcasters lead.
+pub fn broadcast_timings(arrivals: &[(SwitcherDevice, Duration)]) -> Vec<BroadcastTiming> {
+    let mut by_device: HashMap<&str, (&SwitcherDevice, Vec<Duration>)> = HashMap::new();
+    for (device, at) in arrivals {
+        let entry = by_device
+            .entry(device.device_id.as_str())
+            .or_insert_with(|| (device, Vec::new()));
+        entry.0 = device;
+        entry.1.push(*at);
+    }
+
+    let mut timings: Vec<BroadcastTiming> = by_device
+        .into_values()
+        .map(|(device, mut times)| {
+            times.sort();
+            let gaps: Vec<Duration> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            BroadcastTiming {
+                device: device.clone(),
+                packets: times.len(),
+                min_gap: gaps.iter().min().copied(),
+                avg_gap: (!gaps.is_empty())
+                    .then(|| gaps.iter().sum::<Duration>() / gaps.len() as u32),
+                max_gap: gaps.iter().max().copied(),
+            }
+        })
+        .collect();
+    timings.sort_by(|a, b| {
+        b.max_gap
+            .cmp(&a.max_gap)
+            .then_with(|| a.device.name.cmp(&b.device.name))
+    });
+    timings
+}
+
+/// Parse a `--broadcast-address` value. Only broadcast-style IPv4 addresses
+/// are accepted: the limited broadcast 255.255.255.255 or a directed
+/// broadcast whose last octet is 255.
+pub fn parse_broadcast_address(value: &str) -> Result<Ipv4Addr, String> {
+    let address: Ipv4Addr = value
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid IPv4 address", value))?;
+
+    if address.is_loopback() || address.is_multicast() || address.is_unspecified() {
+        return Err(format!("'{}' is not a broadcast address", address));
+    }
+
+    if address.octets()[3] != 255 {
+        return Err(format!(
+            "'{}' is not a broadcast address (expected e.g. 192.168.1.255)",
+            address
+        ));
+    }
+
+    Ok(address)
+}
+
+/// Parse a `--dedupe-by` value: `device-id` or `mac`
+pub fn parse_dedupe_key(value: &str) -> Result<DedupeKey, String> {
+    match value {
+        "device-id" => Ok(DedupeKey::DeviceId),
+        "mac" => Ok(DedupeKey::Mac),
+        _ => Err(format!(
+            "'{}' is not a dedupe key (expected device-id or mac)",
+            value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::DeviceState;
+
+    fn plug_packet(device_id: [u8; 3]) -> Vec<u8> {
+        let mut packet = vec![0; 165];
+        packet[0..2].copy_from_slice(&[0xfe, 0xf0]);
+        packet[18..21].copy_from_slice(&device_id);
+        packet[42..46].copy_from_slice(b"Plug");
+        packet[74..76].copy_from_slice(&[0x01, 0xa8]);
+        packet[76..80].copy_from_slice(&[1, 0, 0, 127]);
+        packet
+    }
+
+    #[test]
+    fn test_tally_scans() {
+        let office =
+            SwitcherDevice::from_discovery_packet(&plug_packet([0x9c, 0x4f, 0x22])).unwrap();
+        let lamp = SwitcherDevice::from_discovery_packet(&plug_packet([0x12, 0x34, 0x56])).unwrap();
+        let scans = vec![
+            vec![office.clone(), lamp.clone()],
+            vec![office.clone()],
+            vec![office.clone()],
+            vec![],
+        ];
+
+        let presence = tally_scans(&scans);
+
+        assert_eq!(presence.len(), 2);
+        assert_eq!(presence[0].device.device_id, "123456");
+        assert_eq!((presence[0].seen, presence[0].scans), (1, 4));
+        assert_eq!(presence[0].stability(), 25.0);
+        assert_eq!((presence[1].seen, presence[1].scans), (3, 4));
+    }
+
+    #[test]
+    fn test_broadcast_timings() {
+        let office =
+            SwitcherDevice::from_discovery_packet(&plug_packet([0x9c, 0x4f, 0x22])).unwrap();
+        let lamp = SwitcherDevice::from_discovery_packet(&plug_packet([0x12, 0x34, 0x56])).unwrap();
+        let arrivals = vec![
+            (office.clone(), Duration::from_secs(0)),
+            (lamp.clone(), Duration::from_secs(1)),
+            (office.clone(), Duration::from_secs(3)),
+            (office.clone(), Duration::from_secs(50)),
+            (office.clone(), Duration::from_secs(53)),
+        ];
+
+        let timings = broadcast_timings(&arrivals);
+
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].device.device_id, "9c4f22");
+        assert_eq!(timings[0].packets, 4);
+        assert_eq!(timings[0].min_gap, Some(Duration::from_secs(3)));
+        assert_eq!(timings[0].avg_gap, Some(Duration::from_secs(53) / 3));
+        assert_eq!(timings[0].max_gap, Some(Duration::from_secs(47)));
+        assert_eq!(timings[1].packets, 1);
+        assert_eq!(timings[1].max_gap, None);
+    }
+
+    #[test]
+    fn test_settled_devices_keep_latest_broadcast_in_window() {
+        let office =
+            SwitcherDevice::from_discovery_packet(&plug_packet([0x9c, 0x4f, 0x22])).unwrap();
+        let lamp = SwitcherDevice::from_discovery_packet(&plug_packet([0x12, 0x34, 0x56])).unwrap();
+        let settled_office = SwitcherDevice {
+            state: DeviceState::On,
+            ..office.clone()
+        };
+        let late_office = SwitcherDevice {
+            power_consumption: 99,
+            ..office.clone()
+        };
+
+        let mut devices = SettledDevices::new(Duration::from_secs(5), Duration::from_secs(2));
+        devices.add(office, Duration::from_secs(1));
+        devices.add(settled_office, Duration::from_secs(3));
+        devices.add(late_office, Duration::from_secs(4)); // outside the window
+        devices.add(lamp, Duration::from_secs(6)); // first heard while settling
+
+        let devices = devices.into_devices();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].state, DeviceState::On);
+        assert_eq!(devices[0].power_consumption, 0);
+    }
+
+    #[tokio::test]
+    async fn test_discover_stream_yields_each_device_once() {
+        let discovery = SwitcherDiscovery::without_cache();
+        let stream = discovery
+            .discover_stream(Duration::from_millis(500))
+            .await
+            .unwrap();
+
+        // A second device announcing 9c4f22 from another IP is flagged, not yielded
+        let mut clone = plug_packet([0x9c, 0x4f, 0x22]);
+        clone[76..80].copy_from_slice(&[192, 168, 1, 5]);
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for packet in [
+            plug_packet([0x9c, 0x4f, 0x22]),
+            plug_packet([0x9c, 0x4f, 0x22]),
+            clone.clone(),
+            clone,
+            plug_packet([0x12, 0x34, 0x56]),
+        ] {
+            sender
+                .send_to(&packet, ("127.0.0.1", SWITCHER_DISCOVERY_PORT))
+                .await
+                .unwrap();
+        }
+
+        let ids: Vec<String> = stream.map(|device| device.device_id).collect().await;
+        assert_eq!(ids, ["9c4f22", "123456"]);
+        let duplicates = discovery.duplicate_device_ids();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].device_id, "9c4f22");
+        assert_eq!(duplicates[0].first_ip, "1.0.0.127");
+        assert_eq!(duplicates[0].other_ip, "192.168.1.5");
+    }
+
+    #[test]
+    fn test_dedupe_by_mac() {
+        let old = SwitcherDevice::from_discovery_packet(&plug_packet([0x9c, 0x4f, 0x22])).unwrap();
+        let mut reset =
+            SwitcherDevice::from_discovery_packet(&plug_packet([0x12, 0x34, 0x56])).unwrap();
+        reset.mac_address = old.mac_address.to_lowercase().replace(':', "-");
+
+        let by_id = dedupe_devices(vec![old.clone(), reset.clone()], DedupeKey::DeviceId);
+        assert_eq!(by_id.len(), 2);
+
+        let by_mac = dedupe_devices(vec![old, reset], DedupeKey::Mac);
+        assert_eq!(by_mac.len(), 1);
+        assert_eq!(by_mac[0].device_id, "123456");
+
+        assert_eq!(parse_dedupe_key("mac"), Ok(DedupeKey::Mac));
+        assert!(parse_dedupe_key("ip").is_err());
+    }
+
+    #[test]
+    fn test_find_moved() {
+        let office =
+            SwitcherDevice::from_discovery_packet(&plug_packet([0x9c, 0x4f, 0x22])).unwrap();
+        let lamp = SwitcherDevice::from_discovery_packet(&plug_packet([0x12, 0x34, 0x56])).unwrap();
+        let mut cache = DeviceCache::new();
+        cache.add_device(office.clone());
+
+        let mut moved_office = office.clone();
+        moved_office.ip_address = "192.168.1.9".to_string();
+        let moved = find_moved(&cache, &[moved_office, lamp.clone()]);
+        assert_eq!(
+            moved,
+            vec![MovedDevice {
+                device_id: "9c4f22".to_string(),
+                name: "Plug".to_string(),
+                old_ip: office.ip_address.clone(),
+                new_ip: "192.168.1.9".to_string(),
+            }]
         );
-        sleep(duration).await;
-        handle.abort();
 
-        let devices = discovered_devices.lock().unwrap();
-        let device_count = devices.len();
-        info!(
-            "Network discovery completed - found {} devices",
-            device_count
+        assert!(find_moved(&cache, &[office, lamp]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_broadcast_address() {
+        assert_eq!(
+            parse_broadcast_address("192.168.5.255"),
+            Ok(Ipv4Addr::new(192, 168, 5, 255))
+        );
+        assert_eq!(
+            parse_broadcast_address("255.255.255.255"),
+            Ok(Ipv4Addr::BROADCAST)
+        );
+        assert!(parse_broadcast_address("192.168.5.10").is_err());
+        assert!(parse_broadcast_address("239.1.1.255").is_err());
+        assert!(parse_broadcast_address("not-an-ip").is_err());
+    }
+
+    #[test]
+    fn test_probe_only_when_asked() {
+        assert_eq!(SwitcherDiscovery::without_cache().broadcast_address, None);
+        assert_eq!(
+            SwitcherDiscovery::without_cache()
+                .with_active_probe()
+                .broadcast_address,
+            Some(Ipv4Addr::BROADCAST)
+        );
+        let subnet = Ipv4Addr::new(192, 168, 5, 255);
+        assert_eq!(
+            SwitcherDiscovery::without_cache()
+                .with_broadcast_address(subnet)
+                .with_active_probe()
+                .broadcast_address,
+            Some(subnet)
         );
-        Ok(devices.values().cloned().collect())
     }
 }
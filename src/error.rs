@@ -0,0 +1,233 @@
+use crate::control::{MAX_DEVICE_NAME_LEN, MIN_DEVICE_NAME_LEN};
+use std::fmt;
+
+/// Exit code for errors that don't fall into one of the categories below
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_UNREACHABLE: i32 = 2;
+pub const EXIT_INVALID_DEVICE: i32 = 3;
+pub const EXIT_CONFIG: i32 = 4;
+pub const EXIT_USAGE: i32 = 64; // EX_USAGE from sysexits.h
+pub const EXIT_INTERRUPTED: i32 = 130; // 128 + SIGINT, as shells report Ctrl-C
+
+/// Error categories that scripts can tell apart by the process exit code
+#[derive(Debug)]
+pub enum SwitcherError {
+    /// Could not connect to the device, or it stopped answering. Worth retrying.
+    Unreachable(String),
+    /// Connecting to the device took longer than the connect timeout
+    Timeout(String),
+    /// The device accepted the connection but did not complete the login
+    LoginFailed(String),
+    /// The device answered a command with fewer bytes than a reply needs
+    ShortResponse(String),
+    /// The device's hostname didn't resolve, so no connection was attempted
+    HostNotFound(String),
+    /// The whole command ran past `--deadline`; holds the deadline in seconds
+    DeadlineExceeded(u64),
+    /// Ctrl-C ended a discovery scan early; holds how many devices it had
+    /// found, which were still saved
+    Interrupted(usize),
+    /// The device answered but rejected the device ID, or no such device is known
+    InvalidDevice(String),
+    /// The pairing, cache or settings file could not be read or written
+    Config(String),
+    /// Invalid arguments. Retrying won't help.
+    Usage(String),
+    /// A new device name shorter than `MIN_DEVICE_NAME_LEN` bytes; holds the length given
+    NameTooShort(usize),
+    /// A new device name longer than `MAX_DEVICE_NAME_LEN` bytes; holds the length given
+    NameTooLong(usize),
+    /// A new device name with an embedded NUL, which the device would truncate at
+    NameContainsNul,
+    /// A socket or file operation failed outside the cases above
+    Io(std::io::Error),
+}
+
+impl SwitcherError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SwitcherError::Unreachable(_)
+            | SwitcherError::Timeout(_)
+            | SwitcherError::LoginFailed(_)
+            | SwitcherError::ShortResponse(_)
+            | SwitcherError::HostNotFound(_)
+            | SwitcherError::DeadlineExceeded(_) => EXIT_UNREACHABLE,
+            SwitcherError::InvalidDevice(_) => EXIT_INVALID_DEVICE,
+            SwitcherError::Config(_) => EXIT_CONFIG,
+            SwitcherError::Interrupted(_) => EXIT_INTERRUPTED,
+            SwitcherError::Usage(_)
+            | SwitcherError::NameTooShort(_)
+            | SwitcherError::NameTooLong(_)
+            | SwitcherError::NameContainsNul => EXIT_USAGE,
+            SwitcherError::Io(_) => EXIT_FAILURE,
+        }
+    }
+
+    /// Whether no device was reached at all, so trying another address may
+    /// help. A device that answered and then failed is not retried elsewhere.
+    pub fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            SwitcherError::Unreachable(_)
+                | SwitcherError::Timeout(_)
+                | SwitcherError::HostNotFound(_)
+                | SwitcherError::Io(_)
+        )
+    }
+
+    /// Stable snake_case name of the category, the `kind` in `--parseable-error` output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SwitcherError::Unreachable(_) => "unreachable",
+            SwitcherError::Timeout(_) => "timeout",
+            SwitcherError::LoginFailed(_) => "login_failed",
+            SwitcherError::ShortResponse(_) => "short_response",
+            SwitcherError::HostNotFound(_) => "host_not_found",
+            SwitcherError::DeadlineExceeded(_) => "deadline_exceeded",
+            SwitcherError::Interrupted(_) => "interrupted",
+            SwitcherError::InvalidDevice(_) => "invalid_device",
+            SwitcherError::Config(_) => "config",
+            SwitcherError::Usage(_) => "usage",
+            SwitcherError::NameTooShort(_)
+            | SwitcherError::NameTooLong(_)
+            | SwitcherError::NameContainsNul => "invalid_name",
+            SwitcherError::Io(_) => "io",
+        }
+    }
+}
+
+impl fmt::Display for SwitcherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwitcherError::Unreachable(message)
+            | SwitcherError::Timeout(message)
+            | SwitcherError::LoginFailed(message)
+            | SwitcherError::ShortResponse(message)
+            | SwitcherError::HostNotFound(message)
+            | SwitcherError::InvalidDevice(message)
+            | SwitcherError::Config(message)
+            | SwitcherError::Usage(message) => write!(f, "{}", message),
+            SwitcherError::NameTooShort(length) | SwitcherError::NameTooLong(length) => write!(
+                f,
+                "Device name must be {}-{} characters; you gave {}",
+                MIN_DEVICE_NAME_LEN, MAX_DEVICE_NAME_LEN, length
+            ),
+            SwitcherError::NameContainsNul => write!(f, "Device name must not contain a NUL byte"),
+            SwitcherError::DeadlineExceeded(secs) => {
+                write!(f, "Command exceeded the {}s deadline", secs)
+            }
+            SwitcherError::Interrupted(found) => write!(
+                f,
+                "Discovery interrupted; saved the {} device(s) found so far",
+                found
+            ),
+            SwitcherError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SwitcherError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SwitcherError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SwitcherError {
+    fn from(e: std::io::Error) -> Self {
+        SwitcherError::Io(e)
+    }
+}
+
+/// Process exit code for any error: the category's code for a
+/// `SwitcherError`, `EXIT_FAILURE` for everything else
+pub fn exit_code(error: &(dyn std::error::Error + 'static)) -> i32 {
+    error
+        .downcast_ref::<SwitcherError>()
+        .map_or(EXIT_FAILURE, SwitcherError::exit_code)
+}
+
+/// `{"error":{"kind":"unreachable","message":"..."}}` for `--parseable-error`.
+/// Errors that aren't a `SwitcherError` have kind `other`.
+pub fn envelope(error: &(dyn std::error::Error + 'static)) -> serde_json::Value {
+    let kind = error
+        .downcast_ref::<SwitcherError>()
+        .map_or("other", SwitcherError::kind);
+    serde_json::json!({
+        "error": {
+            "kind": kind,
+            "message": error.to_string(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code_through_box() {
+        let unreachable: Box<dyn std::error::Error> =
+            SwitcherError::Unreachable("timed out".to_string()).into();
+        assert_eq!(exit_code(unreachable.as_ref()), EXIT_UNREACHABLE);
+        assert_eq!(unreachable.to_string(), "timed out");
+
+        let deadline: Box<dyn std::error::Error> = SwitcherError::DeadlineExceeded(10).into();
+        assert_eq!(exit_code(deadline.as_ref()), EXIT_UNREACHABLE);
+        assert_eq!(deadline.to_string(), "Command exceeded the 10s deadline");
+
+        let interrupted: Box<dyn std::error::Error> = SwitcherError::Interrupted(2).into();
+        assert_eq!(exit_code(interrupted.as_ref()), EXIT_INTERRUPTED);
+
+        let usage: Box<dyn std::error::Error> = SwitcherError::Usage("bad".to_string()).into();
+        assert_eq!(exit_code(usage.as_ref()), EXIT_USAGE);
+
+        let too_long: Box<dyn std::error::Error> = SwitcherError::NameTooLong(40).into();
+        assert_eq!(exit_code(too_long.as_ref()), EXIT_USAGE);
+        assert_eq!(
+            too_long.to_string(),
+            "Device name must be 2-32 characters; you gave 40"
+        );
+
+        let other: Box<dyn std::error::Error> = "something else".into();
+        assert_eq!(exit_code(other.as_ref()), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_io_error_converts() {
+        fn open_missing() -> Result<(), SwitcherError> {
+            std::fs::File::open("/nonexistent/switcher_config.json")?;
+            Ok(())
+        }
+
+        let err = open_missing().unwrap_err();
+        assert!(matches!(err, SwitcherError::Io(_)));
+        assert_eq!(err.kind(), "io");
+        assert_eq!(err.exit_code(), EXIT_FAILURE);
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn test_is_connection_error() {
+        assert!(SwitcherError::Timeout("timed out".to_string()).is_connection_error());
+        assert!(SwitcherError::HostNotFound("plug.lan".to_string()).is_connection_error());
+        assert!(!SwitcherError::InvalidDevice("did not turn ON".to_string()).is_connection_error());
+        assert!(!SwitcherError::LoginFailed("short".to_string()).is_connection_error());
+        assert!(!SwitcherError::Usage("bad".to_string()).is_connection_error());
+    }
+
+    #[test]
+    fn test_envelope() {
+        let unreachable: Box<dyn std::error::Error> =
+            SwitcherError::Unreachable("timed out".to_string()).into();
+        assert_eq!(
+            envelope(unreachable.as_ref()),
+            serde_json::json!({"error": {"kind": "unreachable", "message": "timed out"}})
+        );
+
+        let other: Box<dyn std::error::Error> = "something else".into();
+        assert_eq!(envelope(other.as_ref())["error"]["kind"], "other");
+    }
+}
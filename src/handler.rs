@@ -0,0 +1,363 @@
+use crate::device::{DeviceState, SwitcherDevice, SwitcherDeviceType, POWER_PLUG_TYPE_CODE};
+
+/// Device key byte (offset 40 of a discovery packet) a device sends when it
+/// has no key. Any other value is the key, stored as two hex digits.
+pub const NO_DEVICE_KEY: u8 = 0x00;
+
+/// Parses the discovery broadcasts of one family of Switcher models. New
+/// models are supported by implementing this and registering the handler
+/// in a `DeviceTypeRegistry`, rather than by growing one parse function.
+pub trait DeviceTypeHandler: Send + Sync {
+    /// Whether this handler parses packets with this type code (lowercase
+    /// hex, e.g. "01a8")
+    fn matches(&self, type_code: &str) -> bool;
+
+    /// Parse a Switcher-framed discovery packet with a matching type code.
+    /// In strict mode return `None` rather than default any field.
+    fn parse(&self, data: &[u8], strict: bool) -> Option<SwitcherDevice>;
+}
+
+/// The built-in handler for the Switcher Power Plug (type 01a8)
+#[derive(Debug, Clone, Default)]
+pub struct PowerPlugHandler {
+    extra_type_codes: Vec<String>, // from a --profile, also parsed as Power Plugs
+}
+
+impl PowerPlugHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also accept these type codes (lowercase hex) as Power Plugs
+    pub fn with_type_codes(extra_type_codes: Vec<String>) -> Self {
+        Self { extra_type_codes }
+    }
+}
+
+impl DeviceTypeHandler for PowerPlugHandler {
+    fn matches(&self, type_code: &str) -> bool {
+        type_code == POWER_PLUG_TYPE_CODE || self.extra_type_codes.iter().any(|c| c == type_code)
+    }
+
+    fn parse(&self, data: &[u8], strict: bool) -> Option<SwitcherDevice> {
+        if data.len() != 165 || data[0..2] != [0xfe, 0xf0] {
+            return None;
+        }
+
+        let hex_data = hex::encode(data);
+
+        let device_id = hex::encode(&data[18..21]);
+        let device_key = (data[40] != NO_DEVICE_KEY).then(|| hex::encode(&data[40..41]));
+
+        let name_bytes = &data[42..74];
+        let name_end = name_bytes
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(name_bytes.len());
+        let name = if strict {
+            String::from_utf8(name_bytes[..name_end].to_vec()).ok()?
+        } else {
+            String::from_utf8_lossy(&name_bytes[..name_end]).to_string()
+        };
+
+        let device_type_hex = hex::encode(&data[74..76]);
+        if !self.matches(&device_type_hex) {
+            return None;
+        }
+        let device_type = SwitcherDeviceType::PowerPlug.display_name().to_string();
+
+        // IP address from hex positions 152:160 (aioswitcher protocol)
+        if hex_data.len() < 160 {
+            return None;
+        }
+        let hex_ip = &hex_data[152..160];
+        let ip_addr = u32::from_str_radix(
+            &format!(
+                "{}{}{}{}",
+                &hex_ip[6..8],
+                &hex_ip[4..6],
+                &hex_ip[2..4],
+                &hex_ip[0..2]
+            ),
+            16,
+        )
+        .ok()?;
+        let ip_address = format!(
+            "{}.{}.{}.{}",
+            ip_addr & 0xFF,
+            (ip_addr >> 8) & 0xFF,
+            (ip_addr >> 16) & 0xFF,
+            (ip_addr >> 24) & 0xFF
+        );
+
+        // MAC address (hex positions 160:172 in hex representation)
+        if hex_data.len() < 278 {
+            return None;
+        }
+        let hex_mac = &hex_data[160..172];
+        let mac_address = format!(
+            "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+            u8::from_str_radix(&hex_mac[0..2], 16).ok()?,
+            u8::from_str_radix(&hex_mac[2..4], 16).ok()?,
+            u8::from_str_radix(&hex_mac[4..6], 16).ok()?,
+            u8::from_str_radix(&hex_mac[6..8], 16).ok()?,
+            u8::from_str_radix(&hex_mac[8..10], 16).ok()?,
+            u8::from_str_radix(&hex_mac[10..12], 16).ok()?
+        );
+
+        // Device state (hex positions 266:268 in hex representation)
+        let hex_device_state = &hex_data[266..268];
+        let state = match hex_device_state {
+            "01" => DeviceState::On,
+            "00" => DeviceState::Off,
+            _ if strict => return None,
+            _ => DeviceState::Off, // Default to Off for unknown states
+        };
+
+        // Power consumption (hex positions 270:278 in hex representation)
+        let hex_power = &hex_data[270..278];
+        let power_consumption =
+            u16::from_str_radix(&format!("{}{}", &hex_power[2..4], &hex_power[0..2]), 16);
+        let power_consumption = match power_consumption {
+            Ok(power_consumption) => power_consumption,
+            Err(_) if strict => return None,
+            Err(_) => 0,
+        };
+
+        Some(SwitcherDevice {
+            device_id,
+            device_key,
+            ip_address,
+            mac_address,
+            name,
+            device_type,
+            device_type_code: device_type_hex,
+            state,
+            power_consumption,
+        })
+    }
+}
+
+/// Why a discovery packet didn't produce a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacketRejection {
+    /// No `fe f0` Switcher header, so some other sender's traffic
+    NotSwitcher,
+    /// Switcher-framed, but too short to hold a device type code; holds the length
+    Truncated(usize),
+    /// No registered handler parses this type code (lowercase hex)
+    UnsupportedDeviceType(String),
+    /// A handler matched the type code but rejected the packet, e.g. for its
+    /// length, or in strict mode for a field it would have to default
+    Malformed { type_code: String, len: usize },
+}
+
+impl std::fmt::Display for PacketRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PacketRejection::NotSwitcher => write!(f, "not a Switcher packet"),
+            PacketRejection::Truncated(len) => {
+                write!(
+                    f,
+                    "Switcher packet too short for a device type ({} bytes)",
+                    len
+                )
+            }
+            PacketRejection::UnsupportedDeviceType(type_code) => {
+                write!(f, "unsupported device type {}", type_code)
+            }
+            PacketRejection::Malformed { type_code, len } => write!(
+                f,
+                "device type {} packet did not parse ({} bytes)",
+                type_code, len
+            ),
+        }
+    }
+}
+
+/// The handlers `SwitcherDevice::from_discovery_packet` and discovery
+/// consult, in registration order; the first one that matches a packet's
+/// type code parses it
+pub struct DeviceTypeRegistry {
+    handlers: Vec<Box<dyn DeviceTypeHandler>>,
+}
+
+impl DeviceTypeRegistry {
+    /// A registry with no handlers, which parses nothing
+    pub fn empty() -> Self {
+        Self {
+            handlers: Vec::new(),
+        }
+    }
+
+    pub fn with_handler(mut self, handler: impl DeviceTypeHandler + 'static) -> Self {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    fn handler_for(&self, type_code: &str) -> Option<&dyn DeviceTypeHandler> {
+        self.handlers
+            .iter()
+            .find(|handler| handler.matches(type_code))
+            .map(|handler| handler.as_ref())
+    }
+
+    /// Parse a discovery packet with the handler for its type code
+    pub fn parse(&self, data: &[u8], strict: bool) -> Option<SwitcherDevice> {
+        self.try_parse(data, strict).ok()
+    }
+
+    /// Like `parse`, but say why a packet was dropped
+    pub fn try_parse(&self, data: &[u8], strict: bool) -> Result<SwitcherDevice, PacketRejection> {
+        let type_code = discovery_type_code(data)?;
+        let handler = self
+            .handler_for(&type_code)
+            .ok_or_else(|| PacketRejection::UnsupportedDeviceType(type_code.clone()))?;
+        handler
+            .parse(data, strict)
+            .ok_or(PacketRejection::Malformed {
+                type_code,
+                len: data.len(),
+            })
+    }
+
+    /// Type code of a Switcher-framed discovery packet that no handler
+    /// matches, so it can be reported instead of silently dropped
+    pub fn unsupported_type_code(&self, data: &[u8]) -> Option<String> {
+        discovery_type_code(data)
+            .ok()
+            .filter(|type_code| self.handler_for(type_code).is_none())
+    }
+}
+
+impl Default for DeviceTypeRegistry {
+    /// The built-in handlers
+    fn default() -> Self {
+        Self::empty().with_handler(PowerPlugHandler::new())
+    }
+}
+
+/// The type code of a Switcher-framed discovery packet, as lowercase hex
+fn discovery_type_code(data: &[u8]) -> Result<String, PacketRejection> {
+    if data.len() < 2 || data[0..2] != [0xfe, 0xf0] {
+        return Err(PacketRejection::NotSwitcher);
+    }
+    if data.len() < 76 {
+        return Err(PacketRejection::Truncated(data.len()));
+    }
+    Ok(hex::encode(&data[74..76]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plug_packet(type_code: [u8; 2]) -> Vec<u8> {
+        let mut packet = vec![0; 165];
+        packet[0..2].copy_from_slice(&[0xfe, 0xf0]);
+        packet[42..46].copy_from_slice(b"Plug");
+        packet[74..76].copy_from_slice(&type_code);
+        packet
+    }
+
+    /// Stands in for a third-party handler
+    struct BreakerHandler;
+
+    impl DeviceTypeHandler for BreakerHandler {
+        fn matches(&self, type_code: &str) -> bool {
+            type_code == "0e01"
+        }
+
+        fn parse(&self, data: &[u8], _strict: bool) -> Option<SwitcherDevice> {
+            Some(SwitcherDevice {
+                device_id: hex::encode(&data[18..21]),
+                device_key: None,
+                ip_address: String::new(),
+                mac_address: String::new(),
+                name: "Breaker".to_string(),
+                device_type: "Switcher Breaker".to_string(),
+                device_type_code: "0e01".to_string(),
+                state: DeviceState::Unknown,
+                power_consumption: 0,
+            })
+        }
+    }
+
+    #[test]
+    fn test_device_key_sentinel() {
+        let registry = DeviceTypeRegistry::default();
+        let mut packet = plug_packet([0x01, 0xa8]);
+        assert_eq!(registry.parse(&packet, false).unwrap().device_key, None);
+
+        packet[40] = 0xa1;
+        assert_eq!(
+            registry
+                .parse(&packet, false)
+                .unwrap()
+                .device_key
+                .as_deref(),
+            Some("a1")
+        );
+    }
+
+    #[test]
+    fn test_registry_dispatches_by_type_code() {
+        let registry = DeviceTypeRegistry::default();
+        let breaker = plug_packet([0x0e, 0x01]);
+        assert!(registry.parse(&breaker, false).is_none());
+        assert_eq!(
+            registry.unsupported_type_code(&breaker),
+            Some("0e01".to_string())
+        );
+        assert_eq!(
+            registry
+                .parse(&plug_packet([0x01, 0xa8]), false)
+                .unwrap()
+                .device_type,
+            "Switcher Power Plug"
+        );
+
+        assert_eq!(
+            registry.try_parse(&breaker, false).unwrap_err(),
+            PacketRejection::UnsupportedDeviceType("0e01".to_string())
+        );
+        assert_eq!(
+            registry
+                .try_parse(&plug_packet([0x01, 0xa8])[..100], false)
+                .unwrap_err(),
+            PacketRejection::Malformed {
+                type_code: "01a8".to_string(),
+                len: 100
+            }
+        );
+        assert_eq!(
+            registry
+                .try_parse(&[0xfe, 0xf0, 0x00, 0x00], false)
+                .unwrap_err(),
+            PacketRejection::Truncated(4)
+        );
+        assert_eq!(
+            registry.try_parse(b"M-SEARCH", false).unwrap_err(),
+            PacketRejection::NotSwitcher
+        );
+
+        let registry = registry.with_handler(BreakerHandler);
+        assert_eq!(registry.parse(&breaker, false).unwrap().name, "Breaker");
+        assert_eq!(registry.unsupported_type_code(&breaker), None);
+        assert!(DeviceTypeRegistry::empty()
+            .parse(&plug_packet([0x01, 0xa8]), false)
+            .is_none());
+    }
+
+    #[test]
+    fn test_power_plug_extra_type_codes() {
+        let packet = plug_packet([0x01, 0xa9]);
+        assert!(PowerPlugHandler::new().parse(&packet, true).is_none());
+
+        let device = PowerPlugHandler::with_type_codes(vec!["01a9".to_string()])
+            .parse(&packet, true)
+            .unwrap();
+        assert_eq!(device.device_type, "Switcher Power Plug");
+        assert_eq!(device.device_type_code, "01a9");
+    }
+}
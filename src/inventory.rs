@@ -0,0 +1,165 @@
+use crate::cache::DeviceCache;
+use crate::device::SwitcherDevice;
+use crate::pairing::PairingConfig;
+use crate::utils::current_timestamp;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Which store a known device came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceSource {
+    Paired,
+    Cached,
+    Both,
+}
+
+impl std::fmt::Display for DeviceSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceSource::Paired => write!(f, "paired"),
+            DeviceSource::Cached => write!(f, "cached"),
+            DeviceSource::Both => write!(f, "both"),
+        }
+    }
+}
+
+/// A device from the cache, the pairing config or both, as shown by `list`
+#[derive(Debug, Clone, Serialize)]
+pub struct KnownDevice {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(flatten)]
+    pub device: SwitcherDevice, // from whichever store saw it last
+    pub last_seen: u64,
+    pub source: DeviceSource,
+}
+
+impl KnownDevice {
+    pub fn seen_within(&self, threshold_secs: u64) -> bool {
+        current_timestamp().saturating_sub(self.last_seen) < threshold_secs
+    }
+}
+
+/// Join the cache and the pairing config by device_id, sorted with paired
+/// devices first (by alias) and then cached-only devices by name
+pub fn merge(cache: &DeviceCache, pairing: &PairingConfig) -> Vec<KnownDevice> {
+    let mut known: HashMap<&str, KnownDevice> = cache
+        .devices
+        .iter()
+        .map(|(device_id, cached)| {
+            let device = KnownDevice {
+                alias: None,
+                device: cached.device.clone(),
+                last_seen: cached.last_seen,
+                source: DeviceSource::Cached,
+            };
+            (device_id.as_str(), device)
+        })
+        .collect();
+
+    for (device_id, paired) in &pairing.devices {
+        match known.get_mut(device_id.as_str()) {
+            Some(existing) => {
+                if paired.last_seen > existing.last_seen {
+                    existing.device = paired.device.clone();
+                    existing.last_seen = paired.last_seen;
+                }
+                existing.alias = Some(paired.alias.clone());
+                existing.source = DeviceSource::Both;
+            }
+            None => {
+                known.insert(
+                    device_id.as_str(),
+                    KnownDevice {
+                        alias: Some(paired.alias.clone()),
+                        device: paired.device.clone(),
+                        last_seen: paired.last_seen,
+                        source: DeviceSource::Paired,
+                    },
+                );
+            }
+        }
+    }
+
+    let mut known: Vec<KnownDevice> = known.into_values().collect();
+    known.sort_by(|a, b| {
+        (a.alias.is_none(), &a.alias, &a.device.name).cmp(&(
+            b.alias.is_none(),
+            &b.alias,
+            &b.device.name,
+        ))
+    });
+    known
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::DeviceState;
+    use crate::utils::mock_clock;
+
+    fn create_test_device(id: &str, name: &str, ip: &str) -> SwitcherDevice {
+        SwitcherDevice {
+            device_id: id.to_string(),
+            name: name.to_string(),
+            ip_address: ip.to_string(),
+            mac_address: "00:11:22:33:44:55".to_string(),
+            device_key: Some("a1".to_string()),
+            device_type: "Switcher Power Plug".to_string(),
+            device_type_code: "01a8".to_string(),
+            state: DeviceState::Off,
+            power_consumption: 0,
+        }
+    }
+
+    #[test]
+    fn test_merge_cache_and_pairing() {
+        mock_clock::set(1_000_000);
+        let mut pairing = PairingConfig::new();
+        pairing
+            .pair_device(
+                create_test_device("123", "Heater", "192.168.1.5"),
+                "Heater".to_string(),
+            )
+            .unwrap();
+        pairing
+            .pair_device(
+                create_test_device("456", "Lamp", "192.168.1.6"),
+                "Lamp".to_string(),
+            )
+            .unwrap();
+
+        // The cache saw the heater later, at a new IP
+        mock_clock::advance(60);
+        let mut cache = DeviceCache::new();
+        cache.add_device(create_test_device("123", "Heater", "192.168.1.9"));
+        cache.add_device(create_test_device("789", "Kettle", "192.168.1.7"));
+
+        let known = merge(&cache, &pairing);
+
+        let summary: Vec<_> = known
+            .iter()
+            .map(|known| {
+                (
+                    known.alias.as_deref(),
+                    known.device.ip_address.as_str(),
+                    known.source,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some("Heater"), "192.168.1.9", DeviceSource::Both),
+                (Some("Lamp"), "192.168.1.6", DeviceSource::Paired),
+                (None, "192.168.1.7", DeviceSource::Cached),
+            ]
+        );
+        assert_eq!(known[0].last_seen, 1_000_060);
+
+        let json = serde_json::to_value(&known[0]).unwrap();
+        assert_eq!(json["source"], "both");
+        assert_eq!(json["device_id"], "123");
+    }
+}
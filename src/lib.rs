@@ -1,7 +1,51 @@
+//! Local control of Switcher Power Plugs: discovery, pairing and on/off
+//! control over the LAN, as used by the `switcher-rust` CLI.
+//!
+//! The types most programs need are re-exported here, so
+//! `use switcher_rust::SwitcherController;` works without knowing the
+//! module layout. Everything else stays reachable through its module.
+
+pub mod bulk;
 pub mod cache;
 pub mod config;
 pub mod control;
+#[cfg(feature = "dashboard")]
+pub mod dashboard;
 pub mod device;
 pub mod discovery;
+pub mod error;
+pub mod handler;
+pub mod inventory;
+pub mod metrics;
 pub mod pairing;
+pub mod power;
+pub mod profile;
+pub mod template;
 pub mod utils;
+pub mod webhook;
+
+/// TCP port for login and control packets
+pub use control::SWITCHER_CONTROL_PORT;
+/// UDP port devices broadcast discovery packets on
+pub use discovery::SWITCHER_DISCOVERY_PORT;
+
+/// Persists discovered devices between runs
+pub use cache::CacheManager;
+/// Reads and writes the config file that holds the cache, pairings and settings
+pub use config::ConfigManager;
+/// Ports, timeouts and retries shared by every controller a program creates
+pub use control::ControllerConfig;
+/// Logs in to one device and turns it on or off, or reads its status
+pub use control::SwitcherController;
+/// On, Off or Unknown
+pub use device::DeviceState;
+/// State and power draw read from a device
+pub use device::DeviceStatus;
+/// A device as found by discovery: ID, key, address, name and last state
+pub use device::SwitcherDevice;
+/// Listens for device broadcasts, optionally backed by the cache
+pub use discovery::SwitcherDiscovery;
+/// Error categories that callers can match on and map to exit codes
+pub use error::SwitcherError;
+/// Assigns aliases to devices and stores them in the config
+pub use pairing::PairingManager;
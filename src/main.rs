@@ -1,21 +1,42 @@
-use clap::{Parser, Subcommand};
-use log::{debug, error, info};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
 use std::io::Write;
 use tokio::time::Duration;
+use tokio_stream::StreamExt;
 
+mod bulk;
 mod cache;
 mod config;
 mod control;
+#[cfg(feature = "dashboard")]
+mod dashboard;
 mod device;
 mod discovery;
+mod error;
+mod handler;
+mod inventory;
+mod metrics;
 mod pairing;
+mod power;
+mod profile;
+mod template;
 mod utils;
+mod webhook;
 
+use bulk::{BulkReport, BulkResult};
 use cache::CacheManager;
-use control::SwitcherController;
+use config::ConfigManager;
+use control::{ControllerConfig, SwitcherController};
+use device::DeviceState;
 use discovery::SwitcherDiscovery;
+use error::SwitcherError;
 use pairing::PairingManager;
-use utils::{current_timestamp, format_timestamp};
+use profile::ProtocolProfile;
+use utils::{
+    contains_ignore_case, current_timestamp, env_assignment, format_datetime, format_duration,
+    format_timestamp,
+};
 
 #[derive(Parser)]
 #[command(name = "switcher-rust")]
@@ -29,23 +50,232 @@ struct Cli {
 
     #[arg(long, global = true, help = "Enable debug logging")]
     debug: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Output format"
+    )]
+    format: OutputFormat,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "format",
+        help = "Shorthand for --format json"
+    )]
+    json: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Print JSON on a single line instead of pretty-printed, and discovered devices one per line"
+    )]
+    compact: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Print errors to stderr as {\"error\":{\"kind\":...,\"message\":...}} (implied by --format json)"
+    )]
+    parseable_error: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_parser = control::parse_proxy_url,
+        help = "Reach devices through a SOCKS5 proxy, e.g. socks5://127.0.0.1:1080 (control only, not discovery)"
+    )]
+    proxy: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Open control connections from this local port, for firewalls that filter by source port"
+    )]
+    local_port: Option<u16>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "WINDOW",
+        num_args = 0..=1,
+        default_missing_value = "60s",
+        value_parser = utils::parse_duration,
+        help = "For sleep-capable models: keep retrying the connection for this long (default 60s) to catch the device awake"
+    )]
+    wake_retry: Option<Duration>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "TOML protocol profile overriding response byte offsets and accepted device type codes"
+    )]
+    profile: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Abort the command if it takes longer than this many seconds in total"
+    )]
+    deadline: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        default_value = "local",
+        help = "Show dates in local time or UTC: local or utc"
+    )]
+    timezone: utils::Timezone,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    /// One JSON object per line; bulk commands stream a line per device
+    Ndjson,
+    /// SWITCHER_*=value lines for `eval`; single-device `status` and `get` only
+    Env,
+}
+
+impl OutputFormat {
+    fn is_json(self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Ndjson)
+    }
+}
+
+/// A single value printed by `get`
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum GetField {
+    /// on, off or unknown (queries the device)
+    State,
+    /// Power draw in watts (queries the device)
+    Power,
+    Ip,
+    Mac,
+    Id,
+}
+
+impl GetField {
+    /// Variable name for `--format env`, shared with `status`
+    fn env_name(self) -> &'static str {
+        match self {
+            GetField::State => "SWITCHER_STATE",
+            GetField::Power => "SWITCHER_POWER",
+            GetField::Ip => "SWITCHER_IP",
+            GetField::Mac => "SWITCHER_MAC",
+            GetField::Id => "SWITCHER_DEVICE_ID",
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     Discover {
-        #[arg(short, long, default_value_t = 30)]
-        timeout: u64,
+        #[arg(
+            short,
+            long,
+            help = "Seconds to listen for devices [default: 30, or discovery.timeout]"
+        )]
+        timeout: Option<u64>,
         #[arg(long, help = "Disable device caching")]
         no_cache: bool,
         #[arg(
             long,
-            default_value_t = 3600,
-            help = "Cache timeout in seconds (default: 3600 = 1 hour)"
+            help = "Cache timeout in seconds, 0 for never [default: 3600, or discovery.cache_timeout]"
         )]
-        cache_timeout: u64,
+        cache_timeout: Option<u64>,
         #[arg(long, help = "Only use cached devices, don't scan network")]
         cache_only: bool,
+        #[arg(
+            long,
+            value_parser = discovery::parse_broadcast_address,
+            help = "Also send discovery probes to this broadcast address (e.g. 192.168.5.255); scans are passive without it"
+        )]
+        broadcast_address: Option<std::net::Ipv4Addr>,
+        #[arg(
+            long,
+            help = "Also send a discovery probe to 255.255.255.255 (or --broadcast-address)"
+        )]
+        active: bool,
+        #[arg(
+            long,
+            help = "Print each device using a template, e.g. '{name}\\t{ip}\\t{power}W'"
+        )]
+        output_template: Option<String>,
+        #[arg(
+            long,
+            help = "Store raw discovery packets in the cache and print them (for bug reports)"
+        )]
+        include_raw: bool,
+        #[arg(long, help = "Also list devices of models this tool doesn't support")]
+        show_unsupported: bool,
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u32).range(1..),
+            help = "Run this many back-to-back scans of --timeout seconds and report how often each device was seen"
+        )]
+        count: Option<u32>,
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 0,
+            conflicts_with_all = ["cache_only", "count"],
+            help = "If a scan finds nothing, scan up to N more times with the same timeout"
+        )]
+        retry_on_empty: u32,
+        #[arg(
+            long,
+            value_name = "MS",
+            default_value_t = 0,
+            help = "After a device's first broadcast, wait this long for a second one and use it instead"
+        )]
+        settle: u64,
+        #[arg(
+            long,
+            conflicts_with_all = ["output_template", "include_raw", "count"],
+            help = "Print only the number of devices found; exit 1 if there are none"
+        )]
+        count_only: bool,
+        #[arg(
+            long,
+            help = "Don't refresh paired devices' IP/name/last seen from the scan"
+        )]
+        no_pairing_update: bool,
+        #[arg(long, help = "Read the cache but don't write discovered devices back")]
+        no_cache_write: bool,
+        #[arg(
+            long,
+            help = "Drop devices whose discovery packet has fields that don't parse cleanly"
+        )]
+        strict: bool,
+        #[arg(
+            long,
+            help = "Only show devices whose name or paired alias contains this text (case-insensitive)"
+        )]
+        name_contains: Option<String>,
+        #[arg(long, help = "Only show devices that are already paired")]
+        only_paired: bool,
+        #[arg(
+            long,
+            conflicts_with = "only_paired",
+            help = "Only show devices that still need pairing"
+        )]
+        only_unpaired: bool,
+        #[arg(
+            long,
+            default_value = "device-id",
+            value_parser = discovery::parse_dedupe_key,
+            help = "Merge devices by device-id, or by mac to collapse factory-reset devices"
+        )]
+        dedupe_by: discovery::DedupeKey,
     },
     On {
         #[arg(short, long, help = "Device IP address")]
@@ -54,6 +284,54 @@ enum Commands {
         device_id: Option<String>,
         #[arg(short, long, help = "Paired device alias")]
         alias: Option<String>,
+        #[arg(
+            long,
+            help = "Don't verify the new state (success only means the packet was sent)"
+        )]
+        no_verify: bool,
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u32).range(1..),
+            help = "Send the control packet this many times before verifying"
+        )]
+        repeat: u32,
+        #[arg(
+            long,
+            value_name = "MIN_WATTS",
+            conflicts_with = "no_verify",
+            help = "After turning on, fail if the load draws less than this many watts"
+        )]
+        expect_power: Option<u16>,
+        #[arg(
+            long,
+            value_name = "MIN_WATTS",
+            conflicts_with_all = ["no_verify", "expect_power"],
+            help = "After turning on, poll until the load draws at least this many watts"
+        )]
+        wait_for_power: Option<u16>,
+        #[arg(
+            long,
+            value_name = "SECS",
+            default_value_t = 30,
+            requires = "wait_for_power",
+            help = "Give up on --wait-for-power after this many seconds"
+        )]
+        wait_timeout: u64,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = control::parse_auto_off,
+            conflicts_with_all = ["no_verify", "wait_for_power"],
+            help = "Have the device turn itself off again after this long, e.g. 30m, 1h30m or 90 (seconds)"
+        )]
+        auto_off: Option<Duration>,
+        #[arg(
+            long,
+            value_name = "URL",
+            help = "POST a JSON summary of the outcome to this URL"
+        )]
+        webhook: Option<String>,
     },
     Off {
         #[arg(short, long, help = "Device IP address")]
@@ -62,6 +340,26 @@ enum Commands {
         device_id: Option<String>,
         #[arg(short, long, help = "Paired device alias")]
         alias: Option<String>,
+        #[arg(
+            long,
+            help = "Don't verify the new state (success only means the packet was sent)"
+        )]
+        no_verify: bool,
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u32).range(1..),
+            help = "Send the control packet this many times before verifying"
+        )]
+        repeat: u32,
+        #[arg(
+            long,
+            value_name = "URL",
+            help = "POST a JSON summary of the outcome to this URL"
+        )]
+        webhook: Option<String>,
+        #[arg(long, help = "Turn off even if a countdown timer is running")]
+        force: bool,
     },
     Status {
         #[arg(short, long, help = "Device IP address")]
@@ -70,26 +368,272 @@ enum Commands {
         device_id: Option<String>,
         #[arg(short, long, help = "Paired device alias")]
         alias: Option<String>,
+        #[arg(long, help = "Print a single line like 'on 12W' for status bars")]
+        oneline: bool,
+    },
+    #[command(
+        about = "Print one field of a device with no decoration, for scripts",
+        long_about = "Print one field of a device with no decoration, for scripts. Exits non-zero with the error on stderr if the value can't be read."
+    )]
+    Get {
+        #[arg(value_enum)]
+        field: GetField,
+        #[arg(short, long, help = "Device IP address")]
+        ip: Option<String>,
+        #[arg(short, long, help = "Device ID")]
+        device_id: Option<String>,
+        #[arg(short, long, help = "Paired device alias")]
+        alias: Option<String>,
+    },
+    #[command(about = "Turn on every paired device")]
+    OnAll {
+        #[command(flatten)]
+        bulk: BulkArgs,
+    },
+    #[command(about = "Turn off every paired device")]
+    OffAll {
+        #[command(flatten)]
+        bulk: BulkArgs,
+    },
+    #[command(about = "Show the status of every paired device")]
+    StatusAll {
+        #[command(flatten)]
+        bulk: BulkArgs,
+        #[arg(long, help = "Print the combined wattage of the devices that are on")]
+        show_power_total: bool,
+    },
+    Details {
+        #[arg(short, long, help = "Device IP address")]
+        ip: Option<String>,
+        #[arg(short, long, help = "Device ID")]
+        device_id: Option<String>,
+        #[arg(short, long, help = "Paired device alias")]
+        alias: Option<String>,
+    },
+    #[command(about = "Show or change the persistent auto-shutdown duration")]
+    AutoShutdown {
+        #[arg(short, long, help = "Device IP address")]
+        ip: Option<String>,
+        #[arg(short, long, help = "Device ID")]
+        device_id: Option<String>,
+        #[arg(short, long, help = "Paired device alias")]
+        alias: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "set",
+            required_unless_present = "set",
+            help = "Print the current auto-shutdown duration"
+        )]
+        get: bool,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = control::parse_auto_shutdown,
+            help = "Set the auto-shutdown duration, from 1h to 23h59m (e.g. 2h30m)"
+        )]
+        set: Option<Duration>,
+    },
+    #[command(about = "Summarize the power readings recorded by status and status-all")]
+    PowerHistory {
+        #[arg(short, long, help = "Device ID")]
+        device_id: Option<String>,
+        #[arg(short, long, help = "Paired device alias")]
+        alias: Option<String>,
+        #[arg(
+            long,
+            value_parser = utils::parse_duration,
+            help = "Only readings newer than this long ago, e.g. 12h [default: all]"
+        )]
+        since: Option<Duration>,
+        #[arg(
+            long,
+            value_parser = utils::parse_duration,
+            help = "Only readings older than this long ago, e.g. 1h [default: now]"
+        )]
+        until: Option<Duration>,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Write the readings to a CSV file (timestamp_iso,watts) instead of summarizing them"
+        )]
+        export: Option<std::path::PathBuf>,
+    },
+    Diagnose {
+        #[arg(short, long, help = "Device IP address")]
+        ip: Option<String>,
+        #[arg(short, long, help = "Device ID")]
+        device_id: Option<String>,
+        #[arg(short, long, help = "Paired device alias")]
+        alias: Option<String>,
+        #[arg(
+            long,
+            num_args = 0..,
+            value_delimiter = ',',
+            help = "Try logging in on these control ports (default: 9957,10000)"
+        )]
+        probe_port: Option<Vec<u16>>,
     },
     ClearCache {
         #[arg(long, help = "Clear cache without confirmation")]
         force: bool,
     },
+    Prune {
+        #[arg(
+            long,
+            default_value_t = 7200,
+            help = "Remove cached devices not seen for this many seconds (0 removes nothing)"
+        )]
+        max_age: u64,
+    },
+    #[command(about = "Copy the latest IPs and names from the cache into paired devices")]
+    Reconcile {
+        #[arg(
+            long,
+            default_value_t = 3600,
+            help = "Only use cached devices seen within this many seconds"
+        )]
+        max_age: u64,
+    },
     Pair {
         #[arg(short, long, help = "Device ID to pair")]
         device_id: String,
         #[arg(short, long, help = "Friendly alias for the device")]
         alias: String,
     },
+    #[command(about = "Discover devices and pair the unpaired ones in one session")]
+    Adopt {
+        #[arg(
+            short,
+            long,
+            default_value_t = 10,
+            help = "Seconds to listen for devices"
+        )]
+        timeout: u64,
+        #[arg(
+            short,
+            long,
+            help = "Pair every unpaired device under its device name without prompting"
+        )]
+        yes: bool,
+    },
+    #[command(about = "Point a paired alias at a new device ID, e.g. after a factory reset")]
+    Rekey {
+        #[arg(short, long, help = "Alias of the paired device to update")]
+        alias: String,
+        #[arg(
+            short,
+            long,
+            help = "New device ID (must be in the cache or discoverable)"
+        )]
+        device_id: String,
+    },
     Unpair {
         #[arg(short, long, help = "Alias of the paired device to remove")]
         alias: String,
         #[arg(long, help = "Remove without confirmation")]
         force: bool,
     },
+    SetDefault {
+        #[arg(short, long, help = "Alias of the paired device to use by default")]
+        alias: String,
+    },
+    #[command(about = "Color a paired device's alias in list-paired and the dashboard")]
+    SetColor {
+        #[arg(short, long, help = "Alias of the paired device")]
+        alias: String,
+        #[arg(
+            short,
+            long,
+            required_unless_present = "clear",
+            help = "red, green, yellow, blue, magenta or cyan"
+        )]
+        color: Option<pairing::LabelColor>,
+        #[arg(long, conflicts_with = "color", help = "Go back to the default color")]
+        clear: bool,
+    },
+    #[command(
+        about = "View or change persistent settings, e.g. 'config set discovery.timeout 15'"
+    )]
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
     ListPaired {
         #[arg(long, help = "Show detailed information")]
         verbose: bool,
+        #[arg(
+            long,
+            help = "Print each device using a template, e.g. '{alias}\\t{ip}\\t{state}'"
+        )]
+        output_template: Option<String>,
+        #[arg(
+            long,
+            value_parser = utils::parse_duration,
+            help = "How recently a device must have been seen to count as online, e.g. 30m or 1h30m [default: 1h]"
+        )]
+        online_threshold: Option<Duration>,
+        #[arg(long, help = "Hide devices not seen within the online threshold")]
+        fresh_only: bool,
+        #[arg(
+            long,
+            help = "Only show devices whose alias or name contains this text (case-insensitive)"
+        )]
+        name_contains: Option<String>,
+    },
+    #[command(about = "Every known device from the cache and the pairing config")]
+    List {
+        #[arg(
+            long,
+            value_parser = utils::parse_duration,
+            help = "How recently a device must have been seen to count as online, e.g. 30m or 1h30m [default: 1h]"
+        )]
+        online_threshold: Option<Duration>,
+        #[arg(long, help = "Hide devices not seen within the online threshold")]
+        fresh_only: bool,
+    },
+    #[command(
+        about = "Power and state of every paired device as Prometheus metrics",
+        long_about = "Query every paired device at once and print power and state in the Prometheus text format, e.g. for a textfile collector or a wrapper HTTP endpoint. Series are labelled with alias and device_id; switcher_up is 0 for devices that didn't answer."
+    )]
+    Metrics,
+    #[command(
+        about = "State and power of every device from one short discovery scan, without connecting to any"
+    )]
+    Snapshot {
+        #[arg(
+            short,
+            long,
+            default_value_t = 5,
+            help = "Seconds to listen for broadcasts"
+        )]
+        timeout: u64,
+    },
+    #[command(
+        about = "Listen for broadcasts over a longer period and report how regularly each device sends them"
+    )]
+    DiscoveryProbe {
+        #[arg(
+            short,
+            long,
+            default_value_t = 5,
+            value_parser = clap::value_parser!(u64).range(1..),
+            help = "Minutes to listen for"
+        )]
+        duration: u64,
+    },
+    #[cfg(feature = "schema")]
+    #[command(about = "Print a JSON Schema for switcher_config.json")]
+    Schema,
+    #[cfg(feature = "dashboard")]
+    #[command(about = "Live terminal dashboard of paired devices")]
+    Dashboard {
+        #[arg(
+            long,
+            default_value = "5s",
+            value_parser = utils::parse_duration,
+            help = "How often to poll each device, e.g. 5s or 1m"
+        )]
+        interval: Duration,
     },
     Rename {
         #[arg(short, long, help = "Device IP address")]
@@ -103,12 +647,85 @@ enum Commands {
     },
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+fn main() {
+    // Usage errors get their own exit code so scripts know not to retry
+    let mut cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        // The flags weren't parsed, so look for --parseable-error by hand
+        Err(e) if e.use_stderr() && std::env::args().any(|arg| arg == "--parseable-error") => {
+            let message = e.to_string();
+            let first_line = message.lines().next().unwrap_or_default();
+            let usage = SwitcherError::Usage(first_line.trim_start_matches("error: ").to_string());
+            eprintln!("{}", error::envelope(&usage));
+            std::process::exit(error::EXIT_USAGE);
+        }
+        Err(e) if e.use_stderr() => {
+            let _ = e.print();
+            std::process::exit(error::EXIT_USAGE);
+        }
+        Err(e) => e.exit(),
+    };
+    if cli.json {
+        cli.format = OutputFormat::Json;
+    }
+    let parseable_errors = cli.parseable_error || cli.format.is_json();
+
+    // Reading the local UTC offset is only sound while single-threaded, so
+    // it happens before the runtime starts its worker threads
+    utils::set_timezone(cli.timezone);
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start the async runtime");
+
+    // One hard bound over every connect, retry and re-login a command does
+    let result = runtime.block_on(async {
+        match cli.deadline {
+            Some(secs) => tokio::time::timeout(Duration::from_secs(secs), run(cli))
+                .await
+                .unwrap_or_else(|_| Err(SwitcherError::DeadlineExceeded(secs).into())),
+            None => run(cli).await,
+        }
+    });
+    if let Err(e) = result {
+        if parseable_errors {
+            eprintln!("{}", error::envelope(e.as_ref()));
+        } else {
+            eprintln!("Error: {}", e);
+        }
+        std::process::exit(error::exit_code(e.as_ref()));
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    // Initialize logging based on CLI flags. The dashboard owns the terminal
+    // and `get`/`metrics`/`discover --count-only` output is parsed by scripts
+    // and scrapers, so these only log to the file.
+    let console_logging = match cli.command {
+        #[cfg(feature = "dashboard")]
+        Commands::Dashboard { .. } => false,
+        Commands::Get { .. }
+        | Commands::Metrics
+        | Commands::Discover {
+            count_only: true, ..
+        } => false,
+        _ => true,
+    };
+    // Machine-readable runs keep stdout for output only
+    let parseable_errors = cli.parseable_error || cli.format.is_json();
+    init_logging(
+        cli.verbose,
+        cli.debug,
+        console_logging,
+        parseable_errors || cli.format == OutputFormat::Env,
+    );
 
-    // Initialize logging based on CLI flags
-    init_logging(cli.verbose, cli.debug);
+    // Several devices' variables would overwrite each other
+    if cli.format == OutputFormat::Env
+        && !matches!(cli.command, Commands::Status { .. } | Commands::Get { .. })
+    {
+        return Err(SwitcherError::Usage(
+            "--format env only applies to status and get".to_string(),
+        )
+        .into());
+    }
 
     info!("Starting switcher-rust CLI");
     debug!(
@@ -116,13 +733,60 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         cli.verbose, cli.debug
     );
 
+    // ndjson is line-oriented, so single-object output is never pretty-printed
+    let compact = cli.compact || cli.format == OutputFormat::Ndjson;
+
+    let profile = match &cli.profile {
+        Some(path) => ProtocolProfile::load(path)?,
+        None => ProtocolProfile::default(),
+    };
+
+    // Shared by every controller a command creates
+    let controller_config = ControllerConfig {
+        proxy: cli.proxy.clone(),
+        local_port: cli.local_port,
+        wake_window: cli.wake_retry,
+        layout: cli.profile.is_some().then(|| profile.status.clone()),
+        ..ControllerConfig::default()
+    };
+
     match cli.command {
         Commands::Discover {
             timeout,
             no_cache,
             cache_timeout,
             cache_only,
+            broadcast_address,
+            active,
+            output_template,
+            include_raw,
+            show_unsupported,
+            count,
+            retry_on_empty,
+            settle,
+            count_only,
+            no_pairing_update,
+            no_cache_write,
+            strict,
+            name_contains,
+            only_paired,
+            only_unpaired,
+            dedupe_by,
         } => {
+            if let Some(template) = &output_template {
+                if let Err(e) = template::validate(template, template::DEVICE_PLACEHOLDERS) {
+                    return Err(SwitcherError::Usage(e.to_string()).into());
+                }
+            }
+
+            let settings = ConfigManager::new()
+                .and_then(|config_manager| config_manager.load_settings())
+                .unwrap_or_default()
+                .discovery;
+            let timeout = timeout.unwrap_or(settings.timeout);
+            let cache_timeout = cache_timeout.unwrap_or(settings.cache_timeout);
+            let broadcast_address = broadcast_address.or(settings.broadcast_address);
+
             info!("Starting device discovery - timeout: {}s, no_cache: {}, cache_timeout: {}s, cache_only: {}",
                   timeout, no_cache, cache_timeout, cache_only);
 
@@ -136,18 +800,226 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 );
                 SwitcherDiscovery::with_cache_settings(!no_cache, cache_timeout)
             };
+            let discovery = match broadcast_address {
+                Some(address) => discovery.with_broadcast_address(address),
+                None => discovery,
+            };
+            let discovery = if active {
+                discovery.with_active_probe()
+            } else {
+                discovery
+            };
+            let discovery = if include_raw {
+                discovery.with_raw_capture()
+            } else {
+                discovery
+            };
+            let discovery = if no_pairing_update {
+                discovery.without_pairing_update()
+            } else {
+                discovery
+            };
+            let discovery = if no_cache_write {
+                discovery.without_cache_write()
+            } else {
+                discovery
+            };
+            let discovery = if strict {
+                discovery.with_strict_parsing()
+            } else {
+                discovery
+            };
+            let discovery = discovery
+                .with_dedupe_key(dedupe_by)
+                .with_settle(Duration::from_millis(settle))
+                .with_profile(&profile)
+                .with_save_on_interrupt();
+
+            if let Some(count) = count {
+                let mut scans = Vec::with_capacity(count as usize);
+                for round in 1..=count {
+                    if cli.format == OutputFormat::Text {
+                        println!("🔍 Scan {}/{} ({}s)...", round, count, timeout);
+                    }
+                    scans.push(
+                        discovery
+                            .discover_network(Duration::from_secs(timeout))
+                            .await?,
+                    );
+                }
 
-            let devices = if cache_only {
+                let presence = discovery::tally_scans(&scans);
+                if cli.format.is_json() {
+                    let report: Vec<_> = presence
+                        .iter()
+                        .map(|presence| {
+                            serde_json::json!({
+                                "device_id": presence.device.device_id,
+                                "name": presence.device.name,
+                                "ip_address": presence.device.ip_address,
+                                "seen": presence.seen,
+                                "scans": presence.scans,
+                                "stability": presence.stability(),
+                            })
+                        })
+                        .collect();
+                    print_json(&report, compact)?;
+                } else if presence.is_empty() {
+                    println!("❌ No devices seen in {} scans", count);
+                } else {
+                    println!("\n📶 Discovery stability over {} scans:", count);
+                    for presence in &presence {
+                        let flaky = if presence.seen < presence.scans {
+                            " — flaky"
+                        } else {
+                            ""
+                        };
+                        println!(
+                            "  • {} ({}) seen {}/{} scans ({:.0}%){}",
+                            presence.device.name,
+                            presence.device.ip_address,
+                            presence.seen,
+                            presence.scans,
+                            presence.stability(),
+                            flaky
+                        );
+                    }
+                }
+                return Ok(());
+            }
+
+            let mut devices = if cache_only {
                 info!("Attempting cache-only discovery");
                 discovery.discover_from_cache_only()?
             } else {
                 info!("Starting network discovery for {} seconds", timeout);
-                discovery.discover(Duration::from_secs(timeout)).await?
+                let mut devices = discovery.discover(Duration::from_secs(timeout)).await?;
+                // A single scan can fall between a device's broadcasts
+                for retry in 1..=retry_on_empty {
+                    if !devices.is_empty() {
+                        break;
+                    }
+                    info!("No devices found, retry {}/{}", retry, retry_on_empty);
+                    if cli.format == OutputFormat::Text && output_template.is_none() && !count_only
+                    {
+                        println!(
+                            "🔍 No devices yet, retry {}/{} ({}s)...",
+                            retry, retry_on_empty, timeout
+                        );
+                    }
+                    devices = discovery.discover(Duration::from_secs(timeout)).await?;
+                }
+                devices
             };
 
             info!("Discovery completed - found {} devices", devices.len());
 
-            if devices.is_empty() {
+            if let Some(needle) = &name_contains {
+                let pairing = PairingManager::new()
+                    .ok()
+                    .and_then(|pm| pm.load_pairing().ok());
+                let found = devices.len();
+                devices.retain(|device| {
+                    contains_ignore_case(&device.name, needle)
+                        || pairing
+                            .as_ref()
+                            .and_then(|p| p.devices.get(&device.device_id))
+                            .is_some_and(|paired| contains_ignore_case(&paired.alias, needle))
+                });
+                if devices.is_empty() && found > 0 && output_template.is_none() && !count_only {
+                    println!("🔍 No devices matched '{}' ({} found)", needle, found);
+                    return Ok(());
+                }
+            }
+
+            if only_paired || only_unpaired {
+                let pairing = load_pairing_config()?;
+                let found = devices.len();
+                devices.retain(|device| {
+                    pairing.devices.contains_key(&device.device_id) == only_paired
+                });
+                if devices.is_empty()
+                    && found > 0
+                    && cli.format == OutputFormat::Text
+                    && output_template.is_none()
+                    && !count_only
+                {
+                    if only_paired {
+                        println!("🔍 None of the {} devices found are paired", found);
+                    } else {
+                        println!("✅ All {} devices found are already paired", found);
+                    }
+                    return Ok(());
+                }
+            }
+
+            // Health checks: just the number, and the exit code says whether any answered
+            if count_only {
+                if cli.format.is_json() {
+                    print_json(&serde_json::json!({ "count": devices.len() }), compact)?;
+                } else {
+                    println!("{}", devices.len());
+                }
+                if devices.is_empty() {
+                    std::process::exit(error::EXIT_FAILURE);
+                }
+                return Ok(());
+            }
+
+            // Raw packets from this scan, falling back to those stored in the cache
+            let raw_packets: HashMap<String, String> = if include_raw {
+                let mut raw_packets: HashMap<String, String> = CacheManager::new()
+                    .and_then(|cache_manager| cache_manager.load_cache())
+                    .map(|cache| {
+                        cache
+                            .devices
+                            .into_iter()
+                            .filter_map(|(id, cached)| cached.raw_packet.map(|raw| (id, raw)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                raw_packets.extend(discovery.raw_packets());
+                raw_packets
+            } else {
+                HashMap::new()
+            };
+
+            if let Some(template) = &output_template {
+                let pairing = PairingManager::new()
+                    .ok()
+                    .and_then(|pm| pm.load_pairing().ok());
+
+                for device in &devices {
+                    let alias = pairing
+                        .as_ref()
+                        .and_then(|p| p.devices.get(&device.device_id))
+                        .map(|paired_device| paired_device.alias.as_str());
+                    let fields = template::device_fields(device, alias);
+                    println!("{}", template::render(template, &fields)?);
+                }
+            } else if cli.format.is_json() {
+                let pairing = PairingManager::new()
+                    .ok()
+                    .and_then(|pm| pm.load_pairing().ok());
+                let mut entries = Vec::with_capacity(devices.len());
+                for device in &devices {
+                    let mut entry = device_json(device)?;
+                    if let Some(paired) = pairing
+                        .as_ref()
+                        .and_then(|p| p.devices.get(&device.device_id))
+                    {
+                        entry["alias"] = paired.alias.as_str().into();
+                    }
+                    entries.push(entry);
+                }
+                print_json(&entries, compact)?;
+
+                // Kept off stdout so it stays a plain array of devices
+                let moved = discovery.moved_devices();
+                if !moved.is_empty() {
+                    eprintln!("{}", serde_json::json!({ "moved": moved }));
+                }
+            } else if devices.is_empty() {
                 println!(
                     "❌ No devices found. Make sure your Switcher devices are on the same network."
                 );
@@ -173,18 +1045,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             "[NOT PAIRED]".to_string()
                         });
 
+                    if compact {
+                        println!("  • {} {}", device, pairing_status);
+                        if let Some(raw_packet) = raw_packets.get(&device.device_id) {
+                            println!("    Raw: {}", raw_packet);
+                        }
+                        continue;
+                    }
                     println!(
                         "  • {} ({}) {}",
                         device.name, device.ip_address, pairing_status
                     );
                     println!(
                         "    ID: {}, Key: {}, MAC: {}",
-                        device.device_id, device.device_key, device.mac_address
+                        device.device_id,
+                        device.device_key.as_deref().unwrap_or("none"),
+                        device.mac_address
                     );
                     println!(
                         "    State: {:?}, Power: {}W",
                         device.state, device.power_consumption
                     );
+                    if cli.verbose {
+                        println!(
+                            "    Type: {} ({})",
+                            device.device_type, device.device_type_code
+                        );
+                    }
+                    if let Some(raw_packet) = raw_packets.get(&device.device_id) {
+                        println!("    Raw: {}", raw_packet);
+                    }
+                    println!();
+                }
+                if compact {
                     println!();
                 }
 
@@ -200,37 +1093,197 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     println!();
                 }
             }
+
+            let moved = discovery.moved_devices();
+            if cli.format == OutputFormat::Text && output_template.is_none() && !moved.is_empty() {
+                let pairing = PairingManager::new()
+                    .ok()
+                    .and_then(|pm| pm.load_pairing().ok());
+                for device in &moved {
+                    let name = pairing
+                        .as_ref()
+                        .and_then(|p| p.devices.get(&device.device_id))
+                        .map_or(device.name.as_str(), |paired| paired.alias.as_str());
+                    println!(
+                        "📍 Device '{}' moved from {} to {}",
+                        name, device.old_ip, device.new_ip
+                    );
+                }
+                println!();
+            }
+
+            // Always shown: a shared device_id makes commands hit either device
+            for duplicate in discovery.duplicate_device_ids() {
+                let line = format!(
+                    "Warning: device_id {} seen from two devices: {} (MAC {}) and {} (MAC {})",
+                    duplicate.device_id,
+                    duplicate.first_ip,
+                    duplicate.first_mac,
+                    duplicate.other_ip,
+                    duplicate.other_mac
+                );
+                if cli.format == OutputFormat::Text && output_template.is_none() {
+                    println!("⚠️  {}", line);
+                } else {
+                    eprintln!("{}", line);
+                }
+            }
+
+            if show_unsupported {
+                // Keep stdout clean for template output
+                for device in discovery.unsupported_devices() {
+                    let line = format!(
+                        "Unsupported device at {} (type {})",
+                        device.ip_address, device.device_type_code
+                    );
+                    if output_template.is_some() {
+                        eprintln!("{}", line);
+                    } else {
+                        println!("⚠️  {}", line);
+                    }
+                }
+            }
         }
         Commands::On {
             ip,
             device_id,
             alias,
+            no_verify,
+            repeat,
+            expect_power,
+            wait_for_power,
+            wait_timeout,
+            auto_off,
+            webhook,
         } => {
+            // Both flags set a minimum draw; --wait-for-power also polls for it
+            let min_power = wait_for_power.or(expect_power);
             info!(
                 "Turning device ON - ip: {:?}, device_id: {:?}, alias: {:?}",
                 ip, device_id, alias
             );
+            let webhook_alias = alias.clone();
             match resolve_device_info(ip, device_id, alias).await {
                 Ok((resolved_ip, resolved_device_id)) => {
                     debug!(
                         "Resolved device info - ip: {}, device_id: {}",
                         resolved_ip, resolved_device_id
                     );
-                    let controller = SwitcherController::new(resolved_ip, resolved_device_id);
-                    match controller.turn_on().await {
-                        Ok(_) => {
+                    let webhook_device_id = resolved_device_id.clone();
+                    let result = with_ip_fallback(
+                        SwitcherController::with_config(
+                            resolved_ip,
+                            resolved_device_id,
+                            ControllerConfig {
+                                repeat,
+                                ..controller_config.clone()
+                            },
+                        ),
+                        |controller| async move {
+                            if no_verify {
+                                controller
+                                    .send_command_unverified(DeviceState::On)
+                                    .await
+                                    .map(|_| None)
+                            } else if let Some(min_watts) = wait_for_power {
+                                controller
+                                    .turn_on_and_wait_for_power(
+                                        min_watts,
+                                        Duration::from_secs(wait_timeout),
+                                    )
+                                    .await
+                                    .map(Some)
+                            } else {
+                                match auto_off {
+                                    Some(duration) => {
+                                        controller.turn_on_with_timer(duration).await?
+                                    }
+                                    None => controller.turn_on().await?,
+                                }
+                                match expect_power {
+                                    Some(_) => controller.get_status().await.map(Some),
+                                    None => Ok(None),
+                                }
+                            }
+                        },
+                    )
+                    .await;
+                    if let Some(url) = &webhook {
+                        let event = webhook::CommandEvent::new(
+                            webhook_alias,
+                            webhook_device_id,
+                            DeviceState::On,
+                            !no_verify,
+                            &result,
+                        );
+                        webhook::notify(url, &event).await;
+                    }
+                    match result {
+                        Ok(_) if no_verify => {
+                            info!("Sent ON command without verification");
+                            println!("📤 ON command sent (not verified)");
+                        }
+                        Ok(Some(status))
+                            if status.power_consumption < min_power.unwrap_or_default() =>
+                        {
+                            warn!(
+                                "Device is on but drawing {}W, expected at least {}W",
+                                status.power_consumption,
+                                min_power.unwrap_or_default()
+                            );
+                            let e: Box<dyn std::error::Error> = if wait_for_power.is_some() {
+                                format!(
+                                    "Device turned ON but the load never reached {}W within {}s (last reading {}W)",
+                                    min_power.unwrap_or_default(),
+                                    wait_timeout,
+                                    status.power_consumption
+                                )
+                                .into()
+                            } else {
+                                format!(
+                                    "Device turned ON but is drawing {}W (expected at least {}W)",
+                                    status.power_consumption,
+                                    min_power.unwrap_or_default()
+                                )
+                                .into()
+                            };
+                            exit_with_error(
+                                format!(
+                                    "⚠️  {}\n   Check that the appliance is plugged in and switched on",
+                                    e
+                                ),
+                                e.as_ref(),
+                                parseable_errors,
+                            );
+                        }
+                        Ok(Some(status)) => {
+                            info!(
+                                "Successfully turned device ON, drawing {}W",
+                                status.power_consumption
+                            );
+                            println!(
+                                "✅ Device turned ON ({}W){}",
+                                status.power_consumption,
+                                auto_off_note(auto_off)
+                            );
+                        }
+                        Ok(None) => {
                             info!("Successfully turned device ON");
-                            println!("✅ Device turned ON");
+                            println!("✅ Device turned ON{}", auto_off_note(auto_off));
                         }
                         Err(e) => {
                             error!("Failed to turn device on: {}", e);
-                            println!("❌ Failed to turn device on: {}", e);
+                            exit_with_error(
+                                format!("❌ Failed to turn device on: {}", e),
+                                &e,
+                                parseable_errors,
+                            );
                         }
                     }
                 }
                 Err(e) => {
                     error!("Failed to resolve device info: {}", e);
-                    println!("❌ {}", e);
+                    exit_with_error(format!("❌ {}", e), e.as_ref(), parseable_errors);
                 }
             }
         }
@@ -238,32 +1291,96 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             ip,
             device_id,
             alias,
+            no_verify,
+            repeat,
+            webhook,
+            force,
         } => {
             info!(
                 "Turning device OFF - ip: {:?}, device_id: {:?}, alias: {:?}",
                 ip, device_id, alias
             );
+            let webhook_alias = alias.clone();
             match resolve_device_info(ip, device_id, alias).await {
                 Ok((resolved_ip, resolved_device_id)) => {
                     debug!(
                         "Resolved device info - ip: {}, device_id: {}",
                         resolved_ip, resolved_device_id
                     );
-                    let controller = SwitcherController::new(resolved_ip, resolved_device_id);
-                    match controller.turn_off().await {
+                    let webhook_device_id = resolved_device_id.clone();
+                    let controller = SwitcherController::with_config(
+                        resolved_ip,
+                        resolved_device_id,
+                        ControllerConfig {
+                            repeat,
+                            ..controller_config.clone()
+                        },
+                    );
+
+                    // Turning off mid-countdown silently cancels the timer, so
+                    // unless --force, check for one on the same session first.
+                    // Ok(Some(secs)) means the command was not sent.
+                    let result = with_ip_fallback(controller, |controller| async move {
+                        let mut session = controller.open_session().await?;
+                        if !force {
+                            let details = session.get_details().await?;
+                            let remaining = details.remaining_seconds.filter(|&secs| secs > 0);
+                            if let (DeviceState::On, Some(secs)) = (details.state, remaining) {
+                                return Ok(Some(secs));
+                            }
+                        }
+                        if no_verify {
+                            session.send_unverified(DeviceState::Off).await?;
+                        } else {
+                            session.turn_off().await?;
+                        }
+                        Ok(None)
+                    })
+                    .await;
+                    if let Ok(Some(secs)) = result {
+                        warn!("Refusing to turn off, timer has {}s left", secs);
+                        let e = SwitcherError::Usage(format!(
+                            "A timer is running ({} left); turning the device off cancels it",
+                            format_duration(secs.into())
+                        ));
+                        exit_with_error(
+                            format!("⚠️  {}\n   Re-run with --force to turn it off anyway", e),
+                            &e,
+                            parseable_errors,
+                        );
+                    }
+                    if let Some(url) = &webhook {
+                        let event = webhook::CommandEvent::new(
+                            webhook_alias,
+                            webhook_device_id,
+                            DeviceState::Off,
+                            !no_verify,
+                            &result,
+                        );
+                        webhook::notify(url, &event).await;
+                    }
+                    match result {
+                        Ok(_) if no_verify => {
+                            info!("Sent OFF command without verification");
+                            println!("📤 OFF command sent (not verified)");
+                        }
                         Ok(_) => {
                             info!("Successfully turned device OFF");
                             println!("✅ Device turned OFF");
                         }
                         Err(e) => {
                             error!("Failed to turn device off: {}", e);
-                            println!("❌ Failed to turn device off: {}", e);
+                            exit_with_error(
+                                format!("❌ Failed to turn device off: {}", e),
+                                &e,
+                                parseable_errors,
+                            );
                         }
                     }
                 }
                 Err(e) => {
                     error!("Failed to resolve device info: {}", e);
-                    println!("❌ {}", e);
+                    exit_with_error(format!("❌ {}", e), e.as_ref(), parseable_errors);
                 }
             }
         }
@@ -271,45 +1388,444 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             ip,
             device_id,
             alias,
+            oneline,
         } => {
             info!(
                 "Getting device status - ip: {:?}, device_id: {:?}, alias: {:?}",
                 ip, device_id, alias
             );
-            match resolve_device_info(ip, device_id, alias).await {
+            let prefix = alias
+                .as_ref()
+                .map(|alias| format!("{}: ", alias))
+                .unwrap_or_default();
+            // Errors go to stderr so `eval` never runs them
+            if cli.format == OutputFormat::Env {
+                let (resolved_ip, resolved_device_id) =
+                    resolve_device_info(ip, device_id, alias.clone()).await?;
+                let status = with_ip_fallback(
+                    SwitcherController::with_config(
+                        resolved_ip.clone(),
+                        resolved_device_id.clone(),
+                        controller_config.clone(),
+                    ),
+                    |controller| async move { controller.get_status().await },
+                )
+                .await?;
+                record_power_samples(&[(
+                    resolved_device_id.clone(),
+                    status.power_consumption,
+                    current_timestamp(),
+                )]);
+                let mut variables = vec![
+                    (GetField::State.env_name(), status.state.to_string()),
+                    (
+                        GetField::Power.env_name(),
+                        status.power_consumption.to_string(),
+                    ),
+                    (GetField::Ip.env_name(), resolved_ip),
+                    (GetField::Id.env_name(), resolved_device_id),
+                ];
+                if let Some(alias) = alias {
+                    variables.push(("SWITCHER_ALIAS", alias));
+                }
+                for (name, value) in variables {
+                    println!("{}", env_assignment(name, &value));
+                }
+                return Ok(());
+            }
+            match resolve_device_info(ip, device_id, alias.clone()).await {
                 Ok((resolved_ip, resolved_device_id)) => {
                     debug!(
                         "Resolved device info - ip: {}, device_id: {}",
                         resolved_ip, resolved_device_id
                     );
-                    let controller = SwitcherController::new(resolved_ip, resolved_device_id);
-                    match controller.get_status().await {
+                    match with_ip_fallback(
+                        SwitcherController::with_config(
+                            resolved_ip.clone(),
+                            resolved_device_id.clone(),
+                            controller_config.clone(),
+                        ),
+                        |controller| async move { controller.get_status().await },
+                    )
+                    .await
+                    {
                         Ok(state) => {
                             info!(
                                 "Successfully retrieved device status - state: {:?}, power: {}W",
                                 state.state, state.power_consumption
                             );
-                            println!("📊 Device Status:");
-                            println!("  State: {:?}", state.state);
-                            println!("  Power: {}W", state.power_consumption);
+                            record_power_samples(&[(
+                                resolved_device_id.clone(),
+                                state.power_consumption,
+                                current_timestamp(),
+                            )]);
+                            if cli.format.is_json() {
+                                let mut report = serde_json::to_value(&state)?;
+                                report["device_id"] = resolved_device_id.into();
+                                report["ip_address"] = resolved_ip.into();
+                                if let Some(alias) = &alias {
+                                    report["alias"] = alias.as_str().into();
+                                }
+                                print_json(&report, compact)?;
+                            } else if oneline {
+                                println!("{}{} {}W", prefix, state.state, state.power_consumption);
+                            } else {
+                                println!("📊 Device Status:");
+                                println!("  State: {:?}", state.state);
+                                println!("  Power: {}W", state.power_consumption);
+                            }
                         }
                         Err(e) => {
                             error!("Failed to get device status: {}", e);
-                            println!("❌ Failed to get status: {}", e);
+                            exit_with_error(
+                                format!("❌ Failed to get status: {}", e),
+                                &e,
+                                parseable_errors,
+                            );
                         }
                     }
                 }
                 Err(e) => {
                     error!("Failed to resolve device info: {}", e);
-                    println!("❌ {}", e);
+                    exit_with_error(format!("❌ {}", e), e.as_ref(), parseable_errors);
+                }
+            }
+        }
+        Commands::Get {
+            field,
+            ip,
+            device_id,
+            alias,
+        } => {
+            let (resolved_ip, resolved_device_id) =
+                resolve_device_info(ip, device_id, alias).await?;
+            let value = match field {
+                GetField::Ip => resolved_ip,
+                GetField::Id => resolved_device_id,
+                GetField::Mac => {
+                    let paired = load_pairing_config()?
+                        .devices
+                        .get(&resolved_device_id)
+                        .map(|paired| paired.device.mac_address.clone());
+                    let cached = || {
+                        CacheManager::new()
+                            .and_then(|cache_manager| cache_manager.load_cache())
+                            .ok()?
+                            .get_device(&resolved_device_id)
+                            .map(|device| device.mac_address.clone())
+                    };
+                    paired.or_else(cached).ok_or_else(|| {
+                        SwitcherError::InvalidDevice(format!(
+                            "No MAC address known for device {}. Run 'discover' first.",
+                            resolved_device_id
+                        ))
+                    })?
+                }
+                GetField::State | GetField::Power => {
+                    let status = with_ip_fallback(
+                        SwitcherController::with_config(
+                            resolved_ip,
+                            resolved_device_id.clone(),
+                            controller_config.clone(),
+                        ),
+                        |controller| async move { controller.get_status().await },
+                    )
+                    .await?;
+                    record_power_samples(&[(
+                        resolved_device_id.clone(),
+                        status.power_consumption,
+                        current_timestamp(),
+                    )]);
+                    if field == GetField::State {
+                        status.state.to_string()
+                    } else {
+                        status.power_consumption.to_string()
+                    }
+                }
+            };
+            if cli.format == OutputFormat::Env {
+                println!("{}", env_assignment(field.env_name(), &value));
+            } else {
+                println!("{}", value);
+            }
+        }
+        Commands::OnAll { bulk } => {
+            run_bulk(
+                BulkAction::On,
+                &bulk,
+                cli.format,
+                compact,
+                &controller_config,
+            )
+            .await?
+        }
+        Commands::OffAll { bulk } => {
+            run_bulk(
+                BulkAction::Off,
+                &bulk,
+                cli.format,
+                compact,
+                &controller_config,
+            )
+            .await?
+        }
+        Commands::StatusAll {
+            bulk,
+            show_power_total,
+        } => {
+            run_bulk(
+                BulkAction::Status { show_power_total },
+                &bulk,
+                cli.format,
+                compact,
+                &controller_config,
+            )
+            .await?
+        }
+        Commands::Details {
+            ip,
+            device_id,
+            alias,
+        } => match resolve_device_info(ip, device_id, alias).await {
+            Ok((resolved_ip, resolved_device_id)) => {
+                let controller = SwitcherController::with_config(
+                    resolved_ip,
+                    resolved_device_id,
+                    controller_config.clone(),
+                );
+                match controller.get_details().await {
+                    Ok(details) => print_json(&details, compact)?,
+                    Err(e) => {
+                        error!("Failed to get device details: {}", e);
+                        exit_with_error(
+                            format!("❌ Failed to get details: {}", e),
+                            &e,
+                            parseable_errors,
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                exit_with_error(format!("❌ {}", e), e.as_ref(), parseable_errors);
+            }
+        },
+        Commands::AutoShutdown {
+            ip,
+            device_id,
+            alias,
+            get: _,
+            set,
+        } => match resolve_device_info(ip, device_id, alias).await {
+            Ok((resolved_ip, resolved_device_id)) => {
+                let controller = SwitcherController::with_config(
+                    resolved_ip,
+                    resolved_device_id,
+                    controller_config.clone(),
+                );
+                let result = match set {
+                    Some(duration) => controller.set_auto_shutdown(duration).await.map(|_| {
+                        format!(
+                            "✅ Auto-shutdown set to {}",
+                            format_duration(duration.as_secs())
+                        )
+                    }),
+                    None => controller.get_auto_shutdown().await.map(|duration| {
+                        format!("⏲️  Auto-shutdown: {}", format_duration(duration.as_secs()))
+                    }),
+                };
+                match result {
+                    Ok(message) => println!("{}", message),
+                    Err(e) => {
+                        error!("Auto-shutdown command failed: {}", e);
+                        exit_with_error(format!("❌ {}", e), &e, parseable_errors);
+                    }
+                }
+            }
+            Err(e) => {
+                exit_with_error(format!("❌ {}", e), e.as_ref(), parseable_errors);
+            }
+        },
+        Commands::PowerHistory {
+            device_id,
+            alias,
+            since,
+            until,
+            export,
+        } => {
+            let device_id = match device_id {
+                Some(device_id) if alias.is_none() => device_id,
+                Some(_) => {
+                    let e = SwitcherError::Usage(
+                        "Use either --device-id or --alias, not both".to_string(),
+                    );
+                    exit_with_error(format!("❌ {}", e), &e, parseable_errors);
+                }
+                None => match resolve_device_info(None, None, alias.clone()).await {
+                    Ok((_, device_id)) => device_id,
+                    Err(e) => {
+                        exit_with_error(format!("❌ {}", e), e.as_ref(), parseable_errors);
+                    }
+                },
+            };
+
+            let cache = CacheManager::new()?.load_cache()?;
+            let history = cache
+                .devices
+                .get(&device_id)
+                .map(|cached| cached.power_history.as_slice())
+                .unwrap_or_default();
+
+            let now = current_timestamp();
+            let from = since.map_or(0, |since| now.saturating_sub(since.as_secs()));
+            let to = until.map_or(now, |until| now.saturating_sub(until.as_secs()));
+            let label = alias.unwrap_or_else(|| device_id.clone());
+
+            if let Some(path) = export {
+                let file = std::fs::File::create(&path)?;
+                let rows = power::write_csv(history, from, to, file)?;
+                if cli.format.is_json() {
+                    print_json(
+                        &serde_json::json!({
+                            "device_id": device_id,
+                            "path": path,
+                            "samples": rows,
+                        }),
+                        compact,
+                    )?;
+                } else {
+                    println!(
+                        "💾 Wrote {} power readings for '{}' to {}",
+                        rows,
+                        label,
+                        path.display()
+                    );
+                }
+                return Ok(());
+            }
+
+            let stats = power::summarize(history, from, to);
+
+            if cli.format.is_json() {
+                print_json(
+                    &serde_json::json!({ "device_id": device_id, "stats": stats }),
+                    compact,
+                )?;
+            } else if stats.samples == 0 {
+                println!("📭 No power readings for '{}' in this window", label);
+                println!("   Readings are recorded each time 'status' or 'status-all' runs");
+            } else {
+                println!(
+                    "⚡ Power history for '{}' ({} readings, {} to {}):",
+                    label,
+                    stats.samples,
+                    format_datetime(stats.from.max(history[0].timestamp)),
+                    format_datetime(stats.to)
+                );
+                println!(
+                    "  Min: {}W  Avg: {:.1}W  Max: {}W",
+                    stats.min_watts.unwrap_or_default(),
+                    stats.avg_watts.unwrap_or_default(),
+                    stats.max_watts.unwrap_or_default()
+                );
+                println!("  Energy: {:.1} Wh", stats.energy_wh);
+            }
+        }
+        Commands::Diagnose {
+            ip,
+            device_id,
+            alias,
+            probe_port,
+        } => {
+            let (resolved_ip, resolved_device_id) =
+                match resolve_device_info(ip, device_id, alias).await {
+                    Ok(resolved) => resolved,
+                    Err(e) => {
+                        error!("Failed to resolve device info: {}", e);
+                        exit_with_error(format!("❌ {}", e), e.as_ref(), parseable_errors);
+                    }
+                };
+
+            match probe_port {
+                None => {
+                    let controller = SwitcherController::with_config(
+                        resolved_ip.clone(),
+                        resolved_device_id,
+                        controller_config.clone(),
+                    );
+                    println!("🔎 Checking login on {}...", resolved_ip);
+                    let started = std::time::Instant::now();
+                    match controller.check_login().await {
+                        Ok(()) => println!(
+                            "✅ Device accepted a login in {}ms",
+                            started.elapsed().as_millis()
+                        ),
+                        Err(e) => exit_with_error(
+                            format!(
+                                "❌ Login failed: {}\n   Try --probe-port to check other control ports",
+                                e
+                            ),
+                            &e,
+                            parseable_errors,
+                        ),
+                    }
+                }
+                Some(ports) => {
+                    let ports = if ports.is_empty() {
+                        control::PROBE_PORTS.to_vec()
+                    } else {
+                        ports
+                    };
+
+                    println!("🔎 Probing {} port(s) on {}...", ports.len(), resolved_ip);
+                    let mut working_port = None;
+
+                    for port in ports {
+                        let controller = SwitcherController::with_config(
+                            resolved_ip.clone(),
+                            resolved_device_id.clone(),
+                            ControllerConfig {
+                                port,
+                                ..controller_config.clone()
+                            },
+                        );
+                        match controller.check_login().await {
+                            Ok(()) => {
+                                println!("  ✅ {}: login accepted", controller.port());
+                                working_port.get_or_insert(controller.port());
+                            }
+                            Err(e) => println!("  ❌ {}: {}", controller.port(), e),
+                        }
+                    }
+
+                    match working_port {
+                        Some(port) => println!("\n💡 Device control port: {}", port),
+                        None => {
+                            let e = SwitcherError::Unreachable(
+                                "No candidate port accepted a login".to_string(),
+                            );
+                            exit_with_error(format!("\n❌ {}", e), &e, parseable_errors);
+                        }
+                    }
                 }
             }
         }
         Commands::ClearCache { force } => {
             let cache_manager = CacheManager::new()?;
+            let cache_path = cache_manager.get_cache_path().display().to_string();
 
             if !cache_manager.cache_exists() {
-                println!("ℹ️  No cache file found");
+                if cli.format.is_json() {
+                    print_json(
+                        &serde_json::json!({
+                            "cleared": false,
+                            "path": cache_path,
+                            "devices_removed": 0,
+                        }),
+                        compact,
+                    )?;
+                } else {
+                    println!("ℹ️  No cache file found");
+                }
                 return Ok(());
             }
 
@@ -329,11 +1845,83 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     return Ok(());
                 }
             }
-
-            match cache_manager.clear_cache() {
-                Ok(()) => println!("✅ Cache cleared successfully"),
-                Err(e) => println!("❌ Failed to clear cache: {}", e),
-            }
+
+            match cache_manager.clear_cache() {
+                Ok(devices_removed) if cli.format.is_json() => {
+                    print_json(
+                        &serde_json::json!({
+                            "cleared": true,
+                            "path": cache_path,
+                            "devices_removed": devices_removed,
+                        }),
+                        compact,
+                    )?;
+                }
+                Ok(devices_removed) => println!(
+                    "✅ Cache cleared successfully ({} device(s) removed)",
+                    devices_removed
+                ),
+                Err(e) => println!("❌ Failed to clear cache: {}", e),
+            }
+        }
+        Commands::Prune { max_age } => {
+            let cache_manager = CacheManager::new()?;
+            let mut cache = cache_manager.load_cache()?;
+
+            let devices_removed = cache.remove_old_devices(max_age);
+            if devices_removed > 0 {
+                cache_manager.save_cache(&cache)?;
+            }
+
+            if cli.format.is_json() {
+                print_json(
+                    &serde_json::json!({
+                        "pruned": true,
+                        "path": cache_manager.get_cache_path().display().to_string(),
+                        "devices_removed": devices_removed,
+                        "devices_remaining": cache.devices.len(),
+                    }),
+                    compact,
+                )?;
+            } else {
+                println!(
+                    "✅ Removed {} device(s) not seen in {}s, {} remaining",
+                    devices_removed,
+                    max_age,
+                    cache.devices.len()
+                );
+            }
+        }
+        Commands::Reconcile { max_age } => {
+            let cache = CacheManager::new()?.load_cache()?;
+            let fresh_devices = cache.get_fresh_devices(max_age);
+
+            let pairing_manager = PairingManager::new()?;
+            let mut pairing = pairing_manager.load_pairing()?;
+            let changes = pairing.reconcile(&fresh_devices);
+            pairing_manager.save_pairing(&pairing)?;
+
+            if cli.format.is_json() {
+                print_json(&serde_json::json!({ "changes": changes }), compact)?;
+            } else if changes.is_empty() {
+                println!("✅ Paired devices already match the cache");
+            } else {
+                println!("🔄 Updated {} paired device(s):", changes.len());
+                for change in &changes {
+                    if change.old_ip != change.new_ip {
+                        println!(
+                            "   {}: IP {} → {}",
+                            change.alias, change.old_ip, change.new_ip
+                        );
+                    }
+                    if change.old_name != change.new_name {
+                        println!(
+                            "   {}: name '{}' → '{}'",
+                            change.alias, change.old_name, change.new_name
+                        );
+                    }
+                }
+            }
         }
         Commands::Pair { device_id, alias } => {
             info!(
@@ -345,13 +1933,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let mut cache = cache_manager.load_cache()?;
 
             // Check if device exists in cache
-            if !cache.devices.contains_key(&device_id) {
+            if cache.get_device(&device_id).is_none() {
                 // Device not in cache, need to discover it
                 info!(
                     "Device {} not found in cache, starting discovery",
                     device_id
                 );
-                let discovery = SwitcherDiscovery::new();
+                let discovery = SwitcherDiscovery::new().with_profile(&profile);
                 let devices = discovery.discover(Duration::from_secs(10)).await?;
 
                 if !devices.iter().any(|d| d.device_id == device_id) {
@@ -365,7 +1953,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
 
             // Get the device from cache
-            let device = cache.devices.get(&device_id).unwrap().device.clone();
+            let device = cache.get_device(&device_id).cloned().ok_or_else(|| {
+                format!("Device {} missing from cache after discovery", device_id)
+            })?;
 
             // Now pair the device using pairing manager
             let pairing_manager = PairingManager::new()?;
@@ -390,6 +1980,122 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
         }
+        Commands::Adopt { timeout, yes } => {
+            println!("🔍 Looking for devices ({}s)...", timeout);
+            let discovery = SwitcherDiscovery::new().with_profile(&profile);
+            let devices = discovery
+                .discover_network(Duration::from_secs(timeout))
+                .await?;
+
+            let pairing_manager = PairingManager::new()?;
+            let mut pairing = pairing_manager.load_pairing()?;
+            let unpaired: Vec<_> = pairing.unpaired(&devices).into_iter().cloned().collect();
+
+            if unpaired.is_empty() {
+                println!("✅ Found {} device(s), all already paired", devices.len());
+                return Ok(());
+            }
+            println!("📱 Found {} unpaired device(s)", unpaired.len());
+            if !yes {
+                println!(
+                    "   Enter an alias for each, press Enter to use the device name or '-' to skip"
+                );
+            }
+
+            let mut adopted = 0;
+            'devices: for device in &unpaired {
+                println!("\n  {}", device);
+                loop {
+                    let alias = if yes {
+                        device.name.clone()
+                    } else {
+                        print!("  Alias [{}]: ", device.name);
+                        std::io::stdout().flush()?;
+                        let mut input = String::new();
+                        if std::io::stdin().read_line(&mut input)? == 0 {
+                            println!();
+                            break 'devices; // stdin closed
+                        }
+                        match input.trim() {
+                            "" => device.name.clone(),
+                            "-" => {
+                                println!("  ⏭️  Skipped");
+                                continue 'devices;
+                            }
+                            alias => alias.to_string(),
+                        }
+                    };
+
+                    match pairing.pair_device(device.clone(), alias.clone()) {
+                        Ok(()) => {
+                            // Save as we go so an interrupted session keeps what was confirmed
+                            pairing_manager.save_pairing(&pairing)?;
+                            info!("Adopted device {} as '{}'", device.device_id, alias);
+                            println!("  ✅ Paired as '{}'", alias);
+                            adopted += 1;
+                            break;
+                        }
+                        Err(e) if yes => {
+                            println!("  ❌ {}, skipped", e);
+                            break;
+                        }
+                        Err(e) => println!("  ❌ {}, try another alias", e),
+                    }
+                }
+            }
+
+            println!(
+                "\n✅ Adopted {} of {} unpaired device(s)",
+                adopted,
+                unpaired.len()
+            );
+        }
+        Commands::Rekey { alias, device_id } => {
+            let pairing_manager = PairingManager::new()?;
+            let mut pairing = pairing_manager.load_pairing()?;
+
+            if pairing.get_device_by_alias(&alias).is_none() {
+                println!("❌ No device found with alias '{}'", alias);
+                return Ok(());
+            }
+
+            let cache_manager = CacheManager::new()?;
+            let mut cache = cache_manager.load_cache()?;
+
+            if cache.get_device(&device_id).is_none() {
+                info!(
+                    "Device {} not found in cache, starting discovery",
+                    device_id
+                );
+                let discovery = SwitcherDiscovery::new().with_profile(&profile);
+                let devices = discovery.discover(Duration::from_secs(10)).await?;
+
+                if !devices.iter().any(|d| d.device_id == device_id) {
+                    println!("❌ Device with ID '{}' not found on network", device_id);
+                    println!("   Make sure the device is powered on and connected");
+                    return Ok(());
+                }
+
+                cache = cache_manager.load_cache()?;
+            }
+
+            let device = cache.get_device(&device_id).cloned().ok_or_else(|| {
+                format!("Device {} missing from cache after discovery", device_id)
+            })?;
+
+            match pairing.rekey_device(&alias, device.clone()) {
+                Ok(old_device_id) => {
+                    pairing_manager.save_pairing(&pairing)?;
+                    println!("✅ Alias '{}' re-keyed", alias);
+                    println!("   Device ID: {} → {}", old_device_id, device_id);
+                    println!("   IP: {}", device.ip_address);
+                }
+                Err(e) => {
+                    error!("Failed to re-key alias '{}': {}", alias, e);
+                    println!("❌ {}", e);
+                }
+            }
+        }
         Commands::Unpair { alias, force } => {
             let pairing_manager = PairingManager::new()?;
             let mut pairing = pairing_manager.load_pairing()?;
@@ -428,11 +2134,127 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 Err(e) => println!("❌ Failed to unpair device: {}", e),
             }
         }
-        Commands::ListPaired { verbose } => {
+        Commands::Config { action } => {
+            let config_manager = ConfigManager::new()?;
+            // Validation reports a broken file itself, so don't load it first
+            if let ConfigAction::Validate = action {
+                return validate_config(&config_manager);
+            }
+            let mut settings = config_manager.load_settings()?;
+
+            match action {
+                ConfigAction::Show => {
+                    for key in config::Settings::KEYS {
+                        println!("{} = {}", key, settings.get(key)?);
+                    }
+                }
+                ConfigAction::Get { key } => match settings.get(&key) {
+                    Ok(value) => println!("{}", value),
+                    Err(e) => println!("❌ {}", e),
+                },
+                ConfigAction::Set { key, value } => match settings.set(&key, &value) {
+                    Ok(()) => {
+                        config_manager.save_settings(&settings)?;
+                        println!("✅ {} = {}", key, settings.get(&key)?);
+                    }
+                    Err(e) => println!("❌ {}", e),
+                },
+                ConfigAction::Validate => unreachable!("handled before loading settings"),
+            }
+        }
+        Commands::SetColor { alias, color, .. } => {
+            let pairing_manager = PairingManager::new()?;
+            let mut pairing = pairing_manager.load_pairing()?;
+
+            match pairing.set_color(&alias, color) {
+                Ok(()) => {
+                    pairing_manager.save_pairing(&pairing)?;
+                    match color {
+                        Some(color) => println!(
+                            "🎨 '{}' is now shown in {}",
+                            paint_alias(&alias, Some(color)),
+                            color.name()
+                        ),
+                        None => println!("🎨 '{}' uses the default color again", alias),
+                    }
+                }
+                Err(e) => println!("❌ {}", e),
+            }
+        }
+        Commands::SetDefault { alias } => {
+            let pairing_manager = PairingManager::new()?;
+            let mut pairing = pairing_manager.load_pairing()?;
+
+            match pairing.set_default(&alias) {
+                Ok(()) => {
+                    pairing_manager.save_pairing(&pairing)?;
+                    println!("⭐ '{}' is now the default device", alias);
+                    println!("   Commands run without --ip/--device-id/--alias will use it");
+                }
+                Err(e) => println!("❌ {}", e),
+            }
+        }
+        Commands::ListPaired {
+            verbose,
+            output_template,
+            online_threshold,
+            fresh_only,
+            name_contains,
+        } => {
+            if let Some(template) = &output_template {
+                if let Err(e) = template::validate(template, template::PAIRED_PLACEHOLDERS) {
+                    return Err(SwitcherError::Usage(e.to_string()).into());
+                }
+            }
+
             let pairing_manager = PairingManager::new()?;
             let pairing = pairing_manager.load_pairing()?;
 
-            let paired_devices = pairing.get_paired_devices();
+            let threshold_secs = online_threshold
+                .map(|threshold| threshold.as_secs())
+                .unwrap_or(pairing::DEFAULT_ONLINE_THRESHOLD_SECS);
+            let mut paired_devices = pairing.get_paired_devices();
+            let total_paired = paired_devices.len();
+            if fresh_only {
+                paired_devices.retain(|device| device.seen_within(threshold_secs));
+            }
+            if let Some(needle) = &name_contains {
+                paired_devices.retain(|device| {
+                    contains_ignore_case(&device.alias, needle)
+                        || contains_ignore_case(&device.device.name, needle)
+                });
+            }
+
+            if let Some(template) = &output_template {
+                for device in paired_devices {
+                    let fields = template::paired_fields(device);
+                    println!("{}", template::render(template, &fields)?);
+                }
+                return Ok(());
+            }
+            if cli.format.is_json() {
+                let mut entries = Vec::with_capacity(paired_devices.len());
+                for paired in &paired_devices {
+                    let mut entry = serde_json::to_value(paired)?;
+                    entry["device"] = device_json(&paired.device)?;
+                    entries.push(entry);
+                }
+                print_json(&entries, compact)?;
+                return Ok(());
+            }
+
+            if paired_devices.is_empty() && total_paired > 0 {
+                if let Some(needle) = &name_contains {
+                    println!("📱 No paired devices matched '{}'", needle);
+                    return Ok(());
+                }
+                println!(
+                    "📱 None of the {} paired devices were seen in the last {}",
+                    total_paired,
+                    format_duration(threshold_secs)
+                );
+                return Ok(());
+            }
 
             if paired_devices.is_empty() {
                 println!("📱 No paired devices found");
@@ -443,20 +2265,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("📱 Paired devices ({}):", paired_devices.len());
 
             for device in paired_devices {
-                let recently_seen = (current_timestamp() - device.last_seen) < 3600; // 1 hour
+                let recently_seen = device.seen_within(threshold_secs);
                 let status_icon = if recently_seen { "🟢" } else { "🔴" };
+                let default_marker = if device.is_default { " ⭐" } else { "" };
 
                 println!(
-                    "  {} {} ({})",
-                    status_icon, device.alias, device.device.ip_address
+                    "  {} {} ({}){}",
+                    status_icon,
+                    paint_alias(&device.alias, device.label_color()),
+                    device.device.ip_address,
+                    default_marker
                 );
 
                 if verbose {
                     println!("     Device ID: {}", device.device.device_id);
                     println!("     MAC: {}", device.device.mac_address);
-                    println!("     Type: {}", device.device.device_type);
-                    println!("     Paired: {}", format_timestamp(device.paired_at));
-                    println!("     Last seen: {}", format_timestamp(device.last_seen));
+                    println!(
+                        "     Type: {} ({})",
+                        device.device.device_type, device.device.device_type_code
+                    );
+                    println!(
+                        "     Paired: {} ({})",
+                        format_datetime(device.paired_at),
+                        format_timestamp(device.paired_at)
+                    );
+                    println!(
+                        "     Last seen: {} ({})",
+                        format_datetime(device.last_seen),
+                        format_timestamp(device.last_seen)
+                    );
                     println!();
                 }
             }
@@ -465,6 +2302,234 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("   Use --verbose for detailed information");
             }
         }
+        Commands::List {
+            online_threshold,
+            fresh_only,
+        } => {
+            let cache = CacheManager::new()?.load_cache()?;
+            let pairing = PairingManager::new()?.load_pairing()?;
+
+            let threshold_secs = online_threshold
+                .map(|threshold| threshold.as_secs())
+                .unwrap_or(pairing::DEFAULT_ONLINE_THRESHOLD_SECS);
+            let mut known = inventory::merge(&cache, &pairing);
+            if fresh_only {
+                known.retain(|device| device.seen_within(threshold_secs));
+            }
+
+            if cli.format.is_json() {
+                let mut entries = Vec::with_capacity(known.len());
+                for device in &known {
+                    let mut entry = serde_json::to_value(device)?;
+                    entry["state_code"] = device.device.state.as_code().into();
+                    entries.push(entry);
+                }
+                print_json(&entries, compact)?;
+                return Ok(());
+            }
+            if known.is_empty() {
+                println!("📱 No known devices");
+                println!("   Run 'discover' to find devices on the network");
+                return Ok(());
+            }
+
+            let rows: Vec<[String; 6]> = known
+                .iter()
+                .map(|known| {
+                    [
+                        known.source.to_string(),
+                        known.alias.clone().unwrap_or_else(|| "-".to_string()),
+                        known.device.name.clone(),
+                        known.device.ip_address.clone(),
+                        format_timestamp(known.last_seen),
+                        known.device.state.to_string(),
+                    ]
+                })
+                .collect();
+            let header = ["SOURCE", "ALIAS", "NAME", "IP", "LAST SEEN", "STATE"];
+            let lines = table_lines(&header, &rows);
+            println!("   {}", lines[0]);
+            for (known, line) in known.iter().zip(&lines[1..]) {
+                let online = if known.seen_within(threshold_secs) {
+                    "🟢"
+                } else {
+                    "🔴"
+                };
+                println!("{} {}", online, line);
+            }
+        }
+        Commands::Metrics => {
+            let pairing = load_pairing_config()?;
+            let mut queries = tokio::task::JoinSet::new();
+            for paired in pairing.get_paired_devices() {
+                let controller = SwitcherController::from_device_with_config(
+                    paired.as_ref(),
+                    controller_config.clone(),
+                );
+                let alias = paired.alias.clone();
+                queries.spawn(async move {
+                    let status = match controller.get_status().await {
+                        Ok(status) => Some(status),
+                        Err(e) => {
+                            warn!("Could not read '{}' for metrics: {}", alias, e);
+                            None
+                        }
+                    };
+                    metrics::DeviceSample {
+                        alias,
+                        device_id: controller.device_id().to_string(),
+                        status,
+                    }
+                });
+            }
+
+            let mut samples = queries.join_all().await;
+            samples.sort_by(|a, b| a.alias.cmp(&b.alias));
+            print!("{}", metrics::render(&samples));
+        }
+        Commands::Snapshot { timeout } => {
+            let discovery = SwitcherDiscovery::without_cache().with_profile(&profile);
+            let mut devices = discovery
+                .discover_network(Duration::from_secs(timeout))
+                .await?;
+            let pairing = PairingManager::new()
+                .and_then(|pairing_manager| pairing_manager.load_pairing())
+                .unwrap_or_default();
+            let alias_of = |device: &device::SwitcherDevice| {
+                pairing
+                    .devices
+                    .get(&device.device_id)
+                    .map(|paired| paired.alias.clone())
+            };
+            devices.sort_by_key(|device| {
+                (
+                    alias_of(device).is_none(),
+                    alias_of(device),
+                    device.name.clone(),
+                )
+            });
+
+            if cli.format.is_json() {
+                let snapshot: Vec<_> = devices
+                    .iter()
+                    .map(|device| {
+                        serde_json::json!({
+                            "alias": alias_of(device),
+                            "device_id": device.device_id,
+                            "name": device.name,
+                            "ip_address": device.ip_address,
+                            "state": device.state,
+                            "state_code": device.state.as_code(),
+                            "power_consumption": device.power_consumption,
+                        })
+                    })
+                    .collect();
+                print_json(&snapshot, compact)?;
+                return Ok(());
+            }
+            if devices.is_empty() {
+                println!("📱 No devices broadcast within {}s", timeout);
+                return Ok(());
+            }
+
+            let rows: Vec<[String; 5]> = devices
+                .iter()
+                .map(|device| {
+                    [
+                        alias_of(device).unwrap_or_else(|| "-".to_string()),
+                        device.name.clone(),
+                        device.ip_address.clone(),
+                        device.state.to_string(),
+                        format!("{}W", device.power_consumption),
+                    ]
+                })
+                .collect();
+            for line in table_lines(&["ALIAS", "NAME", "IP", "STATE", "POWER"], &rows) {
+                println!("  {}", line);
+            }
+            println!();
+            println!("ℹ️  From discovery broadcasts, which can lag the device by a few seconds");
+            println!("   Use status-all for live readings");
+        }
+        Commands::DiscoveryProbe { duration } => {
+            let discovery = SwitcherDiscovery::without_cache()
+                .with_every_broadcast()
+                .with_profile(&profile);
+            if cli.format == OutputFormat::Text {
+                println!("🔍 Listening for broadcasts for {}m...", duration);
+            }
+            let started = std::time::Instant::now();
+            let arrivals: Vec<_> = discovery
+                .discover_stream(Duration::from_secs(duration * 60))
+                .await?
+                .map(|device| (device, started.elapsed()))
+                .collect()
+                .await;
+            let timings = discovery::broadcast_timings(&arrivals);
+            let gap_secs = |gap: Option<Duration>| gap.map(|gap| gap.as_secs_f64());
+
+            if cli.format.is_json() {
+                let report: Vec<_> = timings
+                    .iter()
+                    .map(|timing| {
+                        serde_json::json!({
+                            "device_id": timing.device.device_id,
+                            "name": timing.device.name,
+                            "ip_address": timing.device.ip_address,
+                            "packets": timing.packets,
+                            "min_gap_secs": gap_secs(timing.min_gap),
+                            "avg_gap_secs": gap_secs(timing.avg_gap),
+                            "max_gap_secs": gap_secs(timing.max_gap),
+                        })
+                    })
+                    .collect();
+                print_json(&report, compact)?;
+                return Ok(());
+            }
+            if timings.is_empty() {
+                println!("❌ No broadcasts seen in {}m", duration);
+                return Ok(());
+            }
+
+            let gap_label = |gap: Option<Duration>| {
+                gap_secs(gap)
+                    .map(|secs| format!("{:.1}s", secs))
+                    .unwrap_or_else(|| "-".to_string())
+            };
+            let rows: Vec<[String; 6]> = timings
+                .iter()
+                .map(|timing| {
+                    [
+                        timing.device.name.clone(),
+                        timing.device.ip_address.clone(),
+                        timing.packets.to_string(),
+                        gap_label(timing.min_gap),
+                        gap_label(timing.avg_gap),
+                        gap_label(timing.max_gap),
+                    ]
+                })
+                .collect();
+            println!("\n📶 Broadcast timing over {}m:", duration);
+            for line in table_lines(
+                &["NAME", "IP", "PACKETS", "MIN GAP", "AVG GAP", "MAX GAP"],
+                &rows,
+            ) {
+                println!("  {}", line);
+            }
+            println!();
+            println!("ℹ️  Scans shorter than a device's max gap can miss it");
+        }
+        #[cfg(feature = "dashboard")]
+        Commands::Dashboard { interval } => {
+            if let Err(e) = dashboard::run(interval, controller_config).await {
+                println!("❌ {}", e);
+            }
+        }
+        #[cfg(feature = "schema")]
+        Commands::Schema => {
+            let schema = schemars::schema_for!(config::UnifiedConfig);
+            print_json(&schema, compact)?;
+        }
         Commands::Rename {
             ip,
             device_id,
@@ -472,24 +2537,369 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             new_name,
         } => match resolve_device_info(ip, device_id, alias).await {
             Ok((resolved_ip, resolved_device_id)) => {
-                let controller = SwitcherController::new(resolved_ip, resolved_device_id);
+                let controller = SwitcherController::with_config(
+                    resolved_ip,
+                    resolved_device_id,
+                    controller_config.clone(),
+                );
                 match controller.set_device_name(&new_name).await {
                     Ok(_) => {
                         println!("✅ Device name changed to '{}'", new_name);
                         println!("   Note: It may take a few moments for the change to appear in discovery");
                     }
-                    Err(e) => println!("❌ Failed to change device name: {}", e),
+                    Err(e) => {
+                        let message = match &e {
+                                SwitcherError::NameTooShort(_) => format!(
+                                    "❌ {}\n   Use a longer name, e.g. \"Office Plug\"",
+                                    e
+                                ),
+                                SwitcherError::NameTooLong(length) => format!(
+                                    "❌ {}\n   Shorten it by {} (multi-byte characters count more than once)",
+                                    e,
+                                    length - control::MAX_DEVICE_NAME_LEN
+                                ),
+                                SwitcherError::NameContainsNul => format!(
+                                    "❌ {}\n   Remove the NUL byte; the device would cut the name there",
+                                    e
+                                ),
+                                _ => format!("❌ Failed to change device name: {}", e),
+                            };
+                        exit_with_error(message, &e, parseable_errors);
+                    }
                 }
             }
-            Err(e) => println!("❌ {}", e),
+            Err(e) => {
+                exit_with_error(format!("❌ {}", e), e.as_ref(), parseable_errors);
+            }
         },
     }
 
     Ok(())
 }
 
-/// Initialize logging based on CLI flags and environment variables
-fn init_logging(verbose: bool, debug: bool) {
+#[derive(Subcommand)]
+enum ConfigAction {
+    #[command(about = "Print all settings")]
+    Show,
+    #[command(about = "Print a single setting")]
+    Get { key: String },
+    #[command(about = "Change a setting (an empty value clears optional settings)")]
+    Set { key: String, value: String },
+    #[command(about = "Check the config file for unrecognized keys, e.g. typos from hand edits")]
+    Validate,
+}
+
+/// `config validate`: list keys in the config file that loading ignores.
+/// Exits with the config error code if there are any.
+fn validate_config(config_manager: &ConfigManager) -> Result<(), Box<dyn std::error::Error>> {
+    let path = config_manager.get_config_path();
+    if !config_manager.config_exists() {
+        println!("ℹ️  No config file at {} yet", path.display());
+        return Ok(());
+    }
+
+    let unknown = config_manager
+        .unknown_keys()
+        .map_err(|e| SwitcherError::Config(format!("{} is not valid: {}", path.display(), e)))?;
+    if unknown.is_empty() {
+        println!("✅ {} has no unrecognized keys", path.display());
+        return Ok(());
+    }
+
+    println!("❌ {} has unrecognized keys:", path.display());
+    for key in &unknown {
+        println!("   {}", key);
+    }
+    println!("   These are ignored when loading; check them for typos");
+    std::process::exit(error::EXIT_CONFIG);
+}
+
+/// Options shared by the bulk commands
+#[derive(Args)]
+struct BulkArgs {
+    #[arg(
+        long,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Give up on a device after this many seconds so dead devices don't stall the batch"
+    )]
+    timeout_per_device: Option<u64>,
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 300,
+        help = "Skip devices a bulk command couldn't reach within this many seconds (0 never skips)"
+    )]
+    unreachable_ttl: u64,
+    #[arg(
+        long,
+        help = "Contact every device, even ones that were recently unreachable"
+    )]
+    ignore_unreachable_cache: bool,
+}
+
+#[derive(Clone, Copy)]
+enum BulkAction {
+    On,
+    Off,
+    Status { show_power_total: bool },
+}
+
+/// Run an action against every paired device. Text output streams one table
+/// row per device; JSON output is a single aggregate object. Exits with a
+/// non-zero code if any device failed.
+async fn run_bulk(
+    action: BulkAction,
+    args: &BulkArgs,
+    format: OutputFormat,
+    compact: bool,
+    controller_config: &ControllerConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pairing_manager = PairingManager::new()?;
+    let pairing = pairing_manager.load_pairing()?;
+
+    let mut paired_devices = pairing.get_paired_devices();
+    paired_devices.sort_by(|a, b| a.alias.cmp(&b.alias));
+
+    if paired_devices.is_empty() && format == OutputFormat::Text {
+        println!("📱 No paired devices found");
+        println!("   Use 'pair --device-id <id> --alias <alias>' to pair a device");
+        return Ok(());
+    }
+
+    let alias_width = paired_devices
+        .iter()
+        .map(|device| device.alias.chars().count())
+        .max()
+        .unwrap_or_default()
+        .max("ALIAS".len());
+    if format == OutputFormat::Text {
+        println!("  {:<width$}  RESULT", "ALIAS", width = alias_width);
+    }
+
+    // IPs that failed in a recent run, skipped rather than waiting out the connect timeout
+    let recently_unreachable = (!args.ignore_unreachable_cache && args.unreachable_ttl > 0)
+        .then(|| CacheManager::new().and_then(|cache_manager| cache_manager.load_cache()))
+        .and_then(Result::ok);
+
+    let mut results = Vec::with_capacity(paired_devices.len());
+    let mut power_samples = Vec::new();
+    let mut reachability = Vec::new();
+    for paired in paired_devices {
+        let ip = &paired.device.ip_address;
+        if let Some(since) = recently_unreachable
+            .as_ref()
+            .and_then(|cache| cache.unreachable_since(ip, args.unreachable_ttl))
+        {
+            info!(
+                "Skipping '{}', {} was unreachable at {}",
+                paired.alias, ip, since
+            );
+            let result = BulkResult::skipped_unreachable(
+                &paired.alias,
+                current_timestamp().saturating_sub(since),
+            );
+            print_bulk_result(&result, format, alias_width)?;
+            results.push(result);
+            continue;
+        }
+
+        let controller =
+            SwitcherController::from_device_with_config(paired.as_ref(), controller_config.clone());
+        let operation = async {
+            match action {
+                BulkAction::On => with_ip_fallback(controller, |controller| async move {
+                    controller.turn_on().await
+                })
+                .await
+                .map(|_| BulkResult::success(&paired.alias)),
+                BulkAction::Off => with_ip_fallback(controller, |controller| async move {
+                    controller.turn_off().await
+                })
+                .await
+                .map(|_| BulkResult::success(&paired.alias)),
+                BulkAction::Status { .. } => {
+                    with_ip_fallback(controller, |controller| async move {
+                        controller.get_status().await
+                    })
+                    .await
+                    .map(|status| BulkResult::with_status(&paired.alias, &status))
+                }
+            }
+        };
+
+        let result = match args.timeout_per_device {
+            Some(secs) => match tokio::time::timeout(Duration::from_secs(secs), operation).await {
+                Ok(result) => result,
+                Err(_) => {
+                    warn!("'{}' did not finish within {}s", paired.alias, secs);
+                    Ok(BulkResult::timed_out(&paired.alias, secs))
+                }
+            },
+            None => operation.await,
+        };
+        let unreachable = match &result {
+            Ok(result) => result.timed_out,
+            // Login and short-reply failures mean the device answered
+            Err(e) => e.is_connection_error(),
+        };
+        reachability.push((ip.clone(), !unreachable, current_timestamp()));
+        if let Ok(BulkResult {
+            power_consumption: Some(watts),
+            ..
+        }) = &result
+        {
+            power_samples.push((paired.device.device_id.clone(), *watts, current_timestamp()));
+        }
+        let result = result.unwrap_or_else(|e| {
+            error!("Bulk operation failed for '{}': {}", paired.alias, e);
+            BulkResult::failure(&paired.alias, e.to_string())
+        });
+
+        print_bulk_result(&result, format, alias_width)?;
+        results.push(result);
+    }
+    record_in_cache(&power_samples, &reachability, args.unreachable_ttl);
+
+    let report = match action {
+        BulkAction::Status {
+            show_power_total: true,
+        } => BulkReport::new(results).with_power_total(),
+        _ => BulkReport::new(results),
+    };
+    match format {
+        OutputFormat::Json => print_json(&report, compact)?,
+        OutputFormat::Ndjson => println!("{}", report.summary_line()),
+        OutputFormat::Text if report.summary.timed_out > 0 || report.summary.skipped > 0 => {
+            println!(
+                "\n✅ {} succeeded, ❌ {} failed ({} timed out, {} skipped as unreachable)",
+                report.summary.ok,
+                report.summary.failed,
+                report.summary.timed_out,
+                report.summary.skipped
+            )
+        }
+        OutputFormat::Text => println!(
+            "\n✅ {} succeeded, ❌ {} failed",
+            report.summary.ok, report.summary.failed
+        ),
+        OutputFormat::Env => unreachable!("--format env is rejected for bulk commands"),
+    }
+    if let (OutputFormat::Text, Some(total)) = (format, report.power_total) {
+        println!("Total: {}W across {} devices", total.watts, total.devices);
+    }
+
+    if report.any_failed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Report a failed command and exit with its mapped code. `message` is the
+/// prose printed to stdout; with --parseable-error (implied by JSON output)
+/// an error envelope goes to stderr instead.
+fn exit_with_error(message: String, e: &(dyn std::error::Error + 'static), parseable: bool) -> ! {
+    if parseable {
+        eprintln!("{}", error::envelope(e));
+    } else {
+        println!("{}", message);
+    }
+    std::process::exit(error::exit_code(e));
+}
+
+/// Color an alias with its label color, unless stdout isn't a terminal or
+/// NO_COLOR is set
+fn paint_alias(alias: &str, color: Option<pairing::LabelColor>) -> String {
+    use std::io::IsTerminal;
+    match color {
+        Some(color)
+            if std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none() =>
+        {
+            color.paint(alias)
+        }
+        _ => alias.to_string(),
+    }
+}
+
+/// Left-aligned columns sized to the widest cell, header line first
+fn table_lines<const N: usize>(header: &[&str; N], rows: &[[String; N]]) -> Vec<String> {
+    let widths: Vec<usize> = (0..N)
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .chain([header[column].len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+
+    std::iter::once(line(header.to_vec()))
+        .chain(
+            rows.iter()
+                .map(|row| line(row.iter().map(String::as_str).collect())),
+        )
+        .collect()
+}
+
+/// Stream one device's outcome: a table row, an ndjson line, or nothing for
+/// aggregate JSON
+fn print_bulk_result(
+    result: &BulkResult,
+    format: OutputFormat,
+    alias_width: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Text => println!("{}", result.table_row(alias_width)),
+        OutputFormat::Ndjson => {
+            println!("{}", serde_json::to_string(result)?);
+            std::io::stdout().flush()?;
+        }
+        OutputFormat::Json => {}
+        OutputFormat::Env => unreachable!("--format env is rejected for bulk commands"),
+    }
+    Ok(())
+}
+
+/// ", turning off in 30m" after an `on --auto-off` confirmation
+fn auto_off_note(auto_off: Option<Duration>) -> String {
+    auto_off
+        .map(|duration| format!(", turning off in {}", format_duration(duration.as_secs())))
+        .unwrap_or_default()
+}
+
+/// A device's JSON output: its stored fields plus the derived `state_code`,
+/// which the config file doesn't hold
+fn device_json(device: &device::SwitcherDevice) -> Result<serde_json::Value, serde_json::Error> {
+    let mut json = serde_json::to_value(device)?;
+    json["state_code"] = device.state.as_code().into();
+    Ok(json)
+}
+
+/// Print a value as JSON for --format json, on one line with --compact
+fn print_json<T: serde::Serialize>(
+    value: &T,
+    compact: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let json = if compact {
+        serde_json::to_string(value)?
+    } else {
+        serde_json::to_string_pretty(value)?
+    };
+    println!("{}", json);
+    Ok(())
+}
+
+/// Initialize logging based on CLI flags and environment variables. Console
+/// logs go to stdout, or to stderr with `console_to_stderr`.
+fn init_logging(verbose: bool, debug: bool, console: bool, console_to_stderr: bool) {
     use std::path::PathBuf;
     use tracing_appender::rolling::{RollingFileAppender, Rotation};
     use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
@@ -513,12 +2923,20 @@ fn init_logging(verbose: bool, debug: bool) {
     let file_appender = RollingFileAppender::new(Rotation::DAILY, log_dir, "switcher-rust.log");
 
     // Create console layer
-    let console_layer = fmt::layer()
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_thread_names(false)
-        .with_file(false)
-        .with_line_number(false);
+    let console_layer = console.then(|| {
+        let writer = if console_to_stderr {
+            fmt::writer::BoxMakeWriter::new(std::io::stderr)
+        } else {
+            fmt::writer::BoxMakeWriter::new(std::io::stdout)
+        };
+        fmt::layer()
+            .with_writer(writer)
+            .with_target(false)
+            .with_thread_ids(false)
+            .with_thread_names(false)
+            .with_file(false)
+            .with_line_number(false)
+    });
 
     // Create file layer
     let file_layer = fmt::layer()
@@ -546,7 +2964,97 @@ fn init_logging(verbose: bool, debug: bool) {
         .init();
 }
 
-/// Resolve device IP and ID from either direct parameters or paired device alias
+/// Run a controller operation against the controller's IP, falling back to the
+/// device's previously seen IPs from the cache (most recent first) if it can't
+/// be reached. Errors from a device that answered are returned as they are,
+/// so a rejected on/off is never replayed against old addresses.
+async fn with_ip_fallback<T, F, Fut>(
+    controller: SwitcherController,
+    operation: F,
+) -> Result<T, SwitcherError>
+where
+    F: Fn(SwitcherController) -> Fut,
+    Fut: std::future::Future<Output = Result<T, SwitcherError>>,
+{
+    let ip = controller.ip_address().to_string();
+    let device_id = controller.device_id().to_string();
+    let history = CacheManager::new()
+        .and_then(|cache_manager| cache_manager.load_cache())
+        .ok()
+        .and_then(|cache| cache.devices.get(&device_id).map(|c| c.ip_history.clone()))
+        .unwrap_or_default();
+
+    let primary_error = match operation(controller.clone()).await {
+        Ok(result) => return Ok(result),
+        Err(e) if !e.is_connection_error() => return Err(e),
+        Err(e) => e,
+    };
+
+    for previous_ip in history.into_iter().filter(|previous_ip| *previous_ip != ip) {
+        warn!(
+            "Device {} failed at {} ({}), trying previous IP {}",
+            device_id, ip, primary_error, previous_ip
+        );
+        let fallback = controller.clone().with_ip_address(previous_ip.clone());
+        match operation(fallback).await {
+            Ok(result) => {
+                info!(
+                    "Reached device {} at previous IP {}",
+                    device_id, previous_ip
+                );
+                return Ok(result);
+            }
+            // The device is at this address but the operation failed
+            Err(e) if !e.is_connection_error() => return Err(e),
+            Err(e) => debug!("Previous IP {} failed too: {}", previous_ip, e),
+        }
+    }
+
+    Err(primary_error)
+}
+
+/// Append `(device_id, watts, timestamp)` readings to the cached power
+/// history. Devices that aren't in the cache (e.g. controlled by IP only)
+/// are skipped.
+fn record_power_samples(samples: &[(String, u16, u64)]) {
+    record_in_cache(samples, &[], 0);
+}
+
+/// Record power readings and `(ip, reachable, timestamp)` marks in a single
+/// config write. Unreachable marks let later bulk runs within
+/// `unreachable_ttl` skip that IP; recording marks drops ones older than that.
+fn record_in_cache(
+    power_samples: &[(String, u16, u64)],
+    reachability: &[(String, bool, u64)],
+    unreachable_ttl: u64,
+) {
+    if power_samples.is_empty() && reachability.is_empty() {
+        return;
+    }
+    let result = ConfigManager::new().and_then(|config_manager| {
+        config_manager.update(|config| {
+            let cache = config.cache.get_or_insert_with(cache::DeviceCache::new);
+            let mut changed = !reachability.is_empty() && cache.prune_unreachable(unreachable_ttl);
+            for (device_id, watts, timestamp) in power_samples {
+                changed |= cache.record_power(device_id, *watts, *timestamp);
+            }
+            for (ip, reachable, timestamp) in reachability {
+                if *reachable {
+                    changed |= cache.mark_reachable(ip);
+                } else {
+                    cache.mark_unreachable(ip, *timestamp);
+                    changed = true;
+                }
+            }
+            changed
+        })
+    });
+    if let Err(e) = result {
+        warn!("Could not update the device cache: {}", e);
+    }
+}
+
+/// Resolve device IP and ID from direct parameters, a paired device alias, or the default device
 async fn resolve_device_info(
     ip: Option<String>,
     device_id: Option<String>,
@@ -558,28 +3066,56 @@ async fn resolve_device_info(
 
         // Paired device alias provided
         (None, None, Some(alias)) => {
-            let pairing_manager = PairingManager::new()?;
-            let pairing = pairing_manager.load_pairing()?;
+            let pairing = load_pairing_config()?;
 
-            let paired_device = pairing.get_device_by_alias(&alias)
-                .ok_or_else(|| format!("No paired device found with alias '{}'", alias))?;
+            let paired_device = pairing.get_device_by_alias(&alias).ok_or_else(|| {
+                SwitcherError::InvalidDevice(format!("No paired device found with alias '{}'", alias))
+            })?;
 
             // Use the current IP from cache
             Ok((paired_device.device.ip_address.clone(), paired_device.device.device_id.clone()))
         }
 
+        // IP only: take the device ID from whichever cached device is at that IP
+        (Some(ip), None, None) => {
+            let cache = CacheManager::new()
+                .and_then(|cache_manager| cache_manager.load_cache())
+                .map_err(|e| SwitcherError::Config(format!("Could not load device cache: {}", e)))?;
+
+            let device = cache.get_by_ip(&ip).ok_or_else(|| SwitcherError::Usage(format!(
+                "No cached device at {}. Pass --device-id too, or run 'discover' first.",
+                ip
+            )))?;
+
+            Ok((ip, device.device_id.clone()))
+        }
+
         // Invalid combinations
         (Some(_), Some(_), Some(_)) => {
-            Err("Cannot specify both IP/device-id and alias. Use either --ip and --device-id, or --alias.".into())
+            Err(SwitcherError::Usage("Cannot specify both IP/device-id and alias. Use either --ip and --device-id, or --alias.".to_string()).into())
         }
-        (Some(_), None, None) | (None, Some(_), None) => {
-            Err("When using IP/device-id, both --ip and --device-id are required.".into())
+        (None, Some(_), None) => {
+            Err(SwitcherError::Usage("When using --device-id, --ip is required too.".to_string()).into())
         }
+        // No selector: fall back to the default paired device
         (None, None, None) => {
-            Err("Must specify either --ip and --device-id, or --alias for a paired device.".into())
+            let pairing = load_pairing_config()?;
+
+            let default_device = pairing.get_default_device().ok_or_else(|| SwitcherError::Usage(
+                "Must specify either --ip and --device-id, or --alias for a paired device (or set a default with 'set-default --alias <alias>').".to_string(),
+            ))?;
+
+            Ok((default_device.device.ip_address.clone(), default_device.device.device_id.clone()))
         }
         (None, Some(_), Some(_)) | (Some(_), None, Some(_)) => {
-            Err("Cannot mix IP/device-id with alias. Use either --ip and --device-id, or --alias.".into())
+            Err(SwitcherError::Usage("Cannot mix IP/device-id with alias. Use either --ip and --device-id, or --alias.".to_string()).into())
         }
     }
 }
+
+/// Load the pairing config, reporting failures as config errors
+fn load_pairing_config() -> Result<pairing::PairingConfig, SwitcherError> {
+    PairingManager::new()
+        .and_then(|pairing_manager| pairing_manager.load_pairing())
+        .map_err(|e| SwitcherError::Config(format!("Could not load pairing config: {}", e)))
+}
@@ -0,0 +1,122 @@
+use crate::device::{DeviceState, DeviceStatus};
+use std::fmt::Write;
+
+/// One paired device's reading for the `metrics` command. `status` is None
+/// when the device couldn't be queried.
+#[derive(Debug, Clone)]
+pub struct DeviceSample {
+    pub alias: String,
+    pub device_id: String,
+    pub status: Option<DeviceStatus>,
+}
+
+/// Render samples in the Prometheus text exposition format. Metric names are
+/// stable; every series is labelled with `alias` and `device_id`.
+pub fn render(samples: &[DeviceSample]) -> String {
+    let mut output = String::new();
+
+    write_family(
+        &mut output,
+        "switcher_up",
+        "Whether the device answered the status query",
+        samples
+            .iter()
+            .map(|sample| (sample, Some(sample.status.is_some() as u16))),
+    );
+    write_family(
+        &mut output,
+        "switcher_power_watts",
+        "Current power draw in watts",
+        samples.iter().map(|sample| {
+            let watts = sample
+                .status
+                .as_ref()
+                .map(|status| status.power_consumption);
+            (sample, watts)
+        }),
+    );
+    write_family(
+        &mut output,
+        "switcher_state",
+        "Whether the device is on (1) or off (0)",
+        samples.iter().map(|sample| {
+            let state = sample
+                .status
+                .as_ref()
+                .and_then(|status| match status.state {
+                    DeviceState::On => Some(1),
+                    DeviceState::Off => Some(0),
+                    DeviceState::Unknown => None,
+                });
+            (sample, state)
+        }),
+    );
+
+    output
+}
+
+/// Write one metric family; samples without a value are left out
+fn write_family<'a>(
+    output: &mut String,
+    name: &str,
+    help: &str,
+    values: impl Iterator<Item = (&'a DeviceSample, Option<u16>)>,
+) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} gauge", name);
+    for (sample, value) in values {
+        if let Some(value) = value {
+            let _ = writeln!(
+                output,
+                "{}{{alias=\"{}\",device_id=\"{}\"}} {}",
+                name,
+                escape_label(&sample.alias),
+                escape_label(&sample.device_id),
+                value
+            );
+        }
+    }
+}
+
+/// Escape a label value as the exposition format requires
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let samples = vec![
+            DeviceSample {
+                alias: "Office".to_string(),
+                device_id: "9c4f22".to_string(),
+                status: Some(DeviceStatus {
+                    state: DeviceState::On,
+                    power_consumption: 12,
+                }),
+            },
+            DeviceSample {
+                alias: "Kid's \"Lamp\"".to_string(),
+                device_id: "123456".to_string(),
+                status: None,
+            },
+        ];
+
+        let output = render(&samples);
+
+        assert!(output.contains("# TYPE switcher_power_watts gauge\n"));
+        assert!(output.contains("switcher_up{alias=\"Office\",device_id=\"9c4f22\"} 1\n"));
+        assert!(output.contains("switcher_power_watts{alias=\"Office\",device_id=\"9c4f22\"} 12\n"));
+        assert!(output.contains("switcher_state{alias=\"Office\",device_id=\"9c4f22\"} 1\n"));
+        assert!(
+            output.contains("switcher_up{alias=\"Kid's \\\"Lamp\\\"\",device_id=\"123456\"} 0\n")
+        );
+        assert!(!output.contains("switcher_power_watts{alias=\"Kid's"));
+    }
+}
@@ -1,19 +1,159 @@
 use crate::config::ConfigManager;
 use crate::device::SwitcherDevice;
+use crate::error::SwitcherError;
 use crate::utils::current_timestamp;
 use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// How recently a paired device must have been seen to count as online
+pub const DEFAULT_ONLINE_THRESHOLD_SECS: u64 = 3600;
+
+pub const MAX_ALIAS_LEN: usize = 32; // characters
+
+/// Separates aliases in lists such as group members, so it can't appear in one
+pub const ALIAS_LIST_DELIMITER: char = ',';
+
+/// Check an alias before pairing: 1 to `MAX_ALIAS_LEN` characters, no
+/// control characters, no list delimiter and no surrounding whitespace
+pub fn validate_alias(alias: &str) -> Result<(), String> {
+    let length = alias.chars().count();
+    if length == 0 || length > MAX_ALIAS_LEN {
+        return Err(format!(
+            "Alias must be between 1 and {} characters, got {}",
+            MAX_ALIAS_LEN, length
+        ));
+    }
+    if alias.trim() != alias {
+        return Err(format!(
+            "Alias '{}' must not start or end with whitespace",
+            alias
+        ));
+    }
+    if alias.chars().any(char::is_control) {
+        return Err("Alias must not contain control characters".to_string());
+    }
+    if alias.contains(ALIAS_LIST_DELIMITER) {
+        return Err(format!(
+            "Alias '{}' must not contain '{}'",
+            alias, ALIAS_LIST_DELIMITER
+        ));
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct PairedDevice {
     pub device: SwitcherDevice,
     pub alias: String,
     pub paired_at: u64,
     pub last_seen: u64,
+    #[serde(default)]
+    pub is_default: bool, // used when a command is given no device selector
+    /// Label color name set with `set-color`. Kept as a string so a name
+    /// this version doesn't know falls back to default coloring.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// A paired device whose IP or name was refreshed by `reconcile`
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconcileChange {
+    pub alias: String,
+    pub device_id: String,
+    pub old_ip: String,
+    pub new_ip: String,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+impl AsRef<SwitcherDevice> for PairedDevice {
+    fn as_ref(&self) -> &SwitcherDevice {
+        &self.device
+    }
+}
+
+impl From<&PairedDevice> for SwitcherDevice {
+    fn from(paired: &PairedDevice) -> Self {
+        paired.device.clone()
+    }
+}
+
+/// Colors `set-color` accepts for a paired device's alias
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl LabelColor {
+    pub const ALL: [LabelColor; 6] = [
+        LabelColor::Red,
+        LabelColor::Green,
+        LabelColor::Yellow,
+        LabelColor::Blue,
+        LabelColor::Magenta,
+        LabelColor::Cyan,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LabelColor::Red => "red",
+            LabelColor::Green => "green",
+            LabelColor::Yellow => "yellow",
+            LabelColor::Blue => "blue",
+            LabelColor::Magenta => "magenta",
+            LabelColor::Cyan => "cyan",
+        }
+    }
+
+    /// Wrap `text` in this color's ANSI foreground escape
+    pub fn paint(self, text: &str) -> String {
+        let code = match self {
+            LabelColor::Red => 31,
+            LabelColor::Green => 32,
+            LabelColor::Yellow => 33,
+            LabelColor::Blue => 34,
+            LabelColor::Magenta => 35,
+            LabelColor::Cyan => 36,
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    }
+}
+
+impl std::str::FromStr for LabelColor {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        LabelColor::ALL
+            .into_iter()
+            .find(|color| color.name().eq_ignore_ascii_case(value))
+            .ok_or_else(|| {
+                let names: Vec<_> = LabelColor::ALL.iter().map(|color| color.name()).collect();
+                format!("Unknown color '{}'. Supported: {}", value, names.join(", "))
+            })
+    }
+}
+
+impl PairedDevice {
+    /// The stored label color, if it is one this version supports
+    pub fn label_color(&self) -> Option<LabelColor> {
+        self.color.as_deref()?.parse().ok()
+    }
+
+    /// Whether the device was seen within the last `threshold_secs` seconds
+    pub fn seen_within(&self, threshold_secs: u64) -> bool {
+        current_timestamp().saturating_sub(self.last_seen) < threshold_secs
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct PairingConfig {
     pub devices: HashMap<String, PairedDevice>, // device_id -> PairedDevice
     pub aliases: HashMap<String, String>,       // alias -> device_id
@@ -29,12 +169,32 @@ impl PairingConfig {
         }
     }
 
+    /// Pull future `paired_at`/`last_seen` values back to `now`, like
+    /// `DeviceCache::clamp_future_timestamps`. Returns how many were changed.
+    pub fn clamp_future_timestamps(&mut self, now: u64) -> usize {
+        let mut clamped = 0;
+        for paired in self.devices.values_mut() {
+            for timestamp in [&mut paired.paired_at, &mut paired.last_seen] {
+                if *timestamp > now {
+                    *timestamp = now;
+                    clamped += 1;
+                }
+            }
+        }
+        clamped
+    }
+
     pub fn pair_device(&mut self, device: SwitcherDevice, alias: String) -> Result<(), String> {
         debug!(
             "Attempting to pair device {} with alias '{}'",
             device.device_id, alias
         );
 
+        if let Err(e) = validate_alias(&alias) {
+            warn!("Pairing failed: {}", e);
+            return Err(e);
+        }
+
         if self.aliases.contains_key(&alias) {
             warn!("Pairing failed: alias '{}' is already in use", alias);
             return Err(format!("Alias '{}' is already in use", alias));
@@ -42,13 +202,17 @@ impl PairingConfig {
 
         let device_id = device.device_id.clone();
 
-        // Remove old pairing if device was already paired
+        // Remove old pairing if device was already paired, keeping its default marker and color
+        let mut is_default = false;
+        let mut color = None;
         if let Some(old_paired) = self.devices.get(&device_id) {
             info!(
                 "Removing old pairing for device {}: alias '{}'",
                 device_id, old_paired.alias
             );
             self.aliases.remove(&old_paired.alias);
+            is_default = old_paired.is_default;
+            color = old_paired.color.clone();
         }
 
         let paired_device = PairedDevice {
@@ -56,6 +220,8 @@ impl PairingConfig {
             alias: alias.clone(),
             paired_at: current_timestamp(),
             last_seen: current_timestamp(),
+            is_default,
+            color,
         };
 
         self.devices.insert(device_id.clone(), paired_device);
@@ -92,6 +258,56 @@ impl PairingConfig {
         Ok(())
     }
 
+    /// Point an existing alias at a new device identity, e.g. after a factory
+    /// reset. The alias, default marker and `paired_at` are kept. Returns the
+    /// previous device ID.
+    pub fn rekey_device(&mut self, alias: &str, device: SwitcherDevice) -> Result<String, String> {
+        let old_device_id = self
+            .aliases
+            .get(alias)
+            .ok_or_else(|| format!("No device found with alias '{}'", alias))?
+            .clone();
+        let new_device_id = device.device_id.clone();
+
+        if new_device_id == old_device_id {
+            return Err(format!(
+                "Alias '{}' is already paired with device {}",
+                alias, new_device_id
+            ));
+        }
+        if let Some(other) = self.devices.get(&new_device_id) {
+            return Err(format!(
+                "Device {} is already paired as '{}'",
+                new_device_id, other.alias
+            ));
+        }
+
+        let old_paired = self
+            .devices
+            .remove(&old_device_id)
+            .ok_or_else(|| format!("No device found with alias '{}'", alias))?;
+
+        let paired_device = PairedDevice {
+            device,
+            alias: alias.to_string(),
+            paired_at: old_paired.paired_at,
+            last_seen: current_timestamp(),
+            is_default: old_paired.is_default,
+            color: old_paired.color,
+        };
+
+        self.devices.insert(new_device_id.clone(), paired_device);
+        self.aliases
+            .insert(alias.to_string(), new_device_id.clone());
+        self.last_updated = current_timestamp();
+
+        info!(
+            "Re-keyed alias '{}' from device {} to {}",
+            alias, old_device_id, new_device_id
+        );
+        Ok(old_device_id)
+    }
+
     pub fn get_device_by_alias(&self, alias: &str) -> Option<&PairedDevice> {
         let device_id = self.aliases.get(alias)?;
         self.devices.get(device_id)
@@ -101,6 +317,82 @@ impl PairingConfig {
         self.devices.values().collect()
     }
 
+    /// The devices in `devices` that aren't paired yet, in the given order
+    pub fn unpaired<'a>(&self, devices: &'a [SwitcherDevice]) -> Vec<&'a SwitcherDevice> {
+        devices
+            .iter()
+            .filter(|device| !self.devices.contains_key(&device.device_id))
+            .collect()
+    }
+
+    /// Mark the device with the given alias as the default, clearing any previous default
+    pub fn set_default(&mut self, alias: &str) -> Result<(), String> {
+        let device_id = self
+            .aliases
+            .get(alias)
+            .ok_or_else(|| format!("No device found with alias '{}'", alias))?
+            .clone();
+
+        for (id, paired_device) in self.devices.iter_mut() {
+            paired_device.is_default = *id == device_id;
+        }
+        self.last_updated = current_timestamp();
+
+        info!("Set device {} (alias: '{}') as default", device_id, alias);
+        Ok(())
+    }
+
+    /// Set or clear (`None`) the label color of the device with the given alias
+    pub fn set_color(&mut self, alias: &str, color: Option<LabelColor>) -> Result<(), String> {
+        let device_id = self
+            .aliases
+            .get(alias)
+            .ok_or_else(|| format!("No device found with alias '{}'", alias))?;
+        let paired_device = self
+            .devices
+            .get_mut(device_id)
+            .ok_or_else(|| format!("No device found with alias '{}'", alias))?;
+        paired_device.color = color.map(|color| color.name().to_string());
+        self.last_updated = current_timestamp();
+        Ok(())
+    }
+
+    pub fn get_default_device(&self) -> Option<&PairedDevice> {
+        self.devices.values().find(|d| d.is_default)
+    }
+
+    /// Copy the latest IP and name of each freshly cached device into its
+    /// paired record, returning the records whose IP or name changed
+    pub fn reconcile(&mut self, fresh_devices: &[SwitcherDevice]) -> Vec<ReconcileChange> {
+        let mut changes = Vec::new();
+
+        for device in fresh_devices {
+            let Some(paired) = self.devices.get(&device.device_id) else {
+                continue;
+            };
+            let change = ReconcileChange {
+                alias: paired.alias.clone(),
+                device_id: device.device_id.clone(),
+                old_ip: paired.device.ip_address.clone(),
+                new_ip: device.ip_address.clone(),
+                old_name: paired.device.name.clone(),
+                new_name: device.name.clone(),
+            };
+
+            self.update_device_info(device);
+            if change.old_ip != change.new_ip || change.old_name != change.new_name {
+                info!(
+                    "Reconciled '{}': {} -> {}",
+                    change.alias, change.old_ip, change.new_ip
+                );
+                changes.push(change);
+            }
+        }
+
+        changes.sort_by(|a, b| a.alias.cmp(&b.alias));
+        changes
+    }
+
     /// Update device information and last_seen timestamp for a paired device
     pub fn update_device_info(&mut self, device: &SwitcherDevice) -> bool {
         if let Some(paired_device) = self.devices.get_mut(&device.device_id) {
@@ -114,22 +406,33 @@ impl PairingConfig {
     }
 }
 
+impl Default for PairingConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct PairingManager {
     config_manager: ConfigManager,
 }
 
 impl PairingManager {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new() -> Result<Self, SwitcherError> {
         let config_manager = ConfigManager::new()?;
         Ok(Self { config_manager })
     }
 
-    pub fn load_pairing(&self) -> Result<PairingConfig, Box<dyn std::error::Error>> {
+    #[cfg(test)]
+    pub(crate) fn with_config_manager(config_manager: ConfigManager) -> Self {
+        Self { config_manager }
+    }
+
+    pub fn load_pairing(&self) -> Result<PairingConfig, SwitcherError> {
         debug!("Loading pairing configuration");
         self.config_manager.load_pairing_data()
     }
 
-    pub fn save_pairing(&self, pairing: &PairingConfig) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn save_pairing(&self, pairing: &PairingConfig) -> Result<(), SwitcherError> {
         debug!(
             "Saving pairing configuration with {} devices",
             pairing.devices.len()
@@ -149,8 +452,9 @@ mod tests {
             name: name.to_string(),
             ip_address: ip.to_string(),
             mac_address: "00:11:22:33:44:55".to_string(),
-            device_key: "a1".to_string(),
+            device_key: Some("a1".to_string()),
             device_type: "Switcher Power Plug".to_string(),
+            device_type_code: "01a8".to_string(),
             state: DeviceState::Off,
             power_consumption: 0,
         }
@@ -168,6 +472,107 @@ mod tests {
         assert!(pairing.aliases.contains_key("Test Alias"));
     }
 
+    #[test]
+    fn test_pairing_round_trip_through_file() {
+        let dir =
+            std::env::temp_dir().join(format!("switcher-pairing-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("switcher_config.json");
+        let manager =
+            PairingManager::with_config_manager(ConfigManager::with_path(config_path.clone()));
+        ConfigManager::with_path(config_path.clone())
+            .clear_config()
+            .unwrap();
+
+        // Nothing saved yet loads as an empty config
+        assert!(manager.load_pairing().unwrap().devices.is_empty());
+
+        let mut pairing = PairingConfig::new();
+        pairing
+            .pair_device(
+                create_test_device("123", "Heater", "192.168.1.100"),
+                "Heater".to_string(),
+            )
+            .unwrap();
+        pairing
+            .pair_device(
+                create_test_device("456", "Lamp", "192.168.1.101"),
+                "Lamp".to_string(),
+            )
+            .unwrap();
+        pairing.set_default("Lamp").unwrap();
+        manager.save_pairing(&pairing).unwrap();
+
+        // Saving the cache afterwards must not wipe the pairing section
+        let config_manager = ConfigManager::with_path(config_path.clone());
+        config_manager
+            .save_cache_data(&crate::cache::DeviceCache::new())
+            .unwrap();
+
+        let reloaded = PairingManager::with_config_manager(ConfigManager::with_path(config_path))
+            .load_pairing()
+            .unwrap();
+        assert_eq!(reloaded.devices.len(), 2);
+        assert_eq!(reloaded.aliases, pairing.aliases);
+        let heater = reloaded.get_device_by_alias("Heater").unwrap();
+        assert_eq!(heater.device.ip_address, "192.168.1.100");
+        assert_eq!(heater.paired_at, pairing.devices["123"].paired_at);
+        assert!(!heater.is_default);
+        assert_eq!(reloaded.get_default_device().unwrap().alias, "Lamp");
+
+        config_manager.clear_config().unwrap();
+    }
+
+    #[test]
+    fn test_unpaired() {
+        let mut pairing = PairingConfig::new();
+        let discovered = vec![
+            create_test_device("123", "Heater", "192.168.1.100"),
+            create_test_device("456", "Lamp", "192.168.1.101"),
+        ];
+        pairing
+            .pair_device(discovered[0].clone(), "Heater".to_string())
+            .unwrap();
+
+        let unpaired = pairing.unpaired(&discovered);
+        assert_eq!(unpaired.len(), 1);
+        assert_eq!(unpaired[0].device_id, "456");
+    }
+
+    #[test]
+    fn test_label_color() {
+        let mut pairing = PairingConfig::new();
+        pairing
+            .pair_device(
+                create_test_device("123", "Heater", "192.168.1.100"),
+                "Heater".to_string(),
+            )
+            .unwrap();
+
+        assert_eq!("RED".parse::<LabelColor>(), Ok(LabelColor::Red));
+        assert!("mauve"
+            .parse::<LabelColor>()
+            .unwrap_err()
+            .contains("red, green"));
+        assert!(pairing.set_color("Missing", Some(LabelColor::Red)).is_err());
+
+        pairing.set_color("Heater", Some(LabelColor::Red)).unwrap();
+        let paired = pairing.get_device_by_alias("Heater").unwrap();
+        assert_eq!(paired.color.as_deref(), Some("red"));
+        assert_eq!(paired.label_color(), Some(LabelColor::Red));
+        assert_eq!(LabelColor::Red.paint("Heater"), "\x1b[31mHeater\x1b[0m");
+
+        // A color from a newer version falls back to default coloring
+        pairing.devices.get_mut("123").unwrap().color = Some("ultraviolet".to_string());
+        assert_eq!(
+            pairing.get_device_by_alias("Heater").unwrap().label_color(),
+            None
+        );
+
+        pairing.set_color("Heater", None).unwrap();
+        assert_eq!(pairing.get_device_by_alias("Heater").unwrap().color, None);
+    }
+
     #[test]
     fn test_duplicate_alias() {
         let mut pairing = PairingConfig::new();
@@ -181,6 +586,34 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_invalid_aliases_rejected() {
+        let mut pairing = PairingConfig::new();
+        for alias in [
+            "",
+            " Office",
+            "Office ",
+            "Office,Kitchen",
+            "Office\nKitchen",
+            "Office\tKitchen",
+            &"a".repeat(MAX_ALIAS_LEN + 1),
+        ] {
+            let device = create_test_device("123", "Test Device", "192.168.1.100");
+            assert!(
+                pairing.pair_device(device, alias.to_string()).is_err(),
+                "alias {:?} should be rejected",
+                alias
+            );
+        }
+        assert!(pairing.devices.is_empty());
+
+        let device = create_test_device("123", "Test Device", "192.168.1.100");
+        pairing
+            .pair_device(device, "Living Room Lamp".to_string())
+            .unwrap();
+        assert!(validate_alias(&"ä".repeat(MAX_ALIAS_LEN)).is_ok());
+    }
+
     #[test]
     fn test_unpair_device() {
         let mut pairing = PairingConfig::new();
@@ -196,4 +629,109 @@ mod tests {
         assert_eq!(pairing.devices.len(), 0);
         assert_eq!(pairing.aliases.len(), 0);
     }
+
+    #[test]
+    fn test_set_default_clears_previous() {
+        let mut pairing = PairingConfig::new();
+        let device1 = create_test_device("123", "Test Device 1", "192.168.1.100");
+        let device2 = create_test_device("456", "Test Device 2", "192.168.1.101");
+
+        pairing.pair_device(device1, "First".to_string()).unwrap();
+        pairing.pair_device(device2, "Second".to_string()).unwrap();
+        assert!(pairing.get_default_device().is_none());
+
+        pairing.set_default("First").unwrap();
+        assert_eq!(pairing.get_default_device().unwrap().alias, "First");
+
+        pairing.set_default("Second").unwrap();
+        assert_eq!(pairing.get_default_device().unwrap().alias, "Second");
+        assert_eq!(pairing.devices.values().filter(|d| d.is_default).count(), 1);
+
+        assert!(pairing.set_default("Missing").is_err());
+    }
+
+    #[test]
+    fn test_reconcile_reports_changes() {
+        let mut pairing = PairingConfig::new();
+        pairing
+            .pair_device(
+                create_test_device("123", "Plug", "192.168.1.5"),
+                "Office".to_string(),
+            )
+            .unwrap();
+        pairing
+            .pair_device(
+                create_test_device("456", "Lamp", "192.168.1.6"),
+                "Lamp".to_string(),
+            )
+            .unwrap();
+
+        let fresh = vec![
+            create_test_device("123", "Plug", "192.168.1.9"),
+            create_test_device("456", "Lamp", "192.168.1.6"),
+            create_test_device("789", "Unpaired", "192.168.1.7"),
+        ];
+        let changes = pairing.reconcile(&fresh);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].alias, "Office");
+        assert_eq!(changes[0].old_ip, "192.168.1.5");
+        assert_eq!(changes[0].new_ip, "192.168.1.9");
+        assert_eq!(
+            pairing
+                .get_device_by_alias("Office")
+                .unwrap()
+                .device
+                .ip_address,
+            "192.168.1.9"
+        );
+        assert_eq!(pairing.devices.len(), 2);
+    }
+
+    #[test]
+    fn test_paired_device_conversions() {
+        let mut pairing = PairingConfig::new();
+        pairing
+            .pair_device(
+                create_test_device("123", "Plug", "192.168.1.5"),
+                "Office".to_string(),
+            )
+            .unwrap();
+        let paired = pairing.get_device_by_alias("Office").unwrap();
+
+        assert_eq!(paired.as_ref().device_id, "123");
+        let device: SwitcherDevice = paired.into();
+        assert_eq!(device.ip_address, "192.168.1.5");
+    }
+
+    #[test]
+    fn test_rekey_device_preserves_metadata() {
+        crate::utils::mock_clock::set(1_000_000);
+        let mut pairing = PairingConfig::new();
+        let old_device = create_test_device("123", "Plug", "192.168.1.100");
+        pairing.pair_device(old_device, "Desk".to_string()).unwrap();
+        pairing.set_default("Desk").unwrap();
+        pairing.set_color("Desk", Some(LabelColor::Cyan)).unwrap();
+
+        crate::utils::mock_clock::advance(3600);
+        let new_device = create_test_device("789", "Plug", "192.168.1.100");
+        assert_eq!(pairing.rekey_device("Desk", new_device).unwrap(), "123");
+
+        let paired = pairing.get_device_by_alias("Desk").unwrap();
+        assert_eq!(paired.device.device_id, "789");
+        assert_eq!(paired.paired_at, 1_000_000);
+        assert_eq!(paired.last_seen, 1_003_600);
+        assert!(paired.is_default);
+        assert_eq!(paired.label_color(), Some(LabelColor::Cyan));
+        assert!(!pairing.devices.contains_key("123"));
+        assert_eq!(pairing.devices.len(), 1);
+
+        let other = create_test_device("456", "Lamp", "192.168.1.101");
+        pairing
+            .pair_device(other.clone(), "Lamp".to_string())
+            .unwrap();
+        assert!(pairing.rekey_device("Desk", other).is_err());
+        let missing = create_test_device("999", "Other", "192.168.1.102");
+        assert!(pairing.rekey_device("Missing", missing).is_err());
+    }
 }
@@ -0,0 +1,124 @@
+use crate::cache::PowerSample;
+use crate::utils::format_iso8601;
+use serde::Serialize;
+
+/// Summary of the power samples recorded within a time window
+#[derive(Debug, Clone, Serialize)]
+pub struct PowerStats {
+    pub from: u64, // window start, unix seconds
+    pub to: u64,   // window end, unix seconds
+    pub samples: usize,
+    pub min_watts: Option<u16>,
+    pub avg_watts: Option<f64>,
+    pub max_watts: Option<u16>,
+    pub energy_wh: f64,
+}
+
+/// Summarize the samples with `from <= timestamp <= to`. Energy is the
+/// trapezoidal integral between consecutive samples, so a window needs at
+/// least two samples to report any; gaps between polls count as a straight
+/// line between the two readings.
+pub fn summarize(history: &[PowerSample], from: u64, to: u64) -> PowerStats {
+    let mut window: Vec<PowerSample> = history
+        .iter()
+        .filter(|sample| sample.timestamp >= from && sample.timestamp <= to)
+        .copied()
+        .collect();
+    window.sort_by_key(|sample| sample.timestamp);
+
+    // Folded from 0.0 rather than summed, an empty f64 sum is -0.0
+    let watt_seconds = window.windows(2).fold(0.0, |total, pair| {
+        let secs = (pair[1].timestamp - pair[0].timestamp) as f64;
+        total + secs * (f64::from(pair[0].watts) + f64::from(pair[1].watts)) / 2.0
+    });
+
+    PowerStats {
+        from,
+        to,
+        samples: window.len(),
+        min_watts: window.iter().map(|sample| sample.watts).min(),
+        avg_watts: (!window.is_empty()).then(|| {
+            window
+                .iter()
+                .map(|sample| f64::from(sample.watts))
+                .sum::<f64>()
+                / window.len() as f64
+        }),
+        max_watts: window.iter().map(|sample| sample.watts).max(),
+        energy_wh: watt_seconds / 3600.0,
+    }
+}
+
+/// Write the samples with `from <= timestamp <= to` as `timestamp_iso,watts`
+/// CSV rows, oldest first, for spreadsheets. An empty window still gets the
+/// header. Returns the number of rows written.
+pub fn write_csv<W: std::io::Write>(
+    history: &[PowerSample],
+    from: u64,
+    to: u64,
+    writer: W,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut window: Vec<&PowerSample> = history
+        .iter()
+        .filter(|sample| sample.timestamp >= from && sample.timestamp <= to)
+        .collect();
+    window.sort_by_key(|sample| sample.timestamp);
+
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(["timestamp_iso", "watts"])?;
+    for sample in &window {
+        csv.write_record([format_iso8601(sample.timestamp), sample.watts.to_string()])?;
+    }
+    csv.flush()?;
+    Ok(window.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: u64, watts: u16) -> PowerSample {
+        PowerSample { timestamp, watts }
+    }
+
+    #[test]
+    fn test_summarize_window() {
+        let history = vec![
+            sample(0, 500),
+            sample(3600, 100),
+            sample(5400, 300),
+            sample(7200, 300),
+            sample(9000, 2000),
+        ];
+
+        let stats = summarize(&history, 3600, 7200);
+        assert_eq!(stats.samples, 3);
+        assert_eq!(stats.min_watts, Some(100));
+        assert_eq!(stats.max_watts, Some(300));
+        assert_eq!(stats.avg_watts, Some(700.0 / 3.0));
+        // half an hour averaging 200W, then half an hour at 300W
+        assert!((stats.energy_wh - 250.0).abs() < 1e-9);
+
+        let empty = summarize(&history, 10_000, 20_000);
+        assert_eq!(empty.samples, 0);
+        assert_eq!(empty.min_watts, None);
+        assert_eq!(empty.avg_watts, None);
+        assert!(empty.energy_wh.is_sign_positive());
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let history = vec![sample(1_740_837_909, 12), sample(1_740_837_849, 0)];
+
+        let mut output = Vec::new();
+        assert_eq!(write_csv(&history, 0, u64::MAX, &mut output).unwrap(), 2);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "timestamp_iso,watts\n2025-03-01T14:04:09Z,0\n2025-03-01T14:05:09Z,12\n"
+        );
+
+        let mut output = Vec::new();
+        assert_eq!(write_csv(&history, 0, 1000, &mut output).unwrap(), 0);
+        assert_eq!(String::from_utf8(output).unwrap(), "timestamp_iso,watts\n");
+    }
+}
@@ -0,0 +1,76 @@
+use crate::control::StatusLayout;
+use crate::error::SwitcherError;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Protocol overrides loaded from a `--profile` TOML file, for firmware
+/// that moved fields around or reports a type code this tool doesn't know.
+/// Anything left out keeps the built-in Power Plug value:
+///
+/// ```toml
+/// device_type_codes = ["01a9"]
+///
+/// [status]
+/// state_byte = 76
+/// power_byte = 78
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtocolProfile {
+    /// Extra discovery type codes to treat as a Power Plug
+    pub device_type_codes: Vec<String>,
+    /// Byte offsets in the state query response
+    pub status: StatusLayout,
+}
+
+impl ProtocolProfile {
+    pub fn load(path: &Path) -> Result<Self, SwitcherError> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            SwitcherError::Config(format!("Could not read profile {}: {}", path.display(), e))
+        })?;
+        Self::parse(&contents).map_err(|e| {
+            SwitcherError::Config(format!("Invalid profile {}: {}", path.display(), e))
+        })
+    }
+
+    fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        let mut profile: Self = toml::from_str(contents)?;
+        for code in &mut profile.device_type_codes {
+            *code = code.to_ascii_lowercase();
+        }
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_overrides_only_given_offsets() {
+        let profile = ProtocolProfile::parse(
+            "device_type_codes = [\"01A9\"]\n\n[status]\nstate_byte = 76\npower_byte = 78\n",
+        )
+        .unwrap();
+
+        assert_eq!(profile.device_type_codes, vec!["01a9".to_string()]);
+        assert_eq!(profile.status.state_byte, 76);
+        assert_eq!(profile.status.power_byte, 78);
+        assert_eq!(
+            profile.status.min_response_len,
+            StatusLayout::default().min_response_len
+        );
+        assert_eq!(
+            ProtocolProfile::parse("").unwrap(),
+            ProtocolProfile::default()
+        );
+    }
+
+    #[test]
+    fn test_profile_rejects_unknown_keys() {
+        // A typo must not silently fall back to the default offset
+        assert!(ProtocolProfile::parse("[status]\nstate_bite = 76\n").is_err());
+        assert!(ProtocolProfile::parse("power_byte = 78\n").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::device::SwitcherDevice;
+use crate::pairing::PairedDevice;
+
+/// Placeholders available to `--output-template` for discovered devices
+pub const DEVICE_PLACEHOLDERS: &[&str] = &[
+    "name",
+    "id",
+    "device_id",
+    "ip",
+    "ip_address",
+    "mac",
+    "mac_address",
+    "key",
+    "device_key",
+    "type",
+    "device_type",
+    "type_code",
+    "state",
+    "power",
+    "power_consumption",
+    "alias",
+];
+
+/// Placeholders available to `--output-template` for paired devices
+pub const PAIRED_PLACEHOLDERS: &[&str] = &[
+    "name",
+    "id",
+    "device_id",
+    "ip",
+    "ip_address",
+    "mac",
+    "mac_address",
+    "key",
+    "device_key",
+    "type",
+    "device_type",
+    "type_code",
+    "state",
+    "power",
+    "power_consumption",
+    "alias",
+    "paired_at",
+    "last_seen",
+];
+
+/// Build the placeholder values for a device, with an optional paired alias
+pub fn device_fields(device: &SwitcherDevice, alias: Option<&str>) -> Vec<(&'static str, String)> {
+    let state = format!("{:?}", device.state);
+    let power = device.power_consumption.to_string();
+
+    vec![
+        ("name", device.name.clone()),
+        ("id", device.device_id.clone()),
+        ("device_id", device.device_id.clone()),
+        ("ip", device.ip_address.clone()),
+        ("ip_address", device.ip_address.clone()),
+        ("mac", device.mac_address.clone()),
+        ("mac_address", device.mac_address.clone()),
+        ("key", device.device_key.clone().unwrap_or_default()),
+        ("device_key", device.device_key.clone().unwrap_or_default()),
+        ("type", device.device_type.clone()),
+        ("device_type", device.device_type.clone()),
+        ("type_code", device.device_type_code.clone()),
+        ("state", state),
+        ("power", power.clone()),
+        ("power_consumption", power),
+        ("alias", alias.unwrap_or_default().to_string()),
+    ]
+}
+
+/// Build the placeholder values for a paired device
+pub fn paired_fields(paired: &PairedDevice) -> Vec<(&'static str, String)> {
+    let mut fields = device_fields(&paired.device, Some(&paired.alias));
+    fields.push(("paired_at", paired.paired_at.to_string()));
+    fields.push(("last_seen", paired.last_seen.to_string()));
+    fields
+}
+
+/// Check that a template only references known placeholders
+pub fn validate(template: &str, allowed: &[&str]) -> Result<(), String> {
+    let fields: Vec<(&str, String)> = allowed.iter().map(|f| (*f, String::new())).collect();
+    render(template, &fields).map(|_| ())
+}
+
+/// Substitute `{field}` placeholders in a template.
+///
+/// `{{` and `}}` produce literal braces, and the escapes `\t`, `\n` and `\\`
+/// are expanded so templates can be passed through the shell unquoted.
+pub fn render(template: &str, fields: &[(&str, String)]) -> Result<String, String> {
+    let mut output = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(format!("Unclosed placeholder '{{{}'", name)),
+                    }
+                }
+
+                let value = fields
+                    .iter()
+                    .find(|(field, _)| *field == name)
+                    .map(|(_, value)| value)
+                    .ok_or_else(|| {
+                        let available: Vec<&str> = fields.iter().map(|(field, _)| *field).collect();
+                        format!(
+                            "Unknown placeholder '{{{}}}'. Available: {}",
+                            name,
+                            available.join(", ")
+                        )
+                    })?;
+                output.push_str(value);
+            }
+            '}' => return Err("Unmatched '}' in template (use '}}' for a literal brace)".into()),
+            '\\' => match chars.next() {
+                Some('t') => output.push('\t'),
+                Some('n') => output.push('\n'),
+                Some('\\') => output.push('\\'),
+                Some(other) => {
+                    output.push('\\');
+                    output.push(other);
+                }
+                None => output.push('\\'),
+            },
+            _ => output.push(c),
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::DeviceState;
+
+    fn create_test_device() -> SwitcherDevice {
+        SwitcherDevice {
+            device_id: "123".to_string(),
+            name: "Office".to_string(),
+            ip_address: "192.168.1.100".to_string(),
+            mac_address: "00:11:22:33:44:55".to_string(),
+            device_key: Some("a1".to_string()),
+            device_type: "Switcher Power Plug".to_string(),
+            device_type_code: "01a8".to_string(),
+            state: DeviceState::On,
+            power_consumption: 12,
+        }
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let fields = device_fields(&create_test_device(), None);
+        let output = render("{name}\\t{ip}\\t{power}W", &fields).unwrap();
+        assert_eq!(output, "Office\t192.168.1.100\t12W");
+    }
+
+    #[test]
+    fn test_render_literal_braces() {
+        let fields = device_fields(&create_test_device(), Some("desk"));
+        let output = render("{{{alias}}}", &fields).unwrap();
+        assert_eq!(output, "{desk}");
+    }
+
+    #[test]
+    fn test_unknown_placeholder() {
+        let result = validate("{name} {watts}", DEVICE_PLACEHOLDERS);
+        assert!(result.unwrap_err().contains("{watts}"));
+    }
+
+    #[test]
+    fn test_unclosed_placeholder() {
+        assert!(validate("{name", DEVICE_PLACEHOLDERS).is_err());
+        assert!(validate("name}", DEVICE_PLACEHOLDERS).is_err());
+    }
+}
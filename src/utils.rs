@@ -1,33 +1,329 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use time::format_description::{well_known::Rfc3339, FormatItem};
+use time::{macros::format_description, OffsetDateTime, UtcOffset};
+
+/// Source of the current time in Unix seconds
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+/// Wall-clock time from the operating system
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+}
+
+/// Settable clock for tests, so freshness logic can be exercised without
+/// sleeping. Each test thread has its own time; until `set` is called the
+/// mock follows the system clock.
+#[cfg(test)]
+pub mod mock_clock {
+    use super::{Clock, SystemClock};
+    use std::cell::Cell;
+
+    thread_local! {
+        static NOW: Cell<Option<u64>> = const { Cell::new(None) };
+    }
+
+    pub struct MockClock;
+
+    impl Clock for MockClock {
+        fn now(&self) -> u64 {
+            NOW.with(|now| now.get())
+                .unwrap_or_else(|| SystemClock.now())
+        }
+    }
+
+    pub fn set(timestamp: u64) {
+        NOW.with(|now| now.set(Some(timestamp)));
+    }
+
+    pub fn advance(secs: u64) {
+        set(MockClock.now() + secs);
+    }
+}
 
 pub fn current_timestamp() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs()
+    #[cfg(test)]
+    return mock_clock::MockClock.now();
+
+    #[cfg(not(test))]
+    SystemClock.now()
 }
 
 pub fn current_timestamp_hex() -> String {
     format!("{:08x}", current_timestamp())
 }
 
+/// Relative age such as `5 minutes ago`. A future timestamp, left by a
+/// clock that jumped back, reads as `0 seconds ago`.
 pub fn format_timestamp(timestamp: u64) -> String {
-    let duration = std::time::Duration::from_secs(timestamp);
-    let datetime = UNIX_EPOCH + duration;
-
-    match datetime.elapsed() {
-        Ok(elapsed) => {
-            let secs = elapsed.as_secs();
-            if secs < 60 {
-                format!("{} seconds ago", secs)
-            } else if secs < 3600 {
-                format!("{} minutes ago", secs / 60)
-            } else if secs < 86400 {
-                format!("{} hours ago", secs / 3600)
-            } else {
-                format!("{} days ago", secs / 86400)
+    let secs = current_timestamp().saturating_sub(timestamp);
+    if secs < 60 {
+        format!("{} seconds ago", secs)
+    } else if secs < 3600 {
+        format!("{} minutes ago", secs / 60)
+    } else if secs < 86400 {
+        format!("{} hours ago", secs / 3600)
+    } else {
+        format!("{} days ago", secs / 86400)
+    }
+}
+
+/// Which clock absolute dates are shown in (`--timezone`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timezone {
+    #[default]
+    Local,
+    Utc,
+}
+
+impl std::str::FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "local" => Ok(Timezone::Local),
+            "utc" => Ok(Timezone::Utc),
+            _ => Err(format!(
+                "'{}' is not a timezone (expected local or utc)",
+                value
+            )),
+        }
+    }
+}
+
+/// UTC offset used by `format_datetime`; UTC until `set_timezone` is called
+static DISPLAY_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
+const DATETIME_FORMAT: &[FormatItem<'static>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+
+/// Fix the offset absolute dates are shown in. The local offset can only be
+/// read while the process is single-threaded, so call this before starting
+/// the async runtime. Falls back to UTC if the local offset is unknown.
+pub fn set_timezone(timezone: Timezone) {
+    let offset = match timezone {
+        Timezone::Utc => UtcOffset::UTC,
+        Timezone::Local => UtcOffset::current_local_offset().unwrap_or_else(|_| {
+            log::warn!("Could not determine the local UTC offset, showing dates in UTC");
+            UtcOffset::UTC
+        }),
+    };
+    let _ = DISPLAY_OFFSET.set(offset);
+}
+
+/// Absolute date in the `--timezone` offset, e.g. `2025-03-01 14:05:09 +01:00`
+/// or `2025-03-01 13:05:09 UTC`. The offset in effect now is used for every
+/// date, so times from the other side of a DST change are an hour off.
+pub fn format_datetime(timestamp: u64) -> String {
+    format_datetime_at(
+        timestamp,
+        DISPLAY_OFFSET.get().copied().unwrap_or(UtcOffset::UTC),
+    )
+}
+
+/// RFC 3339 / ISO 8601 date in UTC, e.g. `2025-03-01T13:05:09Z`, for
+/// exports read by other programs. Unlike `format_datetime` it ignores
+/// `--timezone`.
+pub fn format_iso8601(timestamp: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp as i64)
+        .ok()
+        .and_then(|datetime| datetime.format(&Rfc3339).ok())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+fn format_datetime_at(timestamp: u64, offset: UtcOffset) -> String {
+    let Ok(datetime) = OffsetDateTime::from_unix_timestamp(timestamp as i64) else {
+        return timestamp.to_string();
+    };
+    let datetime = datetime
+        .to_offset(offset)
+        .format(DATETIME_FORMAT)
+        .unwrap_or_else(|_| timestamp.to_string());
+    if offset.is_utc() {
+        format!("{} UTC", datetime)
+    } else {
+        let (hours, minutes, _) = offset.as_hms();
+        format!("{} {:+03}:{:02}", datetime, hours, minutes.abs())
+    }
+}
+
+/// Parse a human-friendly duration such as `90`, `30s`, `15m`, `1h30m` or `2d`.
+///
+/// A bare number is taken as seconds.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("Duration cannot be empty".to_string());
+    }
+
+    if let Ok(secs) = input.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let multiplier = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => {
+                return Err(format!(
+                    "Invalid duration '{}': unknown unit '{}'",
+                    input, c
+                ))
             }
+        };
+        let value: u64 = number.parse().map_err(|_| {
+            format!(
+                "Invalid duration '{}': expected a number before '{}'",
+                input, c
+            )
+        })?;
+        total = value
+            .checked_mul(multiplier)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| format!("Duration '{}' is too large", input))?;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        return Err(format!(
+            "Invalid duration '{}': missing unit after '{}' (use s, m, h or d)",
+            input, number
+        ));
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
+/// Format seconds in the compact form accepted by `parse_duration`, e.g. `1h30m`
+pub fn format_duration(secs: u64) -> String {
+    if secs == 0 {
+        return "0s".to_string();
+    }
+
+    let mut output = String::new();
+    let mut remaining = secs;
+    for (unit, size) in [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)] {
+        if remaining >= size {
+            output.push_str(&format!("{}{}", remaining / size, unit));
+            remaining %= size;
         }
-        Err(_) => "in the future".to_string(),
+    }
+    output
+}
+
+/// Case-insensitive substring match used by the `--name-contains` filters
+pub fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// `NAME=value` for `eval`, single-quoting the value unless it is made only
+/// of characters the shell leaves alone
+pub fn env_assignment(name: &str, value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.:/@%+,-".contains(c));
+    if plain {
+        format!("{}={}", name, value)
+    } else {
+        format!("{}='{}'", name, value.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_assignment() {
+        assert_eq!(
+            env_assignment("SWITCHER_IP", "192.168.1.5"),
+            "SWITCHER_IP=192.168.1.5"
+        );
+        assert_eq!(env_assignment("SWITCHER_ALIAS", ""), "SWITCHER_ALIAS=''");
+        assert_eq!(
+            env_assignment("SWITCHER_ALIAS", "Kid's $(rm) room"),
+            "SWITCHER_ALIAS='Kid'\\''s $(rm) room'"
+        );
+    }
+
+    #[test]
+    fn test_contains_ignore_case() {
+        assert!(contains_ignore_case("Bedroom Heater", "heater"));
+        assert!(contains_ignore_case("HEATER", "Heat"));
+        assert!(contains_ignore_case("anything", ""));
+        assert!(!contains_ignore_case("Kitchen", "heater"));
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        mock_clock::set(1_000_000);
+
+        assert_eq!(format_timestamp(1_000_000 - 30), "30 seconds ago");
+        assert_eq!(format_timestamp(1_000_000 - 7200), "2 hours ago");
+        assert_eq!(format_timestamp(1_000_000 + 10), "0 seconds ago");
+
+        mock_clock::advance(86400 * 3);
+        assert_eq!(format_timestamp(1_000_000), "3 days ago");
+    }
+
+    #[test]
+    fn test_format_datetime() {
+        assert_eq!(
+            format_datetime_at(1_740_837_909, UtcOffset::UTC),
+            "2025-03-01 14:05:09 UTC"
+        );
+        let offset = UtcOffset::from_hms(1, 0, 0).unwrap();
+        assert_eq!(
+            format_datetime_at(1_740_837_909, offset),
+            "2025-03-01 15:05:09 +01:00"
+        );
+        let offset = UtcOffset::from_hms(-3, -30, 0).unwrap();
+        assert_eq!(
+            format_datetime_at(1_740_837_909, offset),
+            "2025-03-01 10:35:09 -03:30"
+        );
+
+        assert_eq!("UTC".parse(), Ok(Timezone::Utc));
+        assert!("cet".parse::<Timezone>().is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("2d").unwrap(), Duration::from_secs(172800));
+
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert!(parse_duration("5w").is_err());
+        assert!(parse_duration("h").is_err());
+
+        assert_eq!(format_duration(5400), "1h30m");
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(
+            parse_duration(&format_duration(90061)).unwrap().as_secs(),
+            90061
+        );
     }
 }
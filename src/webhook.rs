@@ -0,0 +1,96 @@
+use crate::device::DeviceState;
+use crate::utils::current_timestamp;
+use log::{debug, warn};
+use serde::Serialize;
+use tokio::time::Duration;
+
+/// How long a webhook may take before the command gives up on it
+const WEBHOOK_TIMEOUT_SECS: u64 = 3;
+
+/// JSON body POSTed to `--webhook` after an on/off command
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandEvent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    pub device_id: String,
+    pub action: DeviceState,
+    pub success: bool,
+    pub state: Option<DeviceState>, // None when the state wasn't verified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub timestamp: u64,
+}
+
+impl CommandEvent {
+    pub fn new<T, E: std::fmt::Display>(
+        alias: Option<String>,
+        device_id: String,
+        action: DeviceState,
+        verified: bool,
+        result: &Result<T, E>,
+    ) -> Self {
+        Self {
+            alias,
+            device_id,
+            action,
+            success: result.is_ok(),
+            state: (result.is_ok() && verified).then_some(action),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            timestamp: current_timestamp(),
+        }
+    }
+}
+
+/// POST the event to `url`. Failures and slow endpoints are logged and
+/// otherwise ignored so a dead webhook never fails the command.
+pub async fn notify(url: &str, event: &CommandEvent) {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Could not create webhook client: {}", e);
+            return;
+        }
+    };
+
+    match client.post(url).json(event).send().await {
+        Ok(response) if response.status().is_success() => {
+            debug!("Webhook {} answered {}", url, response.status());
+        }
+        Ok(response) => warn!("Webhook {} answered {}", url, response.status()),
+        Err(e) => warn!("Webhook {} failed: {}", url, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_event_json() {
+        let ok: Result<(), Box<dyn std::error::Error>> = Ok(());
+        let event = CommandEvent::new(
+            Some("Heater".to_string()),
+            "9c4f22".to_string(),
+            DeviceState::On,
+            true,
+            &ok,
+        );
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["alias"], "Heater");
+        assert_eq!(json["action"], "on");
+        assert_eq!(json["success"], true);
+        assert_eq!(json["state"], "on");
+        assert!(json.get("error").is_none());
+
+        let failed: Result<(), Box<dyn std::error::Error>> = Err("timed out".into());
+        let event = CommandEvent::new(None, "9c4f22".to_string(), DeviceState::Off, true, &failed);
+        let json = serde_json::to_value(&event).unwrap();
+        assert!(json.get("alias").is_none());
+        assert_eq!(json["success"], false);
+        assert_eq!(json["state"], serde_json::Value::Null);
+        assert_eq!(json["error"], "timed out");
+    }
+}
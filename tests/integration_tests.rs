@@ -2,11 +2,10 @@ use std::io::{self, Write};
 use std::time::Duration;
 use tokio::time::timeout;
 
-use switcher_rust::cache::CacheManager;
-use switcher_rust::control::SwitcherController;
-use switcher_rust::device::{DeviceState, SwitcherDevice};
-use switcher_rust::discovery::SwitcherDiscovery;
-use switcher_rust::pairing::PairingManager;
+use switcher_rust::{
+    CacheManager, DeviceState, PairingManager, SwitcherController, SwitcherDevice,
+    SwitcherDiscovery, SwitcherError,
+};
 
 struct TestResults {
     passed: usize,
@@ -129,8 +128,7 @@ async fn test_status_commands(results: &mut TestResults, real_device: Option<&Sw
     // Test with real device if available
     if let Some(device) = real_device {
         print!("🧪 Test: Real Device Status... ");
-        let controller =
-            SwitcherController::new(device.ip_address.clone(), device.device_id.clone());
+        let controller = SwitcherController::from_device(device);
 
         match timeout(Duration::from_secs(8), controller.get_status()).await {
             Ok(Ok(status)) => {
@@ -173,8 +171,7 @@ async fn test_control_commands(results: &mut TestResults, real_device: Option<&S
     println!("\n=== CONTROL TESTS ===");
 
     if let Some(device) = real_device {
-        let controller =
-            SwitcherController::new(device.ip_address.clone(), device.device_id.clone());
+        let controller = SwitcherController::from_device(device);
 
         // Get current state
         let original_state = match timeout(Duration::from_secs(8), controller.get_status()).await {
@@ -398,8 +395,7 @@ async fn test_device_renaming(results: &mut TestResults, test_device: Option<&Sw
     println!("\n=== DEVICE RENAMING TESTS ===");
 
     if let Some(device) = test_device {
-        let controller =
-            SwitcherController::new(device.ip_address.clone(), device.device_id.clone());
+        let controller = SwitcherController::from_device(device);
 
         // Get original name
         let original_name = device.name.clone();
@@ -439,8 +435,11 @@ async fn test_device_renaming(results: &mut TestResults, test_device: Option<&Sw
         print!("🧪 Test: Invalid Name Length (too short)... ");
         match timeout(Duration::from_secs(8), controller.set_device_name("A")).await {
             Ok(Ok(())) => results.fail("Should have failed with name too short"),
-            Ok(Err(_)) => results.pass(), // Expected to fail
-            Err(_) => results.pass(),     // Timeout is also acceptable
+            Ok(Err(e)) => match e {
+                SwitcherError::NameTooShort(1) => results.pass(),
+                _ => results.fail(&format!("Expected NameTooShort(1), got: {}", e)),
+            },
+            Err(_) => results.fail("Name validation should fail before connecting"),
         }
 
         print!("🧪 Test: Invalid Name Length (too long)... ");
@@ -452,8 +451,11 @@ async fn test_device_renaming(results: &mut TestResults, test_device: Option<&Sw
         .await
         {
             Ok(Ok(())) => results.fail("Should have failed with name too long"),
-            Ok(Err(_)) => results.pass(), // Expected to fail
-            Err(_) => results.pass(),     // Timeout is also acceptable
+            Ok(Err(e)) => match e {
+                SwitcherError::NameTooLong(35) => results.pass(),
+                _ => results.fail(&format!("Expected NameTooLong(35), got: {}", e)),
+            },
+            Err(_) => results.fail("Name validation should fail before connecting"),
         }
     } else {
         print!("🧪 Test: Device Renaming... ");